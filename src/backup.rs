@@ -0,0 +1,188 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, ErrorKind, Seek, SeekFrom, Write};
+use std::sync::Arc;
+use crate::cipher::PageCipher;
+use crate::compress::{self, Extent};
+use crate::{check, lock_now, sync_dir, ConnectionOptions, FileHeader, OpenError, PageMap, Pager, Table, TABLE_MAX_PAGES};
+
+/// Number of pages `Connection::backup_to` copies at each step.
+pub const BACKUP_STEP_PAGES: usize = 64;
+
+/// A copy of the database being written to another file a few pages at a time, from a snapshot
+/// taken when the backup started. Statements go on changing the database in the meantime, the
+/// copy holds none of their changes.
+///
+/// The copy has a header even when the database has none, the checksums of its pages are up
+/// to date, and its pages are compressed when compression is on and encrypted with the key of
+/// the database when it has one. It is written next to the file, which it replaces only once it
+/// is complete, and removed if the backup is dropped before that.
+pub struct Backup {
+    snapshot: Table,
+    /// Counted by the connection, which is not closed or restored while a backup holds it.
+    _in_progress: Arc<()>,
+    /// The file being replaced, locked against other processes until the copy is renamed to it.
+    _destination: Option<File>,
+    file_name: String,
+    temp_name: String,
+    file: File,
+    next_page: usize,
+    /// Where the pages copied so far were written, for the page map of a compressed or
+    /// encrypted copy.
+    extents: Vec<Extent>,
+    offset: u64,
+    complete: bool
+}
+
+impl Backup {
+
+    /// Lock the destination file if there is one, create or empty the file the copy is written
+    /// to, locked until the backup is dropped, and write its header. The header of a compressed
+    /// or encrypted copy is written again once its page map is known.
+    pub(crate) fn new(snapshot: Table, in_progress: Arc<()>, file_name: &str) -> io::Result<Self> {
+        let destination = match OpenOptions::new().write(true).open(file_name) {
+            Ok(destination) => {
+                lock_now(&destination, true)?;
+                Some(destination)
+            },
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(e) => return Err(e)
+        };
+        let temp_name = format!("{}.tmp", file_name);
+        let mut file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&temp_name)?;
+        lock_now(&file, true)?;
+        file.set_len(0)?;
+        let header = FileHeader::plain(snapshot.pager.page_size);
+        file.write_all(&header.to_bytes())?;
+        Ok(Backup {
+            snapshot,
+            _in_progress: in_progress,
+            _destination: destination,
+            file_name: String::from(file_name),
+            temp_name,
+            file,
+            next_page: 0,
+            extents: Vec::new(),
+            offset: header.header_size,
+            complete: false
+        })
+    }
+
+    /// Number of pages of the database, all of which are copied.
+    pub fn page_count(&self) -> usize {
+        self.snapshot.pager.num_pages
+    }
+
+    /// Number of pages left to copy.
+    pub fn remaining(&self) -> usize {
+        self.page_count() - self.next_page
+    }
+
+    /// Copy up to `pages` more pages, returns whether the backup is complete, in which case the
+    /// copy has been synced to disk and renamed over the file. A page whose checksum does not
+    /// match fails the backup.
+    pub fn step(&mut self, pages: usize) -> io::Result<bool> {
+        if self.complete {
+            return Ok(true);
+        }
+        let end = self.page_count().min(self.next_page + pages);
+        for page_num in self.next_page..end {
+            let page = self.snapshot.pager.get_page_view(page_num).unwrap();
+            if page.corrupt {
+                return Err(io::Error::new(ErrorKind::InvalidData,
+                                          format!("page {} is corrupt, its checksum does not match", page_num)));
+            }
+            let image = self.snapshot.pager.page_image(page_num, page);
+            self.file.write_all(&image)?;
+            self.extents.push(Extent { offset: self.offset, len: image.len() as u32 });
+            self.offset += image.len() as u64;
+        }
+        self.next_page = end;
+        if self.remaining() > 0 {
+            return Ok(false);
+        }
+        let mut header = self.snapshot.pager.write_format();
+        if header.compressed || header.encryption.is_some() {
+            let extents = std::mem::take(&mut self.extents);
+            self.file.write_all(&compress::page_map_bytes(&extents))?;
+            header.page_map = Some(PageMap { offset: self.offset, extents });
+            self.file.seek(SeekFrom::Start(0))?;
+            self.file.write_all(&header.to_bytes())?;
+        }
+        self.file.sync_all()?;
+        fs::rename(&self.temp_name, &self.file_name)?;
+        sync_dir(&self.file_name)?;
+        self.complete = true;
+        Ok(true)
+    }
+}
+
+impl Drop for Backup {
+    fn drop(&mut self) {
+        if !self.complete {
+            let _ = fs::remove_file(&self.temp_name);
+        }
+    }
+}
+
+/// Replace the content of the database with the one of a file written by a backup, which must
+/// be a whole number of pages, or compressed or encrypted pages and their page map, and pass
+/// the integrity check. An encrypted file must have been encrypted with the key of the
+/// database, or with `passphrase` when it has a salt of its own: only the key derived from the
+/// passphrase of the database is kept.
+/// The database is written again in a file next to it which is renamed over it, compressed as
+/// the backup is and encrypted if either has a key. Returns the number of pages restored.
+pub fn restore(table: &mut Table, file_name: &str, passphrase: Option<&str>) -> io::Result<usize> {
+    if table.pager.read_only {
+        return Err(io::Error::new(ErrorKind::PermissionDenied, "the database is open read-only"));
+    }
+    let file = File::open(file_name)?;
+    lock_now(&file, false)?;
+    let options = ConnectionOptions { read_only: true, ..ConnectionOptions::default() };
+    let mut pager = Pager::new(file, file_name, &options).map_err(|e| match e {
+        OpenError::Corrupt(message) => io::Error::new(ErrorKind::InvalidData, message),
+        OpenError::Io(e) => e,
+        OpenError::Locked => io::Error::new(ErrorKind::WouldBlock, "database is locked")
+    })?;
+    let num_pages = pager.num_pages;
+    if num_pages == 0 {
+        return Err(io::Error::new(ErrorKind::InvalidData, "the file has no pages"));
+    }
+    if num_pages > TABLE_MAX_PAGES {
+        return Err(io::Error::new(ErrorKind::InvalidData, format!("more than {} pages", TABLE_MAX_PAGES)));
+    }
+    if let Some(encryption) = &pager.encryption {
+        let cipher = match (&table.pager.cipher, passphrase) {
+            (None, _) => return Err(io::Error::new(ErrorKind::PermissionDenied, "the file is encrypted and the database has no key")),
+            (Some(cipher), _) if cipher.salt() == encryption.salt => Arc::clone(cipher),
+            (Some(_), Some(passphrase)) => Arc::new(PageCipher::with_salt(passphrase, encryption.salt)),
+            (Some(_), None) => return Err(io::Error::new(ErrorKind::PermissionDenied,
+                                                         "the file is encrypted with a salt of its own, its passphrase is needed"))
+        };
+        if !cipher.checks(&encryption.key_check) {
+            return Err(io::Error::new(ErrorKind::PermissionDenied, "the file is encrypted with another key"));
+        }
+        pager.file_cipher = Some(cipher);
+    }
+    pager.verify_checksums = table.pager.verify_checksums;
+    let source = Table::new(pager);
+    let errors = check::integrity_check(&source);
+    if let Some(error) = errors.first() {
+        return Err(io::Error::new(ErrorKind::InvalidData,
+                                  format!("the file fails the integrity check with {} errors, the first: {}", errors.len(), error)));
+    }
+
+    let (page_size, compressed) = (source.pager.page_size, source.pager.compressed);
+    table.pager.replace_file(page_size, compressed, num_pages, |page_num| {
+        let page = source.pager.get_page_view(page_num).unwrap();
+        if page.corrupt {
+            return Err(io::Error::new(ErrorKind::InvalidData,
+                                      format!("page {} is corrupt, its checksum does not match", page_num)));
+        }
+        Ok(page.clone())
+    })?;
+    Ok(num_pages)
+}
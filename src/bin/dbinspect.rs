@@ -0,0 +1,256 @@
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader};
+use std::process;
+use db_tutorial_rust::{NodeType, Page, PageFile};
+
+/// What to print about the file.
+enum Command {
+    /// The header and every page.
+    Pages,
+    /// The header and a single page.
+    Page(usize),
+    Hexdump(usize),
+    Dot
+}
+
+/// The keys of the page, with its children when it is an internal node. `None` when the node
+/// type or the count is invalid, in which case the rest of the page means nothing.
+fn node_contents(page: &Page) -> Option<(NodeType, Vec<u32>, Vec<usize>)> {
+    match page.checked_node_type()? {
+        NodeType::NODE_LEAF => {
+            let num_cells = page.leaf_node_num_cells();
+            if num_cells > page.leaf_node_max_cells() {
+                return None;
+            }
+            let keys = (0..num_cells).map(|i| page.leaf_node_key(i)).collect();
+            Some((NodeType::NODE_LEAF, keys, Vec::new()))
+        },
+        NodeType::NODE_INTERNAL => {
+            let num_keys = page.get_internal_node_num_keys();
+            if num_keys > page.internal_node_max_cells() {
+                return None;
+            }
+            let keys = (0..num_keys).map(|i| page.get_internal_node_key(i)).collect();
+            let children = (0..=num_keys).map(|i| page.get_internal_node_child(i)).collect();
+            Some((NodeType::NODE_INTERNAL, keys, children))
+        }
+    }
+}
+
+fn main() {
+
+    fn print_header(file_name: &str, file: &PageFile) {
+        println!("file: {}", file_name);
+        println!("file size: {} bytes", file.file_len());
+        let stored = [(file.is_compressed(), ", compressed"), (file.is_encrypted(), ", encrypted")]
+            .into_iter().filter(|(is, _)| *is).map(|(_, name)| name).collect::<String>();
+        if file.has_header() {
+            println!("header: page size {}{}", file.page_size(), stored);
+        } else {
+            println!("header: none, pages of {} bytes from the start of the file", file.page_size());
+        }
+        println!("page size: {}", file.page_size());
+        println!("pages: {}", file.num_pages());
+        println!("root page: {}", file.root_page_num());
+        if let Some(page) = file.page(file.root_page_num()) {
+            println!("leaf node max cells: {}", page.leaf_node_max_cells());
+            println!("internal node max cells: {}", page.internal_node_max_cells());
+        }
+        let trailing = file.trailing_bytes();
+        if trailing != 0 {
+            if file.is_compressed() || file.is_encrypted() {
+                println!("warning: {} bytes after the page map", trailing);
+            } else {
+                println!("warning: the file is not a whole number of pages, {} bytes after the last page", trailing);
+            }
+        }
+    }
+
+    fn print_page(page_num: usize, page: &Page) {
+        let is_root = match page.checked_is_root() {
+            Some(is_root) => is_root.to_string(),
+            None => format!("invalid ({})", page.bytes()[1])
+        };
+        println!();
+        println!("page {}", page_num);
+        match page.checked_node_type() {
+            Some(NodeType::NODE_LEAF) => println!("  node type: leaf"),
+            Some(NodeType::NODE_INTERNAL) => println!("  node type: internal"),
+            None => println!("  node type: invalid ({})", page.bytes()[0])
+        }
+        println!("  is root: {}", is_root);
+        println!("  parent: {}", page.get_node_parent());
+        if page.has_valid_checksum() {
+            println!("  checksum: {:08x}", page.stored_checksum());
+        } else {
+            println!("  checksum: {:08x}, mismatch, computed {:08x}", page.stored_checksum(), page.compute_checksum());
+        }
+        match page.checked_node_type() {
+            Some(NodeType::NODE_LEAF) => {
+                println!("  cells: {}", page.leaf_node_num_cells());
+                println!("  next leaf: {}", page.get_leaf_node_next_leaf());
+            },
+            Some(NodeType::NODE_INTERNAL) => {
+                println!("  keys: {}", page.get_internal_node_num_keys());
+                println!("  right child: {}", page.get_internal_node_right_child());
+            },
+            None => return
+        }
+        match node_contents(page) {
+            Some((NodeType::NODE_LEAF, keys, _)) => {
+                for (i, key) in keys.iter().enumerate() {
+                    println!("  cell {}: key {}", i, key);
+                }
+            },
+            Some((NodeType::NODE_INTERNAL, keys, children)) => {
+                for (i, key) in keys.iter().enumerate() {
+                    println!("  child {}: page {}, key {}", i, children[i], key);
+                }
+                println!("  child {}: page {}", keys.len(), children[keys.len()]);
+            },
+            None => println!("  count is past the maximum, cells not shown")
+        }
+    }
+
+    /// Sixteen bytes per line in hex and ASCII, offsets from the start of the page, runs of
+    /// identical lines shown once followed by `*`.
+    fn print_hexdump(page: &Page) {
+        let mut previous: Option<&[u8]> = None;
+        let mut repeating = false;
+        for (i, line) in page.bytes().chunks(16).enumerate() {
+            if previous == Some(line) {
+                if !repeating {
+                    println!("*");
+                    repeating = true;
+                }
+                continue;
+            }
+            previous = Some(line);
+            repeating = false;
+            let hex: Vec<String> = line.iter().map(|b| format!("{:02x}", b)).collect();
+            let ascii: String = line.iter()
+                .map(|&b| if b.is_ascii_graphic() || b == b' ' { b as char } else { '.' })
+                .collect();
+            println!("{:08x}  {}  {}  |{}|", i * 16, hex[..8].join(" "), hex[8..].join(" "), ascii);
+        }
+        println!("{:08x}", page.bytes().len());
+    }
+
+    /// A Graphviz graph of every page: internal nodes point to their children from the port
+    /// beside each key, leaves to their next leaf with a dashed edge.
+    fn print_dot(file: &PageFile) {
+        println!("digraph btree {{");
+        println!("  node [shape=record];");
+        for page_num in 0..file.num_pages() {
+            let page = file.page(page_num).unwrap();
+            match node_contents(page) {
+                Some((NodeType::NODE_LEAF, keys, _)) => {
+                    let keys: Vec<String> = keys.iter().map(u32::to_string).collect();
+                    println!("  page{} [label=\"{{page {}|{}}}\"];", page_num, page_num, keys.join(" "));
+                    let next_leaf = page.get_leaf_node_next_leaf();
+                    if next_leaf != 0 {
+                        println!("  page{} -> page{} [style=dashed, constraint=false];", page_num, next_leaf);
+                    }
+                },
+                Some((NodeType::NODE_INTERNAL, keys, children)) => {
+                    let mut fields = vec![String::from("<c0>")];
+                    for (i, key) in keys.iter().enumerate() {
+                        fields.push(key.to_string());
+                        fields.push(format!("<c{}>", i + 1));
+                    }
+                    println!("  page{} [label=\"{{page {}|{{{}}}}}\"];", page_num, page_num, fields.join("|"));
+                    for (i, child) in children.iter().enumerate() {
+                        println!("  page{}:c{} -> page{};", page_num, i, child);
+                    }
+                },
+                None => println!("  page{} [label=\"page {}|invalid\", color=red];", page_num, page_num)
+            }
+        }
+        println!("}}");
+    }
+
+    fn usage() -> ! {
+        println!("Usage: dbinspect [-k KEY_FILE] [-p PAGE | -x PAGE | -d] FILE");
+        println!("  (none)   print the header and every page");
+        println!("  -p PAGE  print the header and one page");
+        println!("  -x PAGE  hexdump one page");
+        println!("  -d       print the tree as a Graphviz dot graph");
+        println!("  -k KEY_FILE  read the key of an encrypted file from the first line of KEY_FILE, - for stdin");
+        process::exit(1);
+    }
+
+    fn page_arg(arg: Option<String>) -> usize {
+        arg.and_then(|a| a.parse().ok()).unwrap_or_else(|| usage())
+    }
+
+    /// The passphrase on the first line of the file, or of stdin for `-`, rather than on the
+    /// command line where other users can see it.
+    fn read_key(key_file: &str) -> io::Result<String> {
+        let mut line = String::new();
+        if key_file == "-" {
+            io::stdin().lock().read_line(&mut line)?;
+        } else {
+            BufReader::new(File::open(key_file)?).read_line(&mut line)?;
+        }
+        Ok(line.trim_end_matches(['\r', '\n']).to_string())
+    }
+
+    let mut file_name = None;
+    let mut key = None;
+    let mut command = Command::Pages;
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-p" => command = Command::Page(page_arg(args.next())),
+            "-x" => command = Command::Hexdump(page_arg(args.next())),
+            "-d" => command = Command::Dot,
+            "-k" => {
+                let key_file = args.next().unwrap_or_else(|| usage());
+                key = Some(read_key(&key_file).unwrap_or_else(|e| {
+                    eprintln!("Error: cannot read the key from {}: {}", key_file, e);
+                    process::exit(1);
+                }));
+            },
+            _ if file_name.is_none() => file_name = Some(arg),
+            _ => usage()
+        }
+    }
+    let file_name = file_name.unwrap_or_else(|| usage());
+
+    let mut file = PageFile::open(&file_name).unwrap_or_else(|e| {
+        eprintln!("Error: cannot open {}: {}", file_name, e);
+        process::exit(1);
+    });
+    if file.is_encrypted() {
+        match key {
+            Some(key) if file.set_key(&key) => {},
+            Some(_) => {
+                eprintln!("Error: wrong key for {}", file_name);
+                process::exit(1);
+            },
+            None => {
+                eprintln!("Error: {} is encrypted, give the file holding its key with -k", file_name);
+                process::exit(1);
+            }
+        }
+    }
+    let page = |page_num: usize| file.page(page_num).unwrap_or_else(|| {
+        eprintln!("Error: page {} is past the end of the file of {} pages", page_num, file.num_pages());
+        process::exit(1);
+    });
+    match command {
+        Command::Pages => {
+            print_header(&file_name, &file);
+            for page_num in 0..file.num_pages() {
+                print_page(page_num, page(page_num));
+            }
+        },
+        Command::Page(page_num) => {
+            print_header(&file_name, &file);
+            print_page(page_num, page(page_num));
+        },
+        Command::Hexdump(page_num) => print_hexdump(page(page_num)),
+        Command::Dot => print_dot(&file)
+    }
+}
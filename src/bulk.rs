@@ -0,0 +1,138 @@
+use crate::value::Value;
+use crate::{serialize_row, ExecuteResult, Row, Table, TABLE_MAX_PAGES};
+
+/// Fill factor used when none is given, leaving some room in every node for later inserts.
+pub const DEFAULT_FILL_FACTOR: f64 = 0.9;
+
+/// Build the tree of an empty table bottom-up from rows sorted by key.
+///
+/// Leaves are filled one after the other up to `fill_factor` of their capacity and chained
+/// through their next leaf pointers, then every level of internal nodes is built above the
+/// previous one, the root being written last into the root page. Each page is written once,
+/// with no search of the tree and no split.
+///
+/// Rows must come in increasing key order, an out of order row fails with `EXECUTE_UNSORTED`
+/// and a repeated key with `EXECUTE_DUPLICATE_KEY`. The caller undoes the pages written so far
+/// when loading fails.
+pub fn bulk_load(table: &mut Table, rows: impl IntoIterator<Item = [Value; 3]>, fill_factor: f64) -> ExecuteResult {
+    let root_page_num = table.root_page_num;
+    {
+        let root = table.pager.get_page_view(root_page_num).unwrap();
+        if !root.is_leaf_node() || root.leaf_node_num_cells() > 0 {
+            return ExecuteResult::EXECUTE_TABLE_NOT_EMPTY;
+        }
+    }
+    let fill_factor = fill_factor.clamp(0.0, 1.0);
+    let max_cells = table.pager.leaf_node_max_cells();
+    let max_children = table.pager.internal_node_max_cells() + 1;
+    let cells_per_leaf = ((max_cells as f64 * fill_factor).round() as usize).clamp(1, max_cells);
+
+    // every leaf written so far with its largest key
+    let mut leaves: Vec<(usize, u32)> = Vec::new();
+    let mut cells: Vec<Row> = Vec::with_capacity(cells_per_leaf);
+    for values in rows {
+        let row = match Row::from_values(&values) {
+            Ok(row) => row,
+            Err(result) => return result
+        };
+        if let Some(last) = cells.last().map(|r| r.id).or(leaves.last().map(|&(_, key)| key)) {
+            if row.id == last {
+                return ExecuteResult::EXECUTE_DUPLICATE_KEY;
+            } else if row.id < last {
+                return ExecuteResult::EXECUTE_UNSORTED;
+            }
+        }
+        if cells.len() == cells_per_leaf {
+            if let Err(result) = write_leaf(table, &mut leaves, &cells) {
+                return result;
+            }
+            cells.clear();
+        }
+        cells.push(row);
+    }
+
+    if leaves.is_empty() {
+        // everything fits in the root
+        fill_leaf(table, root_page_num, &cells);
+        return ExecuteResult::EXECUTE_SUCCESS;
+    }
+    if !cells.is_empty() {
+        if let Err(result) = write_leaf(table, &mut leaves, &cells) {
+            return result;
+        }
+    }
+
+    let children_per_node = ((max_children as f64 * fill_factor).round() as usize).clamp(2, max_children);
+    let mut level = leaves;
+    while level.len() > max_children {
+        let mut parents = Vec::new();
+        let mut rest = level.as_slice();
+        for size in group_sizes(level.len(), children_per_node, max_children) {
+            let (children, next) = rest.split_at(size);
+            rest = next;
+            let page_num = match allocate_page(table) {
+                Ok(page_num) => page_num,
+                Err(result) => return result
+            };
+            table.write_internal_node(page_num, children);
+            table.set_children_parent(children, page_num);
+            parents.push((page_num, children[children.len() - 1].1));
+        }
+        level = parents;
+    }
+    table.write_internal_node(root_page_num, &level);
+    table.set_children_parent(&level, root_page_num);
+    ExecuteResult::EXECUTE_SUCCESS
+}
+
+/// Write the cells into a new leaf chained after the previous one.
+fn write_leaf(table: &mut Table, leaves: &mut Vec<(usize, u32)>, cells: &[Row]) -> Result<(), ExecuteResult> {
+    let page_num = allocate_page(table)?;
+    fill_leaf(table, page_num, cells);
+    if let Some(&(previous, _)) = leaves.last() {
+        table.pager.get_page(previous).set_leaf_node_next_leaf(page_num);
+    }
+    leaves.push((page_num, cells[cells.len() - 1].id));
+    Ok(())
+}
+
+fn fill_leaf(table: &mut Table, page_num: usize, cells: &[Row]) {
+    let page = table.pager.get_page(page_num);
+    if !page.is_node_root() {
+        page.initialize_leaf_node();
+    }
+    page.set_leaf_node_num_cells(cells.len());
+    for (i, row) in cells.iter().enumerate() {
+        page.set_leaf_node_key(i, row.id);
+        unsafe { serialize_row(page.leaf_node_value(i), row) };
+    }
+}
+
+fn allocate_page(table: &mut Table) -> Result<usize, ExecuteResult> {
+    let page_num = table.pager.get_unused_page_num();
+    if page_num >= TABLE_MAX_PAGES {
+        return Err(ExecuteResult::EXECUTE_TABLE_FULL);
+    }
+    Ok(page_num)
+}
+
+/// Split `count` children into groups of `size`. A last group of a single child, which would
+/// make an internal node without keys, takes one child from the group before it, or is merged
+/// into it when that group has room for up to `max_size` children.
+fn group_sizes(count: usize, size: usize, max_size: usize) -> Vec<usize> {
+    let mut sizes = vec![size; count / size];
+    if !count.is_multiple_of(size) {
+        sizes.push(count % size);
+    }
+    let n = sizes.len();
+    if n > 1 && sizes[n - 1] == 1 {
+        if sizes[n - 2] < max_size {
+            sizes.pop();
+            sizes[n - 2] += 1;
+        } else {
+            sizes[n - 2] -= 1;
+            sizes[n - 1] += 1;
+        }
+    }
+    sizes
+}
@@ -0,0 +1,168 @@
+use crate::{NodeType, Table};
+
+/// Walks the tree of a table from its root and collects every broken invariant.
+struct Checker<'a> {
+    table: &'a Table,
+    num_pages: usize,
+    /// The page referencing each page reached so far.
+    referenced_by: Vec<Option<usize>>,
+    /// Leaves in key order, as reached from the root.
+    leaves: Vec<usize>,
+    /// Depth of the first leaf reached, which every leaf must share.
+    leaf_depth: Option<usize>,
+    /// Whether a page failed its checksum, hiding the subtree under it.
+    corrupt: bool,
+    errors: Vec<String>
+}
+
+impl Checker<'_> {
+
+    fn error(&mut self, page_num: usize, message: String) {
+        self.errors.push(format!("page {}: {}", page_num, message));
+    }
+
+    /// Check the node and the subtree under it, whose keys must be in `(low, high]`.
+    fn check_node(&mut self, page_num: usize, parent: Option<usize>, low: Option<u32>, high: Option<u32>, depth: usize) {
+        let referrer = parent.unwrap_or(page_num);
+        if page_num >= self.num_pages {
+            self.error(referrer, format!("child page {} is past the end of the file of {} pages", page_num, self.num_pages));
+            return;
+        }
+        if let Some(other) = self.referenced_by[page_num] {
+            self.error(page_num, format!("referenced by page {} and by page {}", other, referrer));
+            return;
+        }
+        self.referenced_by[page_num] = Some(referrer);
+
+        let page = self.table.pager.get_page_view(page_num).unwrap();
+        if self.table.pager.is_corrupt(page_num) {
+            self.error(page_num, String::from("checksum mismatch"));
+            self.corrupt = true;
+            return;
+        }
+        match (page.checked_is_root(), parent) {
+            (None, _) => self.error(page_num, String::from("root flag is neither true nor false")),
+            (Some(false), None) => self.error(page_num, String::from("root page is not flagged as root")),
+            (Some(true), Some(_)) => self.error(page_num, String::from("child page is flagged as root")),
+            _ => {}
+        }
+        if let Some(parent) = parent {
+            let parent_pointer = page.get_node_parent();
+            if parent_pointer != parent {
+                self.error(page_num, format!("parent pointer is {} but the parent is page {}", parent_pointer, parent));
+            }
+        }
+
+        let node_type = match page.checked_node_type() {
+            Some(node_type) => node_type,
+            None => {
+                self.error(page_num, String::from("invalid node type"));
+                return;
+            }
+        };
+        let keys: Vec<u32> = match node_type {
+            NodeType::NODE_LEAF => {
+                let num_cells = page.leaf_node_num_cells();
+                let max_cells = page.leaf_node_max_cells();
+                if num_cells > max_cells {
+                    self.error(page_num, format!("{} cells, more than the maximum of {}", num_cells, max_cells));
+                    return;
+                }
+                (0..num_cells).map(|i| page.leaf_node_key(i)).collect()
+            },
+            NodeType::NODE_INTERNAL => {
+                let num_keys = page.get_internal_node_num_keys();
+                let max_keys = page.internal_node_max_cells();
+                if num_keys == 0 || num_keys > max_keys {
+                    self.error(page_num, format!("{} keys, not between 1 and {}", num_keys, max_keys));
+                    return;
+                }
+                (0..num_keys).map(|i| page.get_internal_node_key(i)).collect()
+            }
+        };
+        for pair in keys.windows(2) {
+            if pair[0] >= pair[1] {
+                self.error(page_num, format!("key {} is not less than the key {} after it", pair[0], pair[1]));
+            }
+        }
+        for &key in &keys {
+            if low.is_some_and(|low| key <= low) || high.is_some_and(|high| key > high) {
+                self.error(page_num, format!("key {} is outside the range {} of its parent", key, range(low, high)));
+            }
+        }
+
+        if node_type == NodeType::NODE_LEAF {
+            match self.leaf_depth {
+                Some(leaf_depth) if leaf_depth != depth =>
+                    self.error(page_num, format!("leaf at depth {} but the first leaf is at depth {}", depth, leaf_depth)),
+                Some(_) => {},
+                None => self.leaf_depth = Some(depth)
+            }
+            self.leaves.push(page_num);
+            return;
+        }
+        let children: Vec<usize> = (0..=keys.len()).map(|i| page.get_internal_node_child(i)).collect();
+        let mut child_low = low;
+        for (i, child) in children.into_iter().enumerate() {
+            let child_high = keys.get(i).copied().or(high);
+            self.check_node(child, Some(page_num), child_low, child_high, depth + 1);
+            child_low = child_high;
+        }
+    }
+
+    /// Follow the `next_leaf` pointers from the first leaf, which must visit the leaves in the
+    /// order of the tree and end with 0.
+    fn check_leaf_chain(&mut self) {
+        if self.corrupt {
+            // the leaves under a corrupt page are missing from the list
+            return;
+        }
+        for i in 0..self.leaves.len() {
+            let page_num = self.leaves[i];
+            let expected = self.leaves.get(i + 1).copied().unwrap_or(0);
+            let next_leaf = self.table.pager.get_page_view(page_num).unwrap().get_leaf_node_next_leaf();
+            if next_leaf != expected {
+                self.error(page_num, format!("next leaf is {} but the next leaf of the tree is {}", next_leaf, expected));
+            }
+        }
+    }
+
+    fn check_orphans(&mut self) {
+        if self.corrupt {
+            return;
+        }
+        for page_num in 0..self.num_pages {
+            if self.referenced_by[page_num].is_none() {
+                self.error(page_num, String::from("not referenced by any page"));
+            }
+        }
+    }
+}
+
+fn range(low: Option<u32>, high: Option<u32>) -> String {
+    let low = low.map_or(String::from("-inf"), |k| k.to_string());
+    let high = high.map_or(String::from("+inf"), |k| k.to_string());
+    format!("({}, {}]", low, high)
+}
+
+/// Check the invariants of the B-tree of the table: keys sorted in every node and within the
+/// bounds set by the keys of its parent, parent pointers and root flags matching the tree, cell
+/// counts within their limits, leaves at the same depth and chained in key order, and every
+/// page of the file referenced exactly once. Returns one message per violation, naming the
+/// page, none if the tree is sound.
+pub fn integrity_check(table: &Table) -> Vec<String> {
+    let num_pages = table.pager.num_pages.max(1);
+    let mut checker = Checker {
+        table,
+        num_pages,
+        referenced_by: vec![None; num_pages],
+        leaves: Vec::new(),
+        leaf_depth: None,
+        corrupt: false,
+        errors: Vec::new()
+    };
+    checker.check_node(table.root_page_num, None, None, None, 1);
+    checker.check_leaf_chain();
+    checker.check_orphans();
+    checker.errors
+}
@@ -0,0 +1,85 @@
+use chacha20poly1305::aead::rand_core::RngCore;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use sha2::Sha256;
+use crate::FILE_MAGIC;
+
+pub const SALT_SIZE: usize = 16;
+const NONCE_SIZE: usize = 12;
+const TAG_SIZE: usize = 16;
+
+/// Bytes of the key check in the header: a nonce, the magic bytes encrypted and their tag.
+pub const KEY_CHECK_SIZE: usize = NONCE_SIZE + FILE_MAGIC.len() + TAG_SIZE;
+
+/// Rounds of PBKDF2-HMAC-SHA256 deriving the key from the passphrase, as many as SQLCipher.
+const KDF_ITERATIONS: u32 = 256_000;
+
+/// What the key check is authenticated with, a number no page has.
+const KEY_CHECK_AD: u64 = u64::MAX;
+
+/// The key of an encrypted database, derived from its passphrase and the salt stored in its
+/// header. Every page is encrypted with ChaCha20-Poly1305 under a nonce of its own, and
+/// authenticated with its page number so that pages can not be swapped.
+pub struct PageCipher {
+    salt: [u8; SALT_SIZE],
+    aead: ChaCha20Poly1305
+}
+
+impl PageCipher {
+
+    /// A new key for the passphrase, with a new salt.
+    pub fn new(passphrase: &str) -> PageCipher {
+        let mut salt = [0; SALT_SIZE];
+        OsRng.fill_bytes(&mut salt);
+        PageCipher::with_salt(passphrase, salt)
+    }
+
+    /// The key the passphrase gives with the salt of a file.
+    pub fn with_salt(passphrase: &str, salt: [u8; SALT_SIZE]) -> PageCipher {
+        let mut key = Key::default();
+        pbkdf2::pbkdf2_hmac::<Sha256>(passphrase.as_bytes(), &salt, KDF_ITERATIONS, &mut key);
+        PageCipher { salt, aead: ChaCha20Poly1305::new(&key) }
+    }
+
+    pub fn salt(&self) -> [u8; SALT_SIZE] {
+        self.salt
+    }
+
+    /// The stored page, its nonce followed by the page encrypted and its tag.
+    pub fn encrypt(&self, page_num: usize, page: &[u8]) -> Vec<u8> {
+        self.seal(page_num as u64, page)
+    }
+
+    /// The page decrypted from its stored image, `None` if the image was not encrypted with this
+    /// key as that page.
+    pub fn decrypt(&self, page_num: usize, image: &[u8]) -> Option<Vec<u8>> {
+        self.open(page_num as u64, image)
+    }
+
+    /// The magic bytes encrypted, stored in the header to tell a wrong key from a corrupt page.
+    pub fn key_check(&self) -> [u8; KEY_CHECK_SIZE] {
+        self.seal(KEY_CHECK_AD, FILE_MAGIC).try_into().unwrap()
+    }
+
+    /// Whether this is the key the key check was made with.
+    pub fn checks(&self, key_check: &[u8]) -> bool {
+        self.open(KEY_CHECK_AD, key_check).is_some_and(|magic| magic == FILE_MAGIC)
+    }
+
+    fn seal(&self, ad: u64, plaintext: &[u8]) -> Vec<u8> {
+        let nonce = ChaCha20Poly1305::generate_nonce(&mut OsRng);
+        let ciphertext = self.aead.encrypt(&nonce, Payload { msg: plaintext, aad: &ad.to_le_bytes() })
+            .expect("a page is never too long to encrypt");
+        let mut image = nonce.to_vec();
+        image.extend(ciphertext);
+        image
+    }
+
+    fn open(&self, ad: u64, image: &[u8]) -> Option<Vec<u8>> {
+        if image.len() < NONCE_SIZE + TAG_SIZE {
+            return None;
+        }
+        let (nonce, ciphertext) = image.split_at(NONCE_SIZE);
+        self.aead.decrypt(Nonce::from_slice(nonce), Payload { msg: ciphertext, aad: &ad.to_le_bytes() }).ok()
+    }
+}
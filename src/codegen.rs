@@ -0,0 +1,596 @@
+use crate::expr;
+use crate::schema::TableSchema;
+use crate::sql::{BinaryOp, Delete, Expr, Insert, InsertSource, OnConflict, Pragma, ResultColumn, Select};
+use crate::value::Value;
+use crate::vm::{Addr, Conflict, Opcode, Program, Reg};
+use crate::{is_valid_page_size, PrepareResult};
+
+/// The cursor over the table, statements only ever access one table.
+const TABLE_CURSOR: usize = 0;
+/// The cursor over the rows `insert ... select` collects before inserting them.
+const EPHEMERAL_CURSOR: usize = 1;
+
+/// Bounds on the primary key found in the `where` clause, used to seek instead of scanning the
+/// whole table. The clause is still evaluated for every row, so these only narrow the scan.
+#[derive(Default)]
+struct KeyRange<'e> {
+    eq: Option<&'e Expr>,
+    lower: Option<(BinaryOp, &'e Expr)>,
+    upper: Option<(BinaryOp, &'e Expr)>
+}
+
+impl<'e> KeyRange<'e> {
+
+    fn from_where(where_clause: Option<&'e Expr>, schema: &TableSchema) -> Self {
+        let mut range = KeyRange::default();
+        let mut conjuncts = Vec::new();
+        if let Some(expr) = where_clause {
+            split_conjuncts(expr, &mut conjuncts);
+        }
+        for conjunct in conjuncts {
+            let (op, left, right) = match conjunct {
+                Expr::Binary(op, left, right) if op.is_comparison() => (*op, left.as_ref(), right.as_ref()),
+                _ => continue
+            };
+            // normalize to `key <op> constant`
+            let (op, constant) = if is_primary_key(left, schema) && right.is_constant() {
+                (op, right)
+            } else if is_primary_key(right, schema) && left.is_constant() {
+                (match op {
+                    BinaryOp::Lt => BinaryOp::Gt,
+                    BinaryOp::LtEq => BinaryOp::GtEq,
+                    BinaryOp::Gt => BinaryOp::Lt,
+                    BinaryOp::GtEq => BinaryOp::LtEq,
+                    op => op
+                }, left)
+            } else {
+                continue;
+            };
+            match op {
+                BinaryOp::Eq if range.eq.is_none() => range.eq = Some(constant),
+                BinaryOp::Gt | BinaryOp::GtEq if range.lower.is_none() => range.lower = Some((op, constant)),
+                BinaryOp::Lt | BinaryOp::LtEq if range.upper.is_none() => range.upper = Some((op, constant)),
+                _ => {}
+            }
+        }
+        range
+    }
+}
+
+impl KeyRange<'_> {
+
+    /// The access path as listed by `explain query plan`.
+    fn describe(&self, schema: &TableSchema) -> String {
+        let key = schema.columns[0].name;
+        let mut bounds = Vec::new();
+        if self.eq.is_some() {
+            bounds.push(format!("{}=?", key));
+        } else {
+            if let Some((op, _)) = self.lower {
+                bounds.push(format!("{}{}?", key, op.symbol()));
+            }
+            if let Some((op, _)) = self.upper {
+                bounds.push(format!("{}{}?", key, op.symbol()));
+            }
+        }
+        if bounds.is_empty() {
+            format!("SCAN {}", schema.name)
+        } else {
+            format!("SEARCH {} USING PRIMARY KEY ({})", schema.name, bounds.join(" AND "))
+        }
+    }
+}
+
+fn split_conjuncts<'e>(expr: &'e Expr, conjuncts: &mut Vec<&'e Expr>) {
+    match expr {
+        Expr::Binary(BinaryOp::And, left, right) => {
+            split_conjuncts(left, conjuncts);
+            split_conjuncts(right, conjuncts);
+        },
+        expr => conjuncts.push(expr)
+    }
+}
+
+fn is_primary_key(expr: &Expr, schema: &TableSchema) -> bool {
+    matches!(expr, Expr::Column(name) if schema.column_index(name) == Some(0))
+}
+
+fn find_table(name: &str) -> Result<&'static TableSchema, PrepareResult> {
+    TableSchema::find(name).ok_or_else(|| PrepareResult::PREPARE_UNKNOWN_TABLE(String::from(name)))
+}
+
+/// Translates statements into programs of the virtual machine.
+struct CodeGenerator {
+    schema: &'static TableSchema,
+    instructions: Vec<Opcode>,
+    num_registers: usize,
+    plan: Vec<String>,
+    /// First register of the row being inserted, which `excluded.name` refers to.
+    excluded: Option<Reg>
+}
+
+impl CodeGenerator {
+
+    fn new(schema: &'static TableSchema) -> Self {
+        CodeGenerator {
+            schema,
+            instructions: Vec::new(),
+            num_registers: 0,
+            plan: Vec::new(),
+            excluded: None
+        }
+    }
+
+    fn emit(&mut self, opcode: Opcode) -> Addr {
+        self.instructions.push(opcode);
+        self.instructions.len() - 1
+    }
+
+    /// Address of the next instruction to be emitted.
+    fn current_addr(&self) -> Addr {
+        self.instructions.len()
+    }
+
+    /// Point the jump of an already emitted instruction at `target`.
+    fn patch_jump(&mut self, addr: Addr, target: Addr) {
+        match &mut self.instructions[addr] {
+            Opcode::Rewind { if_empty: t, .. }
+            | Opcode::Next { target: t, .. }
+            | Opcode::SeekRowid { not_found: t, .. }
+            | Opcode::SeekGE { if_eof: t, .. }
+            | Opcode::SeekGT { if_eof: t, .. }
+            | Opcode::Eq { target: t, .. }
+            | Opcode::Ne { target: t, .. }
+            | Opcode::Lt { target: t, .. }
+            | Opcode::Le { target: t, .. }
+            | Opcode::Gt { target: t, .. }
+            | Opcode::Ge { target: t, .. }
+            | Opcode::IfNot { target: t, .. }
+            | Opcode::Goto { target: t }
+            | Opcode::Insert { on_conflict: Conflict::Update(t), .. } => *t = target,
+            opcode => panic!("{:?} does not jump", opcode)
+        }
+    }
+
+    fn alloc_registers(&mut self, count: usize) -> Reg {
+        let start = self.num_registers;
+        self.num_registers += count;
+        start
+    }
+
+    fn finish(mut self, column_names: Vec<String>) -> Program {
+        self.emit(Opcode::Halt);
+        Program {
+            instructions: self.instructions,
+            num_registers: self.num_registers,
+            column_names,
+            plan: self.plan
+        }
+    }
+
+    /// Emit the code computing the expression into `dest`.
+    fn compile_expr(&mut self, expr: &Expr, dest: Reg) {
+        match expr {
+            Expr::Literal(value) => {
+                self.emit(match value {
+                    Value::Null => Opcode::Null { dest },
+                    Value::Integer(i) => Opcode::Integer { value: *i, dest },
+                    Value::Text(s) => Opcode::String { value: s.clone(), dest }
+                });
+            },
+            Expr::Excluded(name) => {
+                let column = self.schema.column_index(name).unwrap();
+                self.emit(Opcode::Copy { source: self.excluded.unwrap() + column, dest });
+            },
+            Expr::Parameter { index, name } => {
+                self.emit(Opcode::Variable { index: *index, name: name.clone(), dest });
+            },
+            Expr::Column(name) => {
+                let column = self.schema.column_index(name).unwrap();
+                if column == 0 {
+                    self.emit(Opcode::Rowid { cursor: TABLE_CURSOR, dest });
+                } else {
+                    self.emit(Opcode::Column { cursor: TABLE_CURSOR, column, dest });
+                }
+            },
+            Expr::Unary(op, operand) => {
+                let reg = self.alloc_registers(1);
+                self.compile_expr(operand, reg);
+                self.emit(Opcode::Unary { op: *op, operand: reg, dest });
+            },
+            Expr::Binary(op, left, right) => {
+                let regs = self.alloc_registers(2);
+                self.compile_expr(left, regs);
+                self.compile_expr(right, regs + 1);
+                self.emit(Opcode::Binary { op: *op, left: regs, right: regs + 1, dest });
+            },
+            Expr::Function(name, args) => {
+                let reg = self.alloc_registers(1);
+                self.compile_expr(&args[0], reg);
+                self.emit(Opcode::Function { name: name.clone(), arg: reg, dest });
+            }
+        }
+    }
+
+    /// Emit code that jumps to the returned placeholder jumps unless the condition is true. The
+    /// jumps must be patched once their target is known.
+    fn compile_condition(&mut self, expr: &Expr, jumps: &mut Vec<Addr>) {
+        match expr {
+            Expr::Binary(BinaryOp::And, left, right) => {
+                self.compile_condition(left, jumps);
+                self.compile_condition(right, jumps);
+            },
+            Expr::Binary(op, left, right) if op.is_comparison() => {
+                let regs = self.alloc_registers(2);
+                self.compile_expr(left, regs);
+                self.compile_expr(right, regs + 1);
+                // jump on the negated comparison, and when it is unknown
+                let (left, right, target, jump_if_null) = (regs, regs + 1, 0, true);
+                jumps.push(self.emit(match op {
+                    BinaryOp::Eq => Opcode::Ne { left, right, target, jump_if_null },
+                    BinaryOp::NotEq => Opcode::Eq { left, right, target, jump_if_null },
+                    BinaryOp::Lt => Opcode::Ge { left, right, target, jump_if_null },
+                    BinaryOp::LtEq => Opcode::Gt { left, right, target, jump_if_null },
+                    BinaryOp::Gt => Opcode::Le { left, right, target, jump_if_null },
+                    _ => Opcode::Lt { left, right, target, jump_if_null }
+                }));
+            },
+            expr => {
+                let reg = self.alloc_registers(1);
+                self.compile_expr(expr, reg);
+                jumps.push(self.emit(Opcode::IfNot { reg, target: 0 }));
+            }
+        }
+    }
+
+    /// Emit the insert of the row held in the registers starting at `start`. For
+    /// `on conflict do update`, the update of the existing row follows, computing the new row in
+    /// the registers starting at `update`.
+    fn compile_insert_row(&mut self, start: Reg, on_conflict: &OnConflict, update: Reg) {
+        let cursor = TABLE_CURSOR;
+        let (assignments, where_clause) = match on_conflict {
+            OnConflict::Abort => {
+                self.emit(Opcode::Insert { cursor, start, on_conflict: Conflict::Abort });
+                return;
+            },
+            OnConflict::Ignore => {
+                self.emit(Opcode::Insert { cursor, start, on_conflict: Conflict::Ignore });
+                return;
+            },
+            OnConflict::Replace => {
+                self.emit(Opcode::Insert { cursor, start, on_conflict: Conflict::Replace });
+                return;
+            },
+            OnConflict::Update { assignments, where_clause } => (assignments, where_clause)
+        };
+        let insert = self.emit(Opcode::Insert { cursor, start, on_conflict: Conflict::Update(0) });
+        let mut skips = vec![self.emit(Opcode::Goto { target: 0 })];
+        let addr = self.current_addr();
+        self.patch_jump(insert, addr);
+        // the cursor points at the existing row, whose columns keep their values unless assigned
+        self.excluded = Some(start);
+        if let Some(expr) = where_clause {
+            self.compile_condition(expr, &mut skips);
+        }
+        for (i, column) in self.schema.columns.iter().enumerate() {
+            let expr = assignments.iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case(column.name))
+                .map_or_else(|| Expr::Column(String::from(column.name)), |(_, expr)| expr.clone());
+            self.compile_expr(&expr, update + i);
+        }
+        self.emit(Opcode::Update { cursor, start: update });
+        self.excluded = None;
+        let end = self.current_addr();
+        for skip in skips {
+            self.patch_jump(skip, end);
+        }
+    }
+
+    /// Emit the loop over the rows matching the `where` clause, with `body` emitting the code
+    /// run for every row.
+    fn compile_loop(&mut self, where_clause: Option<&Expr>, body: impl FnOnce(&mut Self)) -> Result<(), PrepareResult> {
+        if let Some(expr) = where_clause {
+            expr::typecheck(expr, self.schema)?;
+        }
+        let range = KeyRange::from_where(where_clause, self.schema);
+        self.plan.push(range.describe(self.schema));
+        // jumps to the end of the loop
+        let mut exits = Vec::new();
+
+        if let Some(key) = range.eq {
+            // at most one row has the key, there is nothing to loop over
+            let reg = self.alloc_registers(1);
+            self.compile_expr(key, reg);
+            exits.push(self.emit(Opcode::SeekRowid { cursor: TABLE_CURSOR, key: reg, not_found: 0 }));
+            if let Some(expr) = where_clause {
+                self.compile_condition(expr, &mut exits);
+            }
+            body(self);
+        } else {
+            let upper = range.upper.map(|(op, key)| {
+                let reg = self.alloc_registers(1);
+                self.compile_expr(key, reg);
+                (op, reg)
+            });
+            match range.lower {
+                Some((op, key)) => {
+                    let reg = self.alloc_registers(1);
+                    self.compile_expr(key, reg);
+                    exits.push(self.emit(if op == BinaryOp::Gt {
+                        Opcode::SeekGT { cursor: TABLE_CURSOR, key: reg, if_eof: 0 }
+                    } else {
+                        Opcode::SeekGE { cursor: TABLE_CURSOR, key: reg, if_eof: 0 }
+                    }));
+                },
+                None => exits.push(self.emit(Opcode::Rewind { cursor: TABLE_CURSOR, if_empty: 0 }))
+            }
+            let loop_start = self.current_addr();
+            if let Some((op, bound)) = upper {
+                // rows are visited in key order, so the loop ends at the first key out of range
+                let key = self.alloc_registers(1);
+                self.emit(Opcode::Rowid { cursor: TABLE_CURSOR, dest: key });
+                let (left, right, target, jump_if_null) = (key, bound, 0, true);
+                exits.push(self.emit(if op == BinaryOp::Lt {
+                    Opcode::Ge { left, right, target, jump_if_null }
+                } else {
+                    Opcode::Gt { left, right, target, jump_if_null }
+                }));
+            }
+            let mut skips = Vec::new();
+            if let Some(expr) = where_clause {
+                self.compile_condition(expr, &mut skips);
+            }
+            body(self);
+            let next = self.emit(Opcode::Next { cursor: TABLE_CURSOR, target: loop_start });
+            for skip in skips {
+                self.patch_jump(skip, next);
+            }
+        }
+        let end = self.current_addr();
+        for exit in exits {
+            self.patch_jump(exit, end);
+        }
+        Ok(())
+    }
+
+    /// Emit the code visiting the rows read by the select, with `body` emitting the code run for
+    /// every row.
+    fn compile_select_rows(&mut self, select: &Select, body: impl FnOnce(&mut Self)) -> Result<(), PrepareResult> {
+        if select.from.is_some() {
+            self.emit(Opcode::OpenRead { cursor: TABLE_CURSOR, table: String::from(self.schema.name) });
+            return self.compile_loop(select.where_clause.as_ref(), body);
+        }
+        // without a table the columns are evaluated once, when the where clause holds
+        if let Some(expr) = &select.where_clause {
+            expr::typecheck(expr, self.schema)?;
+        }
+        self.plan.push(String::from("SCAN CONSTANT ROW"));
+        let mut skips = Vec::new();
+        if let Some(expr) = &select.where_clause {
+            self.compile_condition(expr, &mut skips);
+        }
+        body(self);
+        let end = self.current_addr();
+        for skip in skips {
+            self.patch_jump(skip, end);
+        }
+        Ok(())
+    }
+}
+
+/// The expressions and names of the columns returned by the select.
+fn result_columns(select: &Select, schema: &TableSchema) -> Result<(Vec<Expr>, Vec<String>), PrepareResult> {
+    let mut outputs = Vec::new();
+    let mut column_names = Vec::new();
+    for column in &select.columns {
+        match column {
+            ResultColumn::All => {
+                for c in schema.columns {
+                    outputs.push(Expr::Column(String::from(c.name)));
+                    column_names.push(String::from(c.name));
+                }
+            },
+            ResultColumn::Expr { expr, alias } => {
+                expr::typecheck(expr, schema)?;
+                outputs.push(expr.clone());
+                column_names.push(alias.clone().unwrap_or_else(|| expr.to_string()));
+            }
+        }
+    }
+    Ok((outputs, column_names))
+}
+
+/// What a select without a `from` clause reads: no table, so no column can be referred to.
+const NO_TABLE: TableSchema = TableSchema { name: "", columns: &[] };
+
+fn select_table(select: &Select) -> Result<&'static TableSchema, PrepareResult> {
+    match &select.from {
+        Some(name) => find_table(name),
+        None => Ok(&NO_TABLE)
+    }
+}
+
+/// Check that a row to insert has a value of a compatible type for every column of the table.
+/// `schema` is the table the values are computed from.
+fn check_insert_row(table: &TableSchema, row: &[Expr], schema: &TableSchema) -> Result<(), PrepareResult> {
+    if row.len() != table.columns.len() {
+        return Err(PrepareResult::PREPARE_VALUE_COUNT_MISMATCH(table.columns.len(), row.len()));
+    }
+    for (column, expr) in table.columns.iter().zip(row) {
+        if !expr::typecheck(expr, schema)?.is_compatible(column.col_type) {
+            return Err(PrepareResult::PREPARE_TYPE_MISMATCH(expr.to_string()));
+        }
+    }
+    Ok(())
+}
+
+pub fn compile_select(select: &Select) -> Result<Program, PrepareResult> {
+    let schema = select_table(select)?;
+    let mut generator = CodeGenerator::new(schema);
+    let (outputs, column_names) = result_columns(select, schema)?;
+
+    let start = generator.alloc_registers(outputs.len());
+    generator.compile_select_rows(select, |g| {
+        for (i, expr) in outputs.iter().enumerate() {
+            g.compile_expr(expr, start + i);
+        }
+        g.emit(Opcode::ResultRow { start, count: outputs.len() });
+    })?;
+    Ok(generator.finish(column_names))
+}
+
+/// Check the `on conflict` clause of an insert into the table.
+fn check_on_conflict(insert: &Insert, table: &TableSchema) -> Result<(), PrepareResult> {
+    if let Some(target) = &insert.conflict_target {
+        if table.column_index(target) != Some(0) {
+            return Err(PrepareResult::PREPARE_INVALID_CONFLICT_TARGET(target.clone()));
+        }
+    }
+    if let OnConflict::Update { assignments, where_clause } = &insert.on_conflict {
+        for (name, expr) in assignments {
+            let column = table.column_index(name)
+                .ok_or_else(|| PrepareResult::PREPARE_UNKNOWN_COLUMN(name.clone()))?;
+            if column == 0 {
+                return Err(PrepareResult::PREPARE_PRIMARY_KEY_UPDATE(name.clone()));
+            }
+            if !expr::typecheck(expr, table)?.is_compatible(table.columns[column].col_type) {
+                return Err(PrepareResult::PREPARE_TYPE_MISMATCH(expr.to_string()));
+            }
+        }
+        if let Some(expr) = where_clause {
+            expr::typecheck(expr, table)?;
+        }
+    }
+    Ok(())
+}
+
+pub fn compile_insert(insert: &Insert) -> Result<Program, PrepareResult> {
+    let table = find_table(&insert.table)?;
+    check_on_conflict(insert, table)?;
+    match &insert.source {
+        InsertSource::Values(rows) => {
+            // there is no current row the values could refer to
+            let no_columns = TableSchema { name: table.name, columns: &[] };
+            let mut generator = CodeGenerator::new(table);
+            generator.emit(Opcode::OpenWrite { cursor: TABLE_CURSOR, table: String::from(table.name) });
+            let start = generator.alloc_registers(table.columns.len());
+            let update = generator.alloc_registers(table.columns.len());
+            for row in rows {
+                check_insert_row(table, row, &no_columns)?;
+                for (i, expr) in row.iter().enumerate() {
+                    generator.compile_expr(expr, start + i);
+                }
+                generator.compile_insert_row(start, &insert.on_conflict, update);
+            }
+            Ok(generator.finish(Vec::new()))
+        },
+        InsertSource::Select(select) => {
+            let schema = select_table(select)?;
+            let (outputs, _) = result_columns(select, schema)?;
+            check_insert_row(table, &outputs, schema)?;
+            let mut generator = CodeGenerator::new(schema);
+            let count = outputs.len();
+            let start = generator.alloc_registers(count);
+            let update = generator.alloc_registers(count);
+
+            // Collect every selected row before inserting any, so that the scan does not run into
+            // the rows being inserted.
+            generator.emit(Opcode::OpenEphemeral { cursor: EPHEMERAL_CURSOR, columns: count });
+            generator.compile_select_rows(select, |g| {
+                for (i, expr) in outputs.iter().enumerate() {
+                    g.compile_expr(expr, start + i);
+                }
+                g.emit(Opcode::Append { cursor: EPHEMERAL_CURSOR, start, count });
+            })?;
+
+            generator.emit(Opcode::OpenWrite { cursor: TABLE_CURSOR, table: String::from(table.name) });
+            let rewind = generator.emit(Opcode::Rewind { cursor: EPHEMERAL_CURSOR, if_empty: 0 });
+            let loop_start = generator.current_addr();
+            for i in 0..count {
+                generator.emit(Opcode::Column { cursor: EPHEMERAL_CURSOR, column: i, dest: start + i });
+            }
+            generator.compile_insert_row(start, &insert.on_conflict, update);
+            generator.emit(Opcode::Next { cursor: EPHEMERAL_CURSOR, target: loop_start });
+            let end = generator.current_addr();
+            generator.patch_jump(rewind, end);
+            Ok(generator.finish(Vec::new()))
+        }
+    }
+}
+
+/// Pragmas reading a setting of the connection, or changing it when given a value.
+const BOOLEAN_PRAGMAS: [&str; 2] = ["compression", "verify_checksums"];
+const INTEGER_PRAGMAS: [&str; 1] = ["page_size"];
+/// Pragmas taking a passphrase, which only change a setting. An empty passphrase given to
+/// `rekey` decrypts the database.
+const PASSPHRASE_PRAGMAS: [&str; 2] = ["key", "rekey"];
+
+/// The value of an integer pragma if it is one the pragma takes.
+fn integer_value(name: &str, value: &Value) -> Option<i64> {
+    match (name, value) {
+        ("page_size", Value::Integer(i)) if usize::try_from(*i).is_ok_and(is_valid_page_size) => Some(*i),
+        _ => None
+    }
+}
+
+/// The value of a boolean pragma as 0 or 1, taking `on`, `off`, `true`, `false`, `yes`, `no`
+/// and integers the way SQLite does.
+fn boolean_value(value: &Value) -> Option<i64> {
+    match value {
+        Value::Integer(i) => Some((*i != 0) as i64),
+        Value::Text(s) => match s.to_ascii_lowercase().as_str() {
+            "on" | "true" | "yes" => Some(1),
+            "off" | "false" | "no" => Some(0),
+            _ => None
+        },
+        Value::Null => None
+    }
+}
+
+/// Compile `pragma integrity_check`, which outputs a single row holding `ok` or the problems
+/// found in the B-tree of the table, one per line, or a pragma reading or changing a setting.
+/// Reading a setting outputs its value, changing it outputs nothing.
+pub fn compile_pragma(pragma: &Pragma) -> Result<Program, PrepareResult> {
+    let name = pragma.name.to_ascii_lowercase();
+    let schema = &crate::schema::USERS;
+    let mut generator = CodeGenerator::new(schema);
+    let dest = generator.alloc_registers(1);
+    if name == "integrity_check" && pragma.value.is_none() {
+        generator.emit(Opcode::IntegrityCk { table: String::from(schema.name), dest });
+    } else if BOOLEAN_PRAGMAS.contains(&name.as_str()) || INTEGER_PRAGMAS.contains(&name.as_str()) {
+        let value = match &pragma.value {
+            Some(value) if BOOLEAN_PRAGMAS.contains(&name.as_str()) => Some(boolean_value(value)
+                .ok_or_else(|| PrepareResult::PREPARE_INVALID_PRAGMA_VALUE(name.clone()))?),
+            Some(value) => Some(integer_value(&name, value)
+                .ok_or_else(|| PrepareResult::PREPARE_INVALID_PRAGMA_VALUE(name.clone()))?),
+            None => None
+        };
+        generator.emit(Opcode::Pragma { name: name.clone(), value: value.map(Value::Integer), dest });
+        if value.is_some() {
+            return Ok(generator.finish(Vec::new()));
+        }
+    } else if PASSPHRASE_PRAGMAS.contains(&name.as_str()) {
+        let passphrase = match &pragma.value {
+            Some(Value::Text(passphrase)) if name == "rekey" || !passphrase.is_empty() => passphrase.clone(),
+            _ => return Err(PrepareResult::PREPARE_INVALID_PRAGMA_VALUE(name))
+        };
+        generator.emit(Opcode::Pragma { name, value: Some(Value::Text(passphrase)), dest });
+        return Ok(generator.finish(Vec::new()));
+    } else if name == "integrity_check" {
+        return Err(PrepareResult::PREPARE_INVALID_PRAGMA_VALUE(name));
+    } else {
+        return Err(PrepareResult::PREPARE_UNKNOWN_PRAGMA(pragma.name.clone()));
+    }
+    generator.emit(Opcode::ResultRow { start: dest, count: 1 });
+    Ok(generator.finish(vec![name]))
+}
+
+pub fn compile_delete(delete: &Delete) -> Result<Program, PrepareResult> {
+    let schema = find_table(&delete.table)?;
+    let mut generator = CodeGenerator::new(schema);
+    generator.emit(Opcode::OpenWrite { cursor: TABLE_CURSOR, table: String::from(schema.name) });
+    generator.compile_loop(delete.where_clause.as_ref(), |g| {
+        g.emit(Opcode::Delete { cursor: TABLE_CURSOR });
+    })?;
+    Ok(generator.finish(Vec::new()))
+}
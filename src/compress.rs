@@ -0,0 +1,42 @@
+use std::fs::File;
+use std::io::{self, Read, Seek, SeekFrom};
+
+/// Where the compressed image of a page is stored in the file.
+#[derive(Clone, Copy, Debug)]
+pub struct Extent {
+    pub offset: u64,
+    pub len: u32
+}
+
+/// Bytes of an extent in the page map, its offset then its length.
+pub const EXTENT_SIZE: usize = std::mem::size_of::<u64>() + std::mem::size_of::<u32>();
+
+/// The page compressed with LZ4. The zeros padding the text of the rows and the free space of
+/// the pages make most of it.
+pub fn compress_page(page: &[u8]) -> Vec<u8> {
+    lz4_flex::block::compress(page)
+}
+
+/// The page decompressed from its image, `None` if the image is not a compressed page of that
+/// size.
+pub fn decompress_page(image: &[u8], page_size: usize) -> Option<Vec<u8>> {
+    lz4_flex::block::decompress(image, page_size).ok().filter(|page| page.len() == page_size)
+}
+
+/// Read the `len` extents of the page map stored at `offset`.
+pub fn read_page_map(file: &mut File, offset: u64, len: usize) -> io::Result<Vec<Extent>> {
+    let mut bytes = vec![0; len * EXTENT_SIZE];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(bytes.chunks(EXTENT_SIZE).map(|entry| Extent {
+        offset: u64::from_le_bytes(entry[..8].try_into().unwrap()),
+        len: u32::from_le_bytes(entry[8..].try_into().unwrap())
+    }).collect())
+}
+
+/// The page map as it is stored, one extent per page in page order.
+pub fn page_map_bytes(extents: &[Extent]) -> Vec<u8> {
+    extents.iter()
+        .flat_map(|extent| extent.offset.to_le_bytes().into_iter().chain(extent.len.to_le_bytes()))
+        .collect()
+}
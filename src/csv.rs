@@ -0,0 +1,205 @@
+use std::borrow::Cow;
+use std::io::{self, BufRead, Write};
+use crate::schema::TableSchema;
+use crate::value::{SqlType, Value};
+use crate::{execute_statement, prepare_statement, ExecuteResult, PrepareResult, Statement, Table};
+
+/// Why a CSV import or export failed, or why one row of an import was rejected.
+#[derive(Debug)]
+pub enum CsvError {
+    /// The statement reading or writing the table could not be prepared, the table does not exist.
+    Prepare(PrepareResult),
+    /// The row was rejected by the insert, or the select failed.
+    Execute(ExecuteResult),
+    /// The record has a number of fields other than the number of columns of the table.
+    FieldCount { expected: usize, found: usize },
+    Io(io::Error)
+}
+
+impl From<io::Error> for CsvError {
+    fn from(e: io::Error) -> Self {
+        CsvError::Io(e)
+    }
+}
+
+/// Reads RFC 4180 records: fields are separated by commas, a field in double quotes can hold
+/// commas, line breaks and quotes written twice.
+pub struct CsvReader<R> {
+    input: R,
+    line: usize
+}
+
+impl<R: BufRead> CsvReader<R> {
+
+    pub fn new(input: R) -> Self {
+        CsvReader { input, line: 0 }
+    }
+
+    /// Read the next record with the number of the line it starts on, `None` at the end of the
+    /// input. Only the lines of the record are held in memory.
+    pub fn read_record(&mut self) -> io::Result<Option<(usize, Vec<String>)>> {
+        let mut line = String::new();
+        if self.input.read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        self.line += 1;
+        let start = self.line;
+        let mut fields = Vec::new();
+        let mut field = String::new();
+        let mut quoted = false;
+        loop {
+            let mut chars = line.chars().peekable();
+            while let Some(c) = chars.next() {
+                if quoted {
+                    if c != '"' {
+                        field.push(c);
+                    } else if chars.peek() == Some(&'"') {
+                        chars.next();
+                        field.push('"');
+                    } else {
+                        quoted = false;
+                    }
+                    continue;
+                }
+                match c {
+                    '"' if field.is_empty() => quoted = true,
+                    ',' => fields.push(std::mem::take(&mut field)),
+                    '\r' | '\n' => {},
+                    c => field.push(c)
+                }
+            }
+            if !quoted {
+                break;
+            }
+            line.clear();
+            if self.input.read_line(&mut line)? == 0 {
+                return Err(io::Error::new(io::ErrorKind::InvalidData,
+                    format!("unterminated quoted field starting on line {}", start)));
+            }
+            self.line += 1;
+        }
+        fields.push(field);
+        Ok(Some((start, fields)))
+    }
+}
+
+/// Quote the field if it holds a character that would otherwise end it or be dropped.
+pub fn quote(field: &str) -> Cow<'_, str> {
+    if field.contains([',', '"', '\n', '\r']) {
+        Cow::Owned(format!("\"{}\"", field.replace('"', "\"\"")))
+    } else {
+        Cow::Borrowed(field)
+    }
+}
+
+/// Write the fields as one record, `NULL` as an empty field.
+pub fn write_record(output: &mut impl Write, fields: &[Value]) -> io::Result<()> {
+    let fields: Vec<String> = fields.iter()
+        .map(|v| match v {
+            Value::Null => String::new(),
+            v => quote(&v.to_string()).into_owned()
+        })
+        .collect();
+    writeln!(output, "{}", fields.join(","))
+}
+
+/// Whether the record names every column of the table once, in any order. Returns the index in
+/// the record of each column.
+fn header_positions(record: &[String], schema: &TableSchema) -> Option<Vec<usize>> {
+    if record.len() != schema.columns.len() {
+        return None;
+    }
+    let positions: Vec<usize> = schema.columns.iter()
+        .map(|c| record.iter().position(|f| f.trim().eq_ignore_ascii_case(c.name)))
+        .collect::<Option<_>>()?;
+    Some(positions)
+}
+
+/// Convert the field to a value of the column type, text that is not a number going into an
+/// integer column fails like it would in an insert.
+fn field_value(field: &str, column: usize, schema: &TableSchema) -> Result<Value, ExecuteResult> {
+    let column = &schema.columns[column];
+    match column.col_type {
+        SqlType::Integer if field.is_empty() => Ok(Value::Null),
+        SqlType::Integer => field.trim().parse::<i64>()
+            .map(Value::Integer)
+            .map_err(|_| ExecuteResult::EXECUTE_TYPE_MISMATCH(String::from(column.name))),
+        _ => Ok(Value::Text(String::from(field)))
+    }
+}
+
+/// Insert the records of the input into the table one at a time, through the same statement
+/// as `insert into table values (?, ...)` run by `execute`. A first record naming the columns
+/// is taken as the header and gives the order of the fields. Rejected records are passed to
+/// `on_error` with their line number and skipped. Returns the number of rows inserted.
+pub fn import(table_name: &str, input: impl BufRead, mut on_error: impl FnMut(usize, CsvError), mut execute: impl FnMut(&Statement) -> ExecuteResult) -> Result<usize, CsvError> {
+    let schema = TableSchema::find(table_name)
+        .ok_or_else(|| CsvError::Prepare(PrepareResult::PREPARE_UNKNOWN_TABLE(String::from(table_name))))?;
+    let placeholders = vec!["?"; schema.columns.len()].join(", ");
+    let mut statement = prepare_statement(&format!("insert into {} values ({})", schema.name, placeholders))
+        .map_err(CsvError::Prepare)?;
+
+    let mut reader = CsvReader::new(input);
+    let mut positions: Vec<usize> = (0..schema.columns.len()).collect();
+    let mut imported = 0;
+    let mut first = true;
+    while let Some((line, record)) = reader.read_record()? {
+        if record.len() == 1 && record[0].is_empty() {
+            continue;
+        }
+        if std::mem::take(&mut first) {
+            if let Some(header) = header_positions(&record, schema) {
+                positions = header;
+                continue;
+            }
+        }
+        if record.len() != schema.columns.len() {
+            on_error(line, CsvError::FieldCount { expected: schema.columns.len(), found: record.len() });
+            continue;
+        }
+        let values: Result<Vec<Value>, ExecuteResult> = positions.iter().enumerate()
+            .map(|(column, &i)| field_value(&record[i], column, schema))
+            .collect();
+        let values = match values {
+            Ok(values) => values,
+            Err(result) => {
+                on_error(line, CsvError::Execute(result));
+                continue;
+            }
+        };
+        for (i, value) in values.into_iter().enumerate() {
+            statement.bind(i + 1, value);
+        }
+        match execute(&statement) {
+            ExecuteResult::EXECUTE_SUCCESS => imported += 1,
+            result => on_error(line, CsvError::Execute(result))
+        }
+    }
+    Ok(imported)
+}
+
+/// Write a header naming the columns and then every row of the table in key order, read
+/// through a cursor one row at a time. Returns the number of rows written.
+pub fn export(table: &mut Table, table_name: &str, mut output: impl Write) -> Result<usize, CsvError> {
+    let statement = prepare_statement(&format!("select * from {}", table_name)).map_err(CsvError::Prepare)?;
+    let names: Vec<Value> = statement.program.column_names.iter().map(|n| Value::from(n.as_str())).collect();
+    write_record(&mut output, &names)?;
+    let mut exported = 0;
+    let mut error = None;
+    let result = execute_statement(&statement, table, |row| {
+        if error.is_none() {
+            match write_record(&mut output, row) {
+                Ok(()) => exported += 1,
+                Err(e) => error = Some(e)
+            }
+        }
+    });
+    if let Some(e) = error {
+        return Err(CsvError::Io(e));
+    }
+    if result != ExecuteResult::EXECUTE_SUCCESS {
+        return Err(CsvError::Execute(result));
+    }
+    output.flush()?;
+    Ok(exported)
+}
@@ -0,0 +1,209 @@
+use std::cmp::Ordering;
+use crate::schema::TableSchema;
+use crate::sql::{BinaryOp, Expr, UnaryOp};
+use crate::value::{SqlType, Value};
+use crate::{ExecuteResult, PrepareResult};
+
+/// A scalar function callable from SQL.
+struct Function {
+    name: &'static str,
+    /// Types accepted for the single argument.
+    arg_types: &'static [SqlType],
+    return_type: SqlType,
+    apply: fn(&Value) -> Result<Value, ExecuteResult>
+}
+
+const FUNCTIONS: [Function; 5] = [
+    Function {
+        name: "upper",
+        arg_types: &[SqlType::Text],
+        return_type: SqlType::Text,
+        apply: |v| Ok(match v {
+            Value::Text(s) => Value::Text(s.to_uppercase()),
+            _ => Value::Null
+        })
+    },
+    Function {
+        name: "lower",
+        arg_types: &[SqlType::Text],
+        return_type: SqlType::Text,
+        apply: |v| Ok(match v {
+            Value::Text(s) => Value::Text(s.to_lowercase()),
+            _ => Value::Null
+        })
+    },
+    Function {
+        name: "length",
+        arg_types: &[SqlType::Text, SqlType::Integer],
+        return_type: SqlType::Integer,
+        apply: |v| Ok(match v {
+            Value::Null => Value::Null,
+            v => Value::Integer(v.to_string().chars().count() as i64)
+        })
+    },
+    Function {
+        name: "abs",
+        arg_types: &[SqlType::Integer],
+        return_type: SqlType::Integer,
+        apply: |v| Ok(match v {
+            Value::Integer(i) => Value::Integer(i.checked_abs().ok_or(ExecuteResult::EXECUTE_INTEGER_OVERFLOW)?),
+            _ => Value::Null
+        })
+    },
+    Function {
+        name: "char",
+        arg_types: &[SqlType::Integer],
+        return_type: SqlType::Text,
+        apply: |v| Ok(match v {
+            Value::Integer(i) => u32::try_from(*i).ok()
+                .and_then(char::from_u32)
+                .map_or(Value::Null, |c| Value::Text(c.to_string())),
+            _ => Value::Null
+        })
+    }
+];
+
+fn find_function(name: &str) -> Option<&'static Function> {
+    FUNCTIONS.iter().find(|f| f.name == name)
+}
+
+/// Check that every column referenced by the expression exists in the table and that every
+/// operand has a type its operator accepts. Returns the type of the expression.
+///
+/// The type of a parameter is only known once a value is bound, so like `NULL` it is accepted
+/// anywhere.
+pub fn typecheck(expr: &Expr, schema: &TableSchema) -> Result<SqlType, PrepareResult> {
+    fn mismatch(expr: &Expr) -> PrepareResult {
+        PrepareResult::PREPARE_TYPE_MISMATCH(expr.to_string())
+    }
+
+    match expr {
+        Expr::Literal(value) => Ok(value.sql_type()),
+        Expr::Parameter { .. } => Ok(SqlType::Null),
+        Expr::Excluded(name) => match schema.column_index(name) {
+            Some(i) => Ok(schema.columns[i].col_type),
+            None => Err(PrepareResult::PREPARE_UNKNOWN_COLUMN(expr.to_string()))
+        },
+        Expr::Column(name) => match schema.column_index(name) {
+            Some(i) => Ok(schema.columns[i].col_type),
+            None => Err(PrepareResult::PREPARE_UNKNOWN_COLUMN(name.clone()))
+        },
+        Expr::Unary(_, operand) => {
+            if typecheck(operand, schema)?.is_compatible(SqlType::Integer) {
+                Ok(SqlType::Integer)
+            } else {
+                Err(mismatch(expr))
+            }
+        },
+        Expr::Binary(op, left, right) => {
+            let left_type = typecheck(left, schema)?;
+            let right_type = typecheck(right, schema)?;
+            match op {
+                BinaryOp::Concat => Ok(SqlType::Text),
+                op if op.is_comparison() => {
+                    if left_type.is_compatible(right_type) {
+                        Ok(SqlType::Integer)
+                    } else {
+                        Err(mismatch(expr))
+                    }
+                },
+                _ => {
+                    if left_type.is_compatible(SqlType::Integer) && right_type.is_compatible(SqlType::Integer) {
+                        Ok(SqlType::Integer)
+                    } else {
+                        Err(mismatch(expr))
+                    }
+                }
+            }
+        },
+        Expr::Function(name, args) => {
+            let function = find_function(name)
+                .ok_or_else(|| PrepareResult::PREPARE_UNKNOWN_FUNCTION(name.clone()))?;
+            if args.len() != 1 {
+                return Err(mismatch(expr));
+            }
+            let arg_type = typecheck(&args[0], schema)?;
+            if arg_type == SqlType::Null || function.arg_types.contains(&arg_type) {
+                Ok(function.return_type)
+            } else {
+                Err(mismatch(expr))
+            }
+        }
+    }
+}
+
+pub fn apply_unary(op: UnaryOp, operand: Value) -> Result<Value, ExecuteResult> {
+    match (op, operand) {
+        (_, Value::Null) => Ok(Value::Null),
+        (UnaryOp::Neg, Value::Integer(i)) => i.checked_neg()
+            .map(Value::Integer)
+            .ok_or(ExecuteResult::EXECUTE_INTEGER_OVERFLOW),
+        (UnaryOp::Not, v) => Ok(v.as_bool().map_or(Value::Null, |b| Value::from_bool(!b))),
+        (UnaryOp::Neg, Value::Text(_)) => Ok(Value::Null)
+    }
+}
+
+/// Apply a binary operator. Any operator except `and` and `or` yields `NULL` if one of its
+/// operands is `NULL`, and division by zero yields `NULL` as well.
+pub fn apply_binary(op: BinaryOp, left: Value, right: Value) -> Result<Value, ExecuteResult> {
+    match op {
+        BinaryOp::And => {
+            return Ok(match (left.as_bool(), right.as_bool()) {
+                (Some(false), _) | (_, Some(false)) => Value::from_bool(false),
+                (Some(true), Some(true)) => Value::from_bool(true),
+                _ => Value::Null
+            });
+        },
+        BinaryOp::Or => {
+            return Ok(match (left.as_bool(), right.as_bool()) {
+                (Some(true), _) | (_, Some(true)) => Value::from_bool(true),
+                (Some(false), Some(false)) => Value::from_bool(false),
+                _ => Value::Null
+            });
+        },
+        _ => {}
+    }
+    if left == Value::Null || right == Value::Null {
+        return Ok(Value::Null);
+    }
+    if op == BinaryOp::Concat {
+        return Ok(Value::Text(format!("{}{}", left, right)));
+    }
+    if op.is_comparison() {
+        return Ok(compare(&left, &right).map_or(Value::Null, |ordering| Value::from_bool(match op {
+            BinaryOp::Eq => ordering == Ordering::Equal,
+            BinaryOp::NotEq => ordering != Ordering::Equal,
+            BinaryOp::Lt => ordering == Ordering::Less,
+            BinaryOp::LtEq => ordering != Ordering::Greater,
+            BinaryOp::Gt => ordering == Ordering::Greater,
+            _ => ordering != Ordering::Less
+        })));
+    }
+    let (l, r) = match (left, right) {
+        (Value::Integer(l), Value::Integer(r)) => (l, r),
+        _ => return Ok(Value::Null)
+    };
+    let result = match op {
+        BinaryOp::Add => l.checked_add(r),
+        BinaryOp::Sub => l.checked_sub(r),
+        BinaryOp::Mul => l.checked_mul(r),
+        BinaryOp::Div | BinaryOp::Rem if r == 0 => return Ok(Value::Null),
+        BinaryOp::Div => l.checked_div(r),
+        _ => l.checked_rem(r)
+    };
+    result.map(Value::Integer).ok_or(ExecuteResult::EXECUTE_INTEGER_OVERFLOW)
+}
+
+/// Compare two values of the same type, `None` if either of them is `NULL` or the types differ.
+pub fn compare(left: &Value, right: &Value) -> Option<Ordering> {
+    match (left, right) {
+        (Value::Integer(l), Value::Integer(r)) => Some(l.cmp(r)),
+        (Value::Text(l), Value::Text(r)) => Some(l.cmp(r)),
+        _ => None
+    }
+}
+
+/// Call a function that has passed the type check.
+pub fn call_function(name: &str, arg: &Value) -> Result<Value, ExecuteResult> {
+    (find_function(name).unwrap().apply)(arg)
+}
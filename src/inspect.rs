@@ -0,0 +1,118 @@
+use std::collections::BTreeSet;
+use std::fs::File;
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::AtomicBool;
+use crate::{empty_slots, FileHeader, Page, Pager, TABLE_MAX_PAGES};
+
+/// A database file opened read-only, to look at its pages as they are on disk. Nothing is ever
+/// written back, checksums are not verified so that corrupt pages can be looked at, and a file
+/// that is not a whole number of pages is still opened, without the bytes of its last partial
+/// page.
+pub struct PageFile {
+    pager: Pager,
+    file_len: u64,
+    trailing_bytes: u64
+}
+
+impl PageFile {
+
+    pub fn open(file_name: &str) -> io::Result<PageFile> {
+        let mut file = File::open(file_name)?;
+        let file_len = file.metadata()?.len();
+        let header = FileHeader::read(&mut file).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        let (num_pages, trailing_bytes) = match &header.page_map {
+            Some(_) => {
+                let num_pages = header.num_pages(file_len).unwrap();
+                (num_pages, file_len.saturating_sub(header.pages_end(num_pages)))
+            },
+            None => {
+                let pages_len = file_len.saturating_sub(header.header_size);
+                (((pages_len / header.page_size as u64) as usize).min(TABLE_MAX_PAGES), pages_len % header.page_size as u64)
+            }
+        };
+        Ok(PageFile {
+            pager: Pager {
+                file_descriptor: Arc::new(Mutex::new(file)),
+                file_name: String::from(file_name),
+                pages: empty_slots(),
+                num_pages,
+                page_size: header.page_size,
+                header_size: header.header_size,
+                header_written: true,
+                use_mmap: false,
+                read_only: true,
+                map: Arc::new(Mutex::new(None)),
+                journal: None,
+                verify_checksums: false,
+                corrupt_pages: Mutex::new(BTreeSet::new()),
+                found_corrupt: AtomicBool::new(false),
+                compression: header.compressed,
+                cipher: None,
+                page_map: header.page_map.map(|page_map| Arc::new(page_map.extents)),
+                compressed: header.compressed,
+                encryption: header.encryption,
+                file_cipher: None,
+                dirty: BTreeSet::new(),
+                reformat: false
+            },
+            file_len,
+            trailing_bytes
+        })
+    }
+
+    /// Size of the file in bytes.
+    pub fn file_len(&self) -> u64 {
+        self.file_len
+    }
+
+    /// Size of the pages, as recorded in the header.
+    pub fn page_size(&self) -> usize {
+        self.pager.page_size
+    }
+
+    /// Whether the file starts with a header page, which files written before the page size
+    /// could be chosen lack.
+    pub fn has_header(&self) -> bool {
+        self.pager.header_size > 0 && self.file_len > 0
+    }
+
+    /// Whether the pages are compressed, stored where the page map at the end of the file says.
+    pub fn is_compressed(&self) -> bool {
+        self.pager.compressed
+    }
+
+    /// Whether the pages are encrypted, in which case they read as empty leaves until the key
+    /// is given.
+    pub fn is_encrypted(&self) -> bool {
+        self.pager.encryption.is_some()
+    }
+
+    /// Give the key of an encrypted file, returns whether the passphrase is the right one.
+    pub fn set_key(&mut self, passphrase: &str) -> bool {
+        self.is_encrypted() && self.pager.set_key(passphrase).is_ok()
+    }
+
+    /// Bytes after the last whole page, or after the page map of a compressed file, which a
+    /// sound file does not have.
+    pub fn trailing_bytes(&self) -> u64 {
+        self.trailing_bytes
+    }
+
+    pub fn num_pages(&self) -> usize {
+        self.pager.num_pages
+    }
+
+    /// The page the root of the table is stored in.
+    pub fn root_page_num(&self) -> usize {
+        0
+    }
+
+    /// The page read from the file, `None` past its end.
+    pub fn page(&self, page_num: usize) -> Option<&Page> {
+        if page_num >= self.pager.num_pages {
+            return None;
+        }
+        self.pager.get_page_view(page_num)
+    }
+}
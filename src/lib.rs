@@ -0,0 +1,2675 @@
+#![allow(non_camel_case_types)]
+
+mod backup;
+mod bulk;
+mod check;
+mod cipher;
+mod codegen;
+mod compress;
+mod csv;
+mod expr;
+mod inspect;
+mod output;
+mod schema;
+mod stats;
+mod sql;
+mod value;
+mod vm;
+
+use std::fs::{self, File, OpenOptions, TryLockError};
+use std::collections::{BTreeSet, HashMap};
+use std::io::{self, BufRead, Read, Seek, SeekFrom, Write};
+use std::iter::Rev;
+use std::path::Path;
+use std::ops::{Deref, DerefMut, Range};
+use std::process;
+use std::sync::{Arc, Mutex, OnceLock, RwLock};
+use std::sync::atomic::{self, AtomicBool};
+use std::thread;
+use std::time::{Duration, Instant};
+use memmap2::Mmap;
+use crate::cipher::PageCipher;
+use crate::compress::Extent;
+use crate::ExecuteResult::{EXECUTE_FAIL, EXECUTE_SUCCESS};
+use crate::NodeType::{NODE_INTERNAL, NODE_LEAF};
+use crate::PrepareResult::{PREPARE_NEGATIVE_ID, PREPARE_STRING_TOO_LONG, PREPARE_SYNTAX_ERROR, PREPARE_UNRECOGNIZED_STATEMENT};
+use crate::sql::{Expr, Insert, InsertSource, OnConflict, Parser};
+use crate::vm::{Program, Vm};
+
+pub use crate::backup::Backup;
+pub use crate::bulk::DEFAULT_FILL_FACTOR;
+pub use crate::csv::CsvError;
+pub use crate::inspect::PageFile;
+pub use crate::output::{formatter, Formatter, OutputMode, OutputOptions};
+pub use crate::sql::KEYWORDS;
+pub use crate::stats::TreeStats;
+pub use crate::value::Value;
+
+#[derive(PartialEq, Debug)]
+pub enum PrepareResult {
+    PREPARE_SUCCESS,
+    PREPARE_UNRECOGNIZED_STATEMENT,
+    PREPARE_SYNTAX_ERROR,
+    PREPARE_STRING_TOO_LONG,
+    PREPARE_NEGATIVE_ID,
+    PREPARE_UNKNOWN_TABLE(String),
+    PREPARE_UNKNOWN_COLUMN(String),
+    PREPARE_UNKNOWN_FUNCTION(String),
+    PREPARE_TYPE_MISMATCH(String),
+    /// The number of columns of the table and the number of values to insert into it.
+    PREPARE_VALUE_COUNT_MISMATCH(usize, usize),
+    /// `on conflict` names a column other than the primary key.
+    PREPARE_INVALID_CONFLICT_TARGET(String),
+    /// `on conflict do update` assigns to the primary key.
+    PREPARE_PRIMARY_KEY_UPDATE(String),
+    PREPARE_UNKNOWN_PRAGMA(String),
+    /// The value assigned to the named pragma is not one it takes.
+    PREPARE_INVALID_PRAGMA_VALUE(String)
+}
+
+#[derive(PartialEq, Debug)]
+pub enum ExecuteResult {
+    EXECUTE_SUCCESS,
+    EXECUTE_FAIL,
+    EXECUTE_TABLE_FULL,
+    EXECUTE_DUPLICATE_KEY,
+    EXECUTE_INTEGER_OVERFLOW,
+    EXECUTE_ID_OUT_OF_RANGE,
+    EXECUTE_STRING_TOO_LONG,
+    /// A `NULL` was inserted into the named column.
+    EXECUTE_NOT_NULL(String),
+    /// A value of the wrong type was inserted into the named column.
+    EXECUTE_TYPE_MISMATCH(String),
+    /// A bulk load was given a row whose key is smaller than the key of the row before it.
+    EXECUTE_UNSORTED,
+    /// A bulk load was started on a table that already has rows.
+    EXECUTE_TABLE_NOT_EMPTY,
+    /// The checksum of the page read from the file does not match its content.
+    EXECUTE_CORRUPT_PAGE(usize),
+    /// The page size was set after the file was written or the table got rows.
+    EXECUTE_PAGE_SIZE_FIXED,
+    /// The statement changes the database, which was opened read-only.
+    EXECUTE_READ_ONLY,
+    /// The database is encrypted and `pragma key` has not given its key yet.
+    EXECUTE_NO_KEY,
+    /// `pragma key` was given a passphrase other than the one the database is encrypted with.
+    EXECUTE_WRONG_KEY,
+    /// `pragma key` was given for a database that has rows and is not encrypted, which only
+    /// `pragma rekey` encrypts.
+    EXECUTE_NOT_ENCRYPTED
+}
+
+/// Why a database file could not be opened.
+#[derive(Debug)]
+pub enum OpenError {
+    /// Another process has the file open, for writing, or for reading when opening it for
+    /// writing, and did not close it within the busy timeout.
+    Locked,
+    /// The file is not a database, or its header or its length do not make sense.
+    Corrupt(String),
+    Io(io::Error)
+}
+
+impl From<io::Error> for OpenError {
+    fn from(e: io::Error) -> Self {
+        OpenError::Io(e)
+    }
+}
+
+#[derive(PartialEq)]
+pub enum StatementType {
+    STATEMENT_INSERT,
+    STATEMENT_SELECT,
+    STATEMENT_DELETE,
+    STATEMENT_PRAGMA,
+    STATEMENT_UNSUPPORTED
+}
+
+/// Whether the statement is run, or `explain` lists its program or its query plan instead.
+#[derive(PartialEq)]
+pub enum ExplainMode {
+    EXPLAIN_NONE,
+    EXPLAIN_PROGRAM,
+    EXPLAIN_QUERY_PLAN
+}
+
+/// Result of binding a value to a statement parameter.
+#[derive(PartialEq, Debug)]
+pub enum BindResult {
+    BIND_SUCCESS,
+    BIND_OUT_OF_RANGE,
+    BIND_UNKNOWN_PARAMETER
+}
+
+#[derive(PartialEq)]
+pub enum NodeType {
+    NODE_INTERNAL,
+    NODE_LEAF
+}
+
+pub struct Statement {
+    stmt_type: StatementType,
+    /// The statement compiled for the virtual machine.
+    program: Program,
+    explain: ExplainMode,
+    /// Name of every parameter, `None` for the anonymous ones, indexed from 0.
+    parameter_names: Vec<Option<String>>,
+    /// Values bound to the parameters, `NULL` until bound.
+    parameters: Vec<Value>
+}
+
+impl Statement {
+
+    fn new(stmt_type: StatementType, program: Program, parameter_names: Vec<Option<String>>) -> Self {
+        let parameters = vec![Value::Null; parameter_names.len()];
+        Statement {
+            stmt_type,
+            program,
+            explain: ExplainMode::EXPLAIN_NONE,
+            parameter_names,
+            parameters
+        }
+    }
+
+    /// Names of the columns of the rows the statement outputs, empty if it outputs none.
+    pub fn column_names(&self) -> &[String] {
+        match self.explain {
+            ExplainMode::EXPLAIN_NONE => &self.program.column_names,
+            _ => &[]
+        }
+    }
+
+    /// Number of parameters, which is also the largest valid index.
+    pub fn parameter_count(&self) -> usize {
+        self.parameter_names.len()
+    }
+
+    /// Index of the parameter written as `:name` in the statement, including the colon.
+    pub fn parameter_index(&self, name: &str) -> Option<usize> {
+        self.parameter_names.iter()
+            .position(|n| n.as_deref() == Some(name))
+            .map(|i| i + 1)
+    }
+
+    /// Bind a value to the parameter at the given index. Parameters are numbered from 1, `?`
+    /// taking the number after the previous parameter and `?N` the number N.
+    pub fn bind(&mut self, index: usize, value: impl Into<Value>) -> BindResult {
+        if index == 0 || index > self.parameters.len() {
+            return BindResult::BIND_OUT_OF_RANGE;
+        }
+        self.parameters[index - 1] = value.into();
+        BindResult::BIND_SUCCESS
+    }
+
+    /// Bind a value to the parameter written as `:name` in the statement.
+    pub fn bind_named(&mut self, name: &str, value: impl Into<Value>) -> BindResult {
+        match self.parameter_index(name) {
+            Some(index) => self.bind(index, value),
+            None => BindResult::BIND_UNKNOWN_PARAMETER
+        }
+    }
+
+    /// Clear the bindings so that every parameter is `NULL` again. The compiled program is kept,
+    /// so the statement can be executed again without being prepared anew.
+    pub fn reset(&mut self) {
+        self.parameters.fill(Value::Null);
+    }
+}
+
+#[derive(Clone)]
+pub struct Row {
+    id: u32,
+    username: String,
+    email: String
+}
+
+impl Row {
+
+    /// Values of the row in the column order of `schema::USERS`.
+    fn values(&self) -> [Value; 3] {
+        [
+            Value::Integer(self.id as i64),
+            Value::Text(self.username.clone()),
+            Value::Text(self.email.clone())
+        ]
+    }
+
+    /// Build the row to insert from values in the column order of `schema::USERS`, checking that
+    /// they fit the columns.
+    fn from_values(values: &[Value]) -> Result<Row, ExecuteResult> {
+        let columns = schema::USERS.columns;
+        for (column, value) in columns.iter().zip(values) {
+            match value {
+                Value::Null => return Err(ExecuteResult::EXECUTE_NOT_NULL(String::from(column.name))),
+                value if value.sql_type() != column.col_type => {
+                    return Err(ExecuteResult::EXECUTE_TYPE_MISMATCH(String::from(column.name)));
+                },
+                _ => {}
+            }
+        }
+        match values {
+            [Value::Integer(id), Value::Text(username), Value::Text(email)] => {
+                let id = u32::try_from(*id).map_err(|_| ExecuteResult::EXECUTE_ID_OUT_OF_RANGE)?;
+                if username.len() > USERNAME_SIZE || email.len() > EMAIL_SIZE {
+                    return Err(ExecuteResult::EXECUTE_STRING_TOO_LONG);
+                }
+                Ok(Row {
+                    id,
+                    username: username.clone(),
+                    email: email.clone()
+                })
+            },
+            _ => Err(EXECUTE_FAIL)
+        }
+    }
+}
+
+/// The bytes of a page, a range of a mapping of the file until the page is changed when the
+/// pager maps the file, owned otherwise. A page keeps the mapping it points into alive.
+#[derive(Clone)]
+enum PageBuf {
+    Owned(Vec<u8>),
+    Mapped { map: Arc<Mmap>, range: Range<usize> }
+}
+
+impl PageBuf {
+
+    /// The bytes to change, copied out of the mapping first.
+    fn to_mut(&mut self) -> &mut Vec<u8> {
+        if let PageBuf::Mapped { map, range } = self {
+            *self = PageBuf::Owned(map[range.clone()].to_vec());
+        }
+        match self {
+            PageBuf::Owned(buf) => buf,
+            PageBuf::Mapped { .. } => unreachable!()
+        }
+    }
+
+    fn into_owned(self) -> Vec<u8> {
+        match self {
+            PageBuf::Owned(buf) => buf,
+            PageBuf::Mapped { map, range } => map[range].to_vec()
+        }
+    }
+
+    fn is_mapped(&self) -> bool {
+        matches!(self, PageBuf::Mapped { .. })
+    }
+}
+
+impl Deref for PageBuf {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        match self {
+            PageBuf::Owned(buf) => buf,
+            PageBuf::Mapped { map, range } => &map[range.clone()]
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Page {
+    buf: PageBuf,
+    /// Whether the page is an empty leaf standing in for a page whose checksum did not match.
+    corrupt: bool
+}
+
+impl Page {
+
+    fn new(page_size: usize) -> Self {
+        Page {
+            corrupt: false,
+            buf: PageBuf::Owned(vec![0; page_size])
+        }
+    }
+
+    /// The page as it is laid out in the file.
+    pub fn bytes(&self) -> &[u8] {
+        &self.buf
+    }
+
+    pub fn page_size(&self) -> usize {
+        self.buf.len()
+    }
+
+    /// Number of cells a leaf node of this page size holds.
+    pub fn leaf_node_max_cells(&self) -> usize {
+        leaf_node_max_cells(self.page_size())
+    }
+
+    /// Number of keys an internal node of this page size holds.
+    pub fn internal_node_max_cells(&self) -> usize {
+        internal_node_max_cells(self.page_size())
+    }
+
+    fn checksum_offset(&self) -> usize {
+        self.page_size() - PAGE_CHECKSUM_SIZE
+    }
+
+    /// CRC32C of every byte of the page before the checksum.
+    pub fn compute_checksum(&self) -> u32 {
+        crc32c::crc32c(&self.buf[..self.checksum_offset()])
+    }
+
+    /// The checksum stored at the end of the page when it was last written.
+    pub fn stored_checksum(&self) -> u32 {
+        u32::from_le_bytes(self.buf[self.checksum_offset()..].try_into().unwrap())
+    }
+
+    pub fn has_valid_checksum(&self) -> bool {
+        self.stored_checksum() == self.compute_checksum()
+    }
+
+    fn update_checksum(&mut self) {
+        let checksum = self.compute_checksum();
+        let offset = self.checksum_offset();
+        self.buf.to_mut()[offset..].copy_from_slice(&checksum.to_le_bytes());
+    }
+
+    unsafe fn row_mut_slot(&self, cell_num: usize) -> Box<Row> {
+        // strings are padded with zeros up to the size of their column
+        fn content_len(bytes: &[u8]) -> usize {
+            bytes.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1)
+        }
+        let cell = self.leaf_node_value(cell_num);
+
+        let id = std::ptr::read_unaligned(cell as *const u32);
+        let username_bytes = std::ptr::read((cell as usize + USERNAME_OFFSET) as *const [u8; USERNAME_SIZE]);
+        let email_bytes = std::ptr::read((cell as usize + EMAIL_OFFSET) as *const [u8; EMAIL_SIZE]);
+
+        Box::new(Row {
+            id,
+            username: String::from_utf8_unchecked(Vec::from(&username_bytes[..content_len(&username_bytes)])),
+            email: String::from_utf8_unchecked(Vec::from(&email_bytes[..content_len(&email_bytes)]))
+        })
+    }
+
+    unsafe fn leaf_node_mut_num_cells(&self) -> *mut usize {
+        self.index(LEAF_NODE_NUM_CELLS_OFFSET) as *mut usize
+    }
+
+    pub fn leaf_node_num_cells(&self) -> usize {
+        unsafe { std::ptr::read_unaligned(self.leaf_node_mut_num_cells()) }
+    }
+
+    fn set_leaf_node_num_cells(&mut self, num_cells: usize) {
+        unsafe {
+            std::ptr::write_unaligned(self.leaf_node_mut_num_cells(), num_cells)
+        }
+    }
+
+    fn index(&self, offset: usize) -> isize {
+        let ptr = self.buf.as_ptr();
+        (ptr as isize).checked_add(offset as isize).unwrap()
+    }
+
+    fn leaf_node_cell(&self, cell_num: usize) -> *const u8 {
+        (self.index(LEAF_NODE_HEADER_SIZE + cell_num * LEAF_NODE_CELL_SIZE)) as *const u8
+    }
+
+    pub fn leaf_node_key(&self, cell_num: usize) -> u32 {
+        unsafe { std::ptr::read_unaligned(self.leaf_node_cell(cell_num).add(LEAF_NODE_KEY_OFFSET) as *const u32) }
+    }
+
+    fn set_leaf_node_key(&self, cell_num: usize, key: u32) {
+        unsafe { std::ptr::write_unaligned(self.leaf_node_cell(cell_num).add(LEAF_NODE_KEY_OFFSET) as *mut u32, key) }
+    }
+
+    fn leaf_node_value(&self, cell_num: usize) -> *mut u8 {
+        self.index(LEAF_NODE_HEADER_SIZE + cell_num * LEAF_NODE_CELL_SIZE + LEAF_NODE_VALUE_OFFSET) as *mut u8
+    }
+
+    fn initialize_leaf_node(&mut self) {
+        self.set_node_type(NODE_LEAF);
+        self.set_node_root(false);
+        self.set_leaf_node_next_leaf(0);
+        let ptr = self.index(LEAF_NODE_NUM_CELLS_OFFSET) as *mut usize;
+        unsafe {
+            std::ptr::write_unaligned(ptr, 0);
+        }
+    }
+
+    fn initialize_internal_node(&mut self) {
+        self.set_node_type(NODE_INTERNAL);
+        self.set_node_root(false);
+        let ptr = self.index(INTERNAL_NODE_NUM_KEYS_OFFSET) as *mut usize;
+        unsafe {
+            std::ptr::write_unaligned(ptr, 0);
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.leaf_node_num_cells() >= self.leaf_node_max_cells()
+    }
+
+    fn is_leaf_node(&self) -> bool {
+        *(self.get_node_type()) == NodeType::NODE_LEAF
+    }
+
+    /// The node type, `None` if the byte holds none, which only happens on a corrupt page.
+    pub fn checked_node_type(&self) -> Option<NodeType> {
+        match self.buf[NODE_TYPE_OFFSET] {
+            0 => Some(NODE_INTERNAL),
+            1 => Some(NODE_LEAF),
+            _ => None
+        }
+    }
+
+    /// The root flag, `None` if the byte is neither true nor false.
+    pub fn checked_is_root(&self) -> Option<bool> {
+        match self.buf[IS_ROOT_OFFSET] {
+            0 => Some(false),
+            1 => Some(true),
+            _ => None
+        }
+    }
+
+    fn get_node_type<'a>(&self) -> &'a NodeType {
+        unsafe { &*(self.index(NODE_TYPE_OFFSET) as *const NodeType) }
+    }
+
+    fn set_node_type(&mut self, node_type: NodeType) {
+        let ptr = self.index(NODE_TYPE_OFFSET) as *mut u8;
+        unsafe {
+            *ptr = node_type as u8;
+        }
+    }
+
+    pub fn is_node_root(&self) -> bool {
+        unsafe { std::ptr::read_unaligned(self.index(IS_ROOT_OFFSET) as *const bool) }
+    }
+
+    pub fn set_node_root(&mut self, is_root: bool) {
+        unsafe {
+            std::ptr::write_unaligned(self.index(IS_ROOT_OFFSET) as *mut bool, is_root);
+        }
+    }
+
+    fn internal_node_right_child(&self) -> isize {
+        self.index(INTERNAL_NODE_RIGHT_CHILD_OFFSET)
+    }
+
+    pub fn set_internal_node_right_child(&mut self, internal_node_right_child: usize) {
+        unsafe {
+            std::ptr::write_unaligned(self.internal_node_right_child() as *mut usize, internal_node_right_child);
+        }
+    }
+
+    pub fn get_internal_node_right_child(&self) -> usize {
+        unsafe {
+            std::ptr::read_unaligned(self.internal_node_right_child() as *const usize)
+        }
+    }
+
+    pub fn set_internal_node_num_keys(&mut self, num_keys: usize) {
+        unsafe {
+            std::ptr::write_unaligned(self.index(INTERNAL_NODE_NUM_KEYS_OFFSET) as *mut usize, num_keys);
+        }
+    }
+
+    pub fn get_internal_node_num_keys(&self) -> usize {
+        unsafe {
+            std::ptr::read_unaligned(self.index(INTERNAL_NODE_NUM_KEYS_OFFSET) as *const usize)
+        }
+    }
+
+    pub fn increase_internal_node_num_keys(&mut self, incr: usize) {
+        let origin_num_keys = self.get_internal_node_num_keys();
+        self.set_internal_node_num_keys(origin_num_keys + incr);
+    }
+
+    pub fn internal_node_cell(&self, cell_num: usize) -> isize {
+        self.index(INTERNAL_NODE_HEADER_SIZE + cell_num * INTERNAL_NODE_CELL_SIZE)
+    }
+
+    fn set_internal_node_cell(&mut self, cell_num: usize, page_num: usize) {
+        unsafe { std::ptr::write_unaligned(self.internal_node_cell(cell_num) as *mut usize, page_num) }
+    }
+
+    fn get_internal_node_cell(&self, cell_num: usize) -> usize {
+        unsafe { std::ptr::read_unaligned(self.internal_node_cell(cell_num) as *const usize) }
+    }
+
+    pub fn set_internal_node_child(&mut self, child_num: usize, child_page_num: usize) {
+        let num_keys = self.get_internal_node_num_keys();
+        if child_num > num_keys {
+            println!("Tried to access child_num {} > num_keys {}", child_num, num_keys);
+            process::exit(0x0010);
+        } else if child_num == num_keys {
+            self.set_internal_node_right_child(child_page_num);
+        } else {
+            self.set_internal_node_cell(child_num, child_page_num);
+        }
+    }
+
+    pub fn get_internal_node_child(&self, child_num: usize) -> usize {
+        let num_keys = self.get_internal_node_num_keys();
+        if child_num > num_keys {
+            println!("Tried to access child_num {}", child_num);
+            process::exit(0x0010);
+        } else if child_num == num_keys {
+            self.get_internal_node_right_child()
+        } else {
+            self.get_internal_node_cell(child_num)
+        }
+    }
+
+    pub fn set_internal_node_key(&mut self, key_num: usize, key_val: u32) {
+        unsafe {
+            std::ptr::write_unaligned((self.internal_node_cell(key_num) + INTERNAL_NODE_CHILD_SIZE as isize) as *mut u32, key_val);
+        }
+    }
+
+    pub fn get_internal_node_key(&self, cell_num: usize) -> u32 {
+        unsafe {
+            std::ptr::read_unaligned((self.internal_node_cell(cell_num) + INTERNAL_NODE_CHILD_SIZE as isize) as *const u32)
+        }
+    }
+
+    pub fn get_node_max_key(&self) -> u32 {
+        match self.get_node_type() {
+            NODE_INTERNAL => self.get_internal_node_key(self.get_internal_node_num_keys() - 1),
+            NODE_LEAF => self.leaf_node_key(self.leaf_node_num_cells() - 1)
+        }
+    }
+
+    pub fn get_leaf_node_next_leaf(&self) -> usize {
+        unsafe {
+            std::ptr::read_unaligned(self.index(LEAF_NODE_NEXT_LEAF_OFFSET) as *const usize)
+        }
+    }
+
+    pub fn set_leaf_node_next_leaf(&self, next_leaf: usize) {
+        unsafe {
+            std::ptr::write_unaligned(self.index(LEAF_NODE_NEXT_LEAF_OFFSET) as *mut usize, next_leaf);
+        }
+    }
+
+    pub fn get_node_parent(&self) -> usize {
+        unsafe {
+            std::ptr::read_unaligned(self.index(PARENT_POINTER_OFFSET) as *const usize)
+        }
+    }
+
+    pub fn set_node_parent(&self, parent_page_num: usize) {
+        unsafe {
+            std::ptr::write_unaligned(self.index(PARENT_POINTER_OFFSET) as *mut usize, parent_page_num);
+        }
+    }
+
+    pub fn update_internal_node_key(&mut self, old_key: u32, new_key: u32) {
+        let old_child_index = self.internal_node_find_child(old_key);
+        self.set_internal_node_key(old_child_index, new_key);
+    }
+
+    /// Return the index of the child which should contain the given key.
+    fn internal_node_find_child(&self, key: u32) -> usize {
+        let num_keys = self.get_internal_node_num_keys();
+        // binary search
+        let (mut min_cell, mut max_cell) = (0, num_keys);
+        while min_cell < max_cell {
+            let cell_num = (max_cell - min_cell) / 2 + min_cell;
+            let cell_key_value = self.get_internal_node_key(cell_num);
+            if cell_key_value >= key {
+                max_cell = cell_num;
+            } else {
+                min_cell = cell_num + 1;
+            }
+        }
+        max_cell
+    }
+
+    fn leaf_node_find(&self, key: u32) -> usize {
+        let num_cells = self.leaf_node_num_cells();
+        let (mut min_index, mut one_past_max_index) = (0, num_cells);
+        while one_past_max_index != min_index {
+            let index = (one_past_max_index + min_index) / 2;
+            let key_at_index = self.leaf_node_key(index);
+            if key_at_index == key {
+                // return
+                return index;
+            } else if key_at_index > key {
+                one_past_max_index = index;
+            } else {
+                min_index = index + 1;
+            }
+        }
+        min_index
+    }
+}
+
+/// Pages as they were before the statement in progress changed them, put back if the statement
+/// fails so that it changes either every row or none.
+struct Journal {
+    num_pages: usize,
+    pages: HashMap<usize, PageSlot>
+}
+
+/// A version of a page, read from the file the first time it is needed by any of the pagers
+/// sharing the slot.
+type PageSlot = Arc<OnceLock<Page>>;
+
+/// A slot for every page a table can have, none of them read yet.
+fn empty_slots() -> Arc<Vec<PageSlot>> {
+    Arc::new(std::iter::repeat_with(PageSlot::default).take(TABLE_MAX_PAGES).collect())
+}
+
+pub struct Pager {
+    file_descriptor: Arc<Mutex<File>>,
+    /// Where the file is, a file written again as a whole being renamed to it.
+    file_name: String,
+    /// The current version of every page. The slots are shared as a whole with the snapshots
+    /// taken since the last change, which copies them once, and each slot with the snapshots and
+    /// the journal that hold the same version, a page being copied to a slot of its own before it
+    /// is changed.
+    pages: Arc<Vec<PageSlot>>,
+    num_pages: usize,
+    page_size: usize,
+    /// Offset of the first page in the file, after the header page. 0 for a file written before
+    /// files had a header.
+    header_size: u64,
+    /// Whether the header is in the file, it is written with the pages of a new file.
+    header_written: bool,
+    /// Whether pages are read through a mapping of the file rather than copied into memory.
+    use_mmap: bool,
+    /// Whether the file was opened read-only, nothing is then written back to it.
+    read_only: bool,
+    /// The last mapping of the file, covering the file when it was made, shared with the
+    /// snapshots. A new one is made when the file grows past it, the pages still pointing into
+    /// the older ones keeping them alive.
+    map: Arc<Mutex<Option<Arc<Mmap>>>>,
+    journal: Option<Journal>,
+    /// Whether the checksum of every page read from the file is checked, turned off by
+    /// `pragma verify_checksums = off` to open files written before pages had checksums.
+    verify_checksums: bool,
+    /// Pages whose checksum did not match, found since they were last dropped. They are held as
+    /// empty leaves until `take_corruption` drops them, and never written back.
+    corrupt_pages: Mutex<BTreeSet<usize>>,
+    /// Set when a page is added to the corrupt pages, so that the machine can look for them
+    /// after every instruction without taking their lock.
+    found_corrupt: AtomicBool,
+    /// Whether the file is written with its pages compressed when it is closed, set by
+    /// `pragma compression`.
+    compression: bool,
+    /// The key the file is written with when it is closed, set by `pragma key` and `pragma
+    /// rekey`, `None` to write its pages as they are.
+    cipher: Option<Arc<PageCipher>>,
+    /// Where every page is in the file when its pages are compressed or encrypted, shared with
+    /// the snapshots.
+    page_map: Option<Arc<Vec<Extent>>>,
+    /// Whether the pages in the file are compressed.
+    compressed: bool,
+    /// The salt and the key check of the file when its pages are encrypted.
+    encryption: Option<Encryption>,
+    /// The key the pages in the file are encrypted with, `None` until `pragma key` gives it.
+    file_cipher: Option<Arc<PageCipher>>,
+    /// Pages changed since the file was last written, appended to a file with a page map when
+    /// it is closed.
+    dirty: BTreeSet<usize>,
+    /// Whether the way pages are stored was changed since the file was last written as a whole,
+    /// which it is then written again.
+    reformat: bool
+}
+
+impl Pager {
+
+    fn new(mut file: File, file_name: &str, options: &ConnectionOptions) -> Result<Self, OpenError> {
+        let header = FileHeader::read(&mut file).map_err(OpenError::Corrupt)?;
+        let file_length = file.metadata()?.len();
+        let Some(num_pages) = header.num_pages(file_length) else {
+            return Err(OpenError::Corrupt(String::from("Db file is not a whole number of pages.")));
+        };
+        Ok(Pager {
+            num_pages,
+            page_size: header.page_size,
+            header_size: header.header_size,
+            header_written: file_length > 0,
+            use_mmap: options.mmap,
+            read_only: options.read_only,
+            map: Arc::new(Mutex::new(None)),
+            file_descriptor: Arc::new(Mutex::new(file)),
+            file_name: String::from(file_name),
+            pages: empty_slots(),
+            journal: None,
+            verify_checksums: true,
+            corrupt_pages: Mutex::new(BTreeSet::new()),
+            found_corrupt: AtomicBool::new(false),
+            compression: header.compressed,
+            cipher: None,
+            page_map: header.page_map.map(|page_map| Arc::new(page_map.extents)),
+            compressed: header.compressed,
+            encryption: header.encryption,
+            file_cipher: None,
+            dirty: BTreeSet::new(),
+            reformat: false
+        })
+    }
+
+    fn get_page_view(&self, page_num: usize) -> Option<&Page> {
+        if page_num >= TABLE_MAX_PAGES {
+            panic!("Tried to fetch page number out of bounds. {} > {}", page_num, TABLE_MAX_PAGES);
+        }
+        let page = self.pages[page_num].get_or_init(|| self.load_page(page_num));
+        if page.corrupt {
+            self.corrupt_pages.lock().unwrap().insert(page_num);
+            self.found_corrupt.store(true, atomic::Ordering::Relaxed);
+        }
+        Some(page)
+    }
+
+    /// A pager reading the table as it is now while this one goes on changing it. The two share
+    /// the file and the versions of the pages they have in common.
+    fn snapshot(&self) -> Pager {
+        Pager {
+            file_descriptor: Arc::clone(&self.file_descriptor),
+            file_name: self.file_name.clone(),
+            pages: Arc::clone(&self.pages),
+            num_pages: self.num_pages,
+            page_size: self.page_size,
+            header_size: self.header_size,
+            header_written: self.header_written,
+            use_mmap: self.use_mmap,
+            read_only: true,
+            map: Arc::clone(&self.map),
+            journal: None,
+            verify_checksums: self.verify_checksums,
+            corrupt_pages: Mutex::new(BTreeSet::new()),
+            found_corrupt: AtomicBool::new(false),
+            compression: self.compression,
+            cipher: self.cipher.clone(),
+            page_map: self.page_map.clone(),
+            compressed: self.compressed,
+            encryption: self.encryption,
+            file_cipher: self.file_cipher.clone(),
+            dirty: BTreeSet::new(),
+            reformat: false
+        }
+    }
+
+    /// Where the page starts in the file.
+    fn page_offset(&self, page_num: usize) -> u64 {
+        self.header_size + page_num as u64 * self.page_size as u64
+    }
+
+    /// The page as a view into the mapping of the file, mapping the file again first if it grew
+    /// past the last mapping. `None` if the file can not be mapped.
+    fn mapped_page(&self, page_num: usize) -> Option<Page> {
+        let offset = self.page_offset(page_num) as usize;
+        let end = offset + self.page_size;
+        let mut last_map = self.map.lock().unwrap();
+        if last_map.as_ref().is_none_or(|map| map.len() < end) {
+            let file = self.file_descriptor.lock().unwrap();
+            match unsafe { Mmap::map(&*file) } {
+                Ok(map) if map.len() >= end => *last_map = Some(Arc::new(map)),
+                _ => return None
+            }
+        }
+        let map = Arc::clone(last_map.as_ref().unwrap());
+        Some(Page { buf: PageBuf::Mapped { map, range: offset..end }, corrupt: false })
+    }
+
+    /// How the pages are stored in the file.
+    fn stored_pages(&self) -> StoredPages<'_> {
+        StoredPages {
+            page_size: self.page_size,
+            header_size: self.header_size,
+            page_map: self.page_map.as_deref().map(Vec::as_slice),
+            compressed: self.compressed,
+            cipher: self.file_cipher.as_deref()
+        }
+    }
+
+    /// Whether the pages in the file are encrypted and their key was not given yet, in which
+    /// case none can be read.
+    fn needs_key(&self) -> bool {
+        self.encryption.is_some() && self.file_cipher.is_none()
+    }
+
+    fn load_page(&self, page_num: usize) -> Page {
+        // create a page in memory
+        let mut new_page = Page::new(self.page_size);
+        if page_num < self.num_pages && !self.needs_key() {
+            // compressed or encrypted pages are decoded rather than viewed in the mapping
+            let mapped = if self.use_mmap && self.page_map.is_none() { self.mapped_page(page_num) } else { None };
+            let stored = match mapped {
+                Some(page) => Some(page),
+                None => {
+                    let mut file = self.file_descriptor.lock().unwrap();
+                    match self.stored_pages().read(&mut file, page_num) {
+                        Ok(page) => page,
+                        Err(e) => {
+                            println!("Error reading file: {}", e);
+                            process::exit(0x0100);
+                        }
+                    }
+                }
+            };
+            match stored {
+                Some(page) if !self.verify_checksums || page.has_valid_checksum() => new_page = page,
+                _ => {
+                    // hand out an empty leaf rather than bytes that can not be trusted
+                    new_page.initialize_leaf_node();
+                    new_page.corrupt = true;
+                }
+            }
+        } else if page_num < self.num_pages {
+            // the page can not be decrypted yet, and a zeroed page would be an internal node
+            // pointing at itself
+            new_page.initialize_leaf_node();
+        }
+        new_page
+    }
+
+    fn get_page(&mut self, page_num: usize) -> &mut Page {
+        if page_num >= TABLE_MAX_PAGES {
+            panic!("Tried to fetch page number out of bounds. {} > {}", page_num, TABLE_MAX_PAGES);
+        }
+        self.get_page_view(page_num);
+        if page_num >= self.num_pages {
+            self.num_pages += 1;
+        }
+        self.dirty.insert(page_num);
+        if let Some(journal) = &mut self.journal {
+            if page_num < journal.num_pages && !journal.pages.contains_key(&page_num) {
+                journal.pages.insert(page_num, Arc::clone(&self.pages[page_num]));
+            }
+        }
+        let page = self.own_page(page_num).unwrap();
+        // a page viewed in the mapping of the file is copied before it is changed
+        page.buf.to_mut();
+        page
+    }
+
+    /// The page, if it was read, copied to a slot of its own first if the journal or a snapshot
+    /// shares its version.
+    fn own_page(&mut self, page_num: usize) -> Option<&mut Page> {
+        let slot = self.slot_mut(page_num);
+        if Arc::get_mut(slot).is_none() {
+            *slot = Arc::new(OnceLock::from(slot.get()?.clone()));
+        }
+        Arc::get_mut(slot).unwrap().get_mut()
+    }
+
+    /// The slot of the page, to be replaced or changed, copying the slots first if a snapshot
+    /// shares them.
+    fn slot_mut(&mut self, page_num: usize) -> &mut PageSlot {
+        &mut Arc::make_mut(&mut self.pages)[page_num]
+    }
+
+    /// Find the leftmost leaf page number.
+    /// This implementation is different from the origin of the tutorial in which the implementation
+    /// of finding the leftmost leaf page by finding the page of the lowest key residing. For example,
+    /// by finding the key 0, and then return the page key 0 should be inserted.
+    pub fn get_leftmost_leaf_page_num(&self, page_num: usize) -> usize {
+        let page = self.get_page_view(page_num);
+        if page.is_none() {
+            panic!("invalid page number {}", page_num);
+        }
+        let p = page.unwrap();
+        if p.is_leaf_node() {
+            return page_num;
+        }
+        let child_page_num = p.get_internal_node_child(0);
+        self.get_leftmost_leaf_page_num(child_page_num)
+    }
+
+    pub fn pager_flush(&mut self, page_num: usize) {
+        if self.is_corrupt(page_num) || self.pages[page_num].get().is_none_or(|page| page.corrupt) {
+            return;
+        }
+        let offset = self.page_offset(page_num);
+        if self.pages[page_num].get().is_some_and(|page| page.buf.is_mapped()) {
+            // still the bytes in the file
+            return;
+        }
+        let file_descriptor = Arc::clone(&self.file_descriptor);
+        if let Some(page) = self.own_page(page_num) {
+            page.update_checksum();
+            let mut file = file_descriptor.lock().unwrap();
+            let result = file.seek(SeekFrom::Start(offset))
+                .and_then(|_| file.write_all(&page.buf));
+            if let Err(e) = result {
+                println!("Error writing file: {}", e);
+                process::exit(0x0100);
+            }
+        }
+    }
+
+    /// Write the header of a new file, before its first page.
+    fn write_header(&mut self) {
+        if self.header_written {
+            return;
+        }
+        let header = FileHeader { header_size: self.header_size, ..FileHeader::plain(self.page_size) };
+        let mut file = self.file_descriptor.lock().unwrap();
+        let result = file.seek(SeekFrom::Start(0))
+            .and_then(|_| file.write_all(&header.to_bytes()));
+        if let Err(e) = result {
+            println!("Error writing file: {}", e);
+            process::exit(0x0100);
+        }
+        drop(file);
+        self.header_written = true;
+    }
+
+    /// The page as it is stored in the file, with its checksum brought up to date, compressed
+    /// when compression is on and encrypted when the database has a key.
+    fn page_image(&self, page_num: usize, page: &Page) -> Vec<u8> {
+        self.encode_page(page_num, page, self.compression)
+    }
+
+    /// The page as it is stored in a file whose pages are compressed or not, encrypted when the
+    /// database has a key.
+    fn encode_page(&self, page_num: usize, page: &Page, compressed: bool) -> Vec<u8> {
+        let mut page = page.clone();
+        page.update_checksum();
+        let image = if compressed {
+            compress::compress_page(page.bytes())
+        } else {
+            page.buf.into_owned()
+        };
+        match &self.cipher {
+            Some(cipher) => cipher.encrypt(page_num, &image),
+            None => image
+        }
+    }
+
+    /// The header of a file whose pages are stored the way they are written now, `None` for
+    /// the page map until the pages are written.
+    fn write_format(&self) -> FileHeader {
+        FileHeader {
+            compressed: self.compression,
+            encryption: self.cipher.as_deref().map(Encryption::new),
+            ..FileHeader::plain(self.page_size)
+        }
+    }
+
+    /// Write the pages of a file whose pages are compressed or encrypted, or that is to become
+    /// so. The file is written again as a whole when the way pages are stored changed or when
+    /// the old images of the pages take more room than the current ones, the changed pages are
+    /// appended to it otherwise. Nothing is written when nothing changed, or when a page is
+    /// corrupt.
+    fn write_pages(&mut self) -> io::Result<()> {
+        if self.reformat || self.page_map.is_none() {
+            return self.rewrite();
+        }
+        if self.dirty.is_empty() {
+            return Ok(());
+        }
+        let end = self.append_pages()?;
+        let live: u64 = self.page_map.iter().flat_map(|extents| extents.iter()).map(|extent| extent.len as u64).sum();
+        if end - self.header_size > 2 * live {
+            self.rewrite()?;
+        }
+        Ok(())
+    }
+
+    /// Write every page again to a new file renamed over the file, stored the way pages are
+    /// written now.
+    fn rewrite(&mut self) -> io::Result<()> {
+        let mut pages = Vec::with_capacity(self.num_pages);
+        for page_num in 0..self.num_pages {
+            let page = self.get_page_view(page_num).unwrap();
+            if page.corrupt {
+                return Err(corrupt_page_error(page_num));
+            }
+            pages.push(page.clone());
+        }
+        let mut pages = pages.into_iter();
+        self.replace_file(self.page_size, self.compression, self.num_pages, |_| Ok(pages.next().unwrap()))
+    }
+
+    /// Append the images of the pages changed since the file was last written after the end of
+    /// the file, then a new page map, and only once they are synced a header pointing to it. A
+    /// crash leaves the header pointing either to the old page map or to the new one, whose
+    /// pages are all in the file. Returns where the file ends.
+    fn append_pages(&mut self) -> io::Result<u64> {
+        let mut images = Vec::with_capacity(self.dirty.len());
+        for &page_num in self.dirty.range(..self.num_pages) {
+            let page = self.get_page_view(page_num).unwrap();
+            if page.corrupt {
+                return Err(corrupt_page_error(page_num));
+            }
+            images.push((page_num, self.page_image(page_num, page)));
+        }
+        let mut extents = self.page_map.as_deref().cloned().unwrap_or_default();
+        extents.truncate(self.num_pages);
+
+        let mut file = self.file_descriptor.lock().unwrap();
+        let mut offset = file.seek(SeekFrom::End(0))?;
+        for (page_num, image) in images {
+            file.write_all(&image)?;
+            let extent = Extent { offset, len: image.len() as u32 };
+            match extents.get_mut(page_num) {
+                Some(old) => *old = extent,
+                // new pages follow the last one
+                None => extents.push(extent)
+            }
+            offset += image.len() as u64;
+        }
+        file.write_all(&compress::page_map_bytes(&extents))?;
+        file.sync_data()?;
+        let page_map = PageMap { offset, extents };
+        let end = offset + (page_map.extents.len() * compress::EXTENT_SIZE) as u64;
+        let header = FileHeader {
+            page_size: self.page_size,
+            header_size: self.header_size,
+            page_map: Some(page_map),
+            compressed: self.compressed,
+            encryption: self.encryption
+        };
+        file.seek(SeekFrom::Start(0))?;
+        file.write_all(&header.to_bytes())?;
+        file.sync_data()?;
+        drop(file);
+        self.page_map = header.page_map.map(|page_map| Arc::new(page_map.extents));
+        self.dirty.clear();
+        Ok(end)
+    }
+
+    /// Write the file again as a whole with `num_pages` pages of `page_size` bytes, got from
+    /// `read_page`, compressed or not and encrypted when the database has a key. The pages go to
+    /// a file next to it which is then renamed over it, so that a crash leaves either the old
+    /// file or the new one, and the snapshots go on reading the old one. Every page is read
+    /// from the new file from then on.
+    fn replace_file(&mut self, page_size: usize, compressed: bool, num_pages: usize,
+                    mut read_page: impl FnMut(usize) -> io::Result<Page>) -> io::Result<()> {
+        let temp_name = format!("{}.tmp", self.file_name);
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&temp_name)?;
+        lock_now(&file, true)?;
+        let mut header = FileHeader {
+            page_size,
+            header_size: page_size as u64,
+            compressed,
+            encryption: self.cipher.as_deref().map(Encryption::new),
+            page_map: None
+        };
+        let mut write = || -> io::Result<()> {
+            file.set_len(0)?;
+            let mut offset = header.header_size;
+            file.seek(SeekFrom::Start(offset))?;
+            let mut extents = Vec::with_capacity(num_pages);
+            for page_num in 0..num_pages {
+                let image = self.encode_page(page_num, &read_page(page_num)?, compressed);
+                file.write_all(&image)?;
+                extents.push(Extent { offset, len: image.len() as u32 });
+                offset += image.len() as u64;
+            }
+            if header.compressed || header.encryption.is_some() {
+                file.write_all(&compress::page_map_bytes(&extents))?;
+                header.page_map = Some(PageMap { offset, extents });
+            }
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(&header.to_bytes())?;
+            file.sync_all()?;
+            fs::rename(&temp_name, &self.file_name)?;
+            sync_dir(&self.file_name)
+        };
+        if let Err(e) = write() {
+            let _ = fs::remove_file(&temp_name);
+            return Err(e);
+        }
+        // the snapshots keep the old file and its mapping
+        self.file_descriptor = Arc::new(Mutex::new(file));
+        self.map = Arc::new(Mutex::new(None));
+        let cipher = self.cipher.clone();
+        self.reload(header, num_pages, cipher);
+        Ok(())
+    }
+
+    /// Give the key of an encrypted file, failing with `EXECUTE_WRONG_KEY` if the passphrase
+    /// is not the one it was encrypted with. The pages of a new or empty file are encrypted with
+    /// the key from then on, a file with rows that is not encrypted fails with
+    /// `EXECUTE_NOT_ENCRYPTED` so that a mistyped passphrase does not become its key.
+    fn set_key(&mut self, passphrase: &str) -> Result<(), ExecuteResult> {
+        if let Some(encryption) = self.encryption.filter(|_| self.file_cipher.is_none()) {
+            let cipher = PageCipher::with_salt(passphrase, encryption.salt);
+            if !cipher.checks(&encryption.key_check) {
+                return Err(ExecuteResult::EXECUTE_WRONG_KEY);
+            }
+            self.file_cipher = Some(Arc::new(cipher));
+            self.cipher = self.file_cipher.clone();
+        } else if let Some(current) = &self.cipher {
+            if !PageCipher::with_salt(passphrase, current.salt()).checks(&current.key_check()) {
+                return Err(ExecuteResult::EXECUTE_WRONG_KEY);
+            }
+        } else {
+            if self.header_written && !self.is_empty() {
+                return Err(ExecuteResult::EXECUTE_NOT_ENCRYPTED);
+            }
+            if self.read_only {
+                return Err(ExecuteResult::EXECUTE_READ_ONLY);
+            }
+            self.cipher = Some(Arc::new(PageCipher::new(passphrase)));
+            self.reformat = true;
+        }
+        Ok(())
+    }
+
+    /// Number of cells a leaf node holds with the page size of the file.
+    fn leaf_node_max_cells(&self) -> usize {
+        leaf_node_max_cells(self.page_size)
+    }
+
+    /// Number of keys an internal node holds with the page size of the file.
+    fn internal_node_max_cells(&self) -> usize {
+        internal_node_max_cells(self.page_size)
+    }
+
+    /// Whether the table has no rows, its root being the only page.
+    fn is_empty(&self) -> bool {
+        let root = self.get_page_view(0).unwrap();
+        self.num_pages <= 1 && root.is_leaf_node() && root.leaf_node_num_cells() == 0
+    }
+
+    /// Change the size of the pages of a file that has not been written yet and whose table is
+    /// empty, starting over with an empty root. Returns whether the size could be changed.
+    fn set_page_size(&mut self, page_size: usize) -> bool {
+        if page_size == self.page_size {
+            return true;
+        }
+        if self.header_written || !self.is_empty() {
+            return false;
+        }
+        self.page_size = page_size;
+        self.header_size = page_size as u64;
+        *self.slot_mut(0) = PageSlot::default();
+        self.num_pages = 0;
+        if let Some(journal) = &mut self.journal {
+            journal.pages.clear();
+        }
+        let root = self.get_page(0);
+        root.initialize_leaf_node();
+        root.set_node_root(true);
+        true
+    }
+
+    /// Forget every page read so far, after the file was replaced by one with `num_pages` pages
+    /// laid out as `header` says, encrypted with `cipher` if they are.
+    fn reload(&mut self, header: FileHeader, num_pages: usize, cipher: Option<Arc<PageCipher>>) {
+        self.pages = empty_slots();
+        self.num_pages = num_pages;
+        self.page_size = header.page_size;
+        self.header_size = header.header_size;
+        self.page_map = header.page_map.map(|page_map| Arc::new(page_map.extents));
+        self.compression = header.compressed;
+        self.compressed = header.compressed;
+        self.encryption = header.encryption;
+        self.file_cipher = cipher;
+        // a database with a key stays encrypted
+        self.reformat = self.file_cipher.is_none() && self.cipher.is_some();
+        self.dirty.clear();
+        self.header_written = true;
+        self.journal = None;
+        self.corrupt_pages.get_mut().unwrap().clear();
+        *self.found_corrupt.get_mut() = false;
+    }
+
+    fn close(&mut self) {
+        if let Err(e) = self.file_descriptor.lock().unwrap().flush() {
+            println!("Error flushing file: {}", e);
+            process::exit(0x0100);
+        }
+    }
+
+    fn get_unused_page_num(&self) -> usize {
+        self.num_pages
+    }
+
+    /// Whether the page failed its checksum when it was read.
+    fn is_corrupt(&self, page_num: usize) -> bool {
+        self.corrupt_pages.lock().unwrap().contains(&page_num)
+    }
+
+    /// The first page whose checksum did not match since the corrupt pages were last dropped.
+    fn first_corrupt_page(&self) -> Option<usize> {
+        if !self.found_corrupt.load(atomic::Ordering::Relaxed) {
+            return None;
+        }
+        self.corrupt_pages.lock().unwrap().first().copied()
+    }
+
+    /// Forget the pages found corrupt so that they are read and checked again the next time
+    /// they are needed. Called once the statement that read them is over and rolled back.
+    fn drop_corrupt_pages(&mut self) {
+        *self.found_corrupt.get_mut() = false;
+        for page_num in std::mem::take(self.corrupt_pages.get_mut().unwrap()) {
+            *self.slot_mut(page_num) = PageSlot::default();
+        }
+    }
+
+    /// Start journaling the pages about to be changed by a statement.
+    fn begin(&mut self) {
+        self.journal = Some(Journal {
+            num_pages: self.num_pages,
+            pages: HashMap::new()
+        });
+    }
+
+    /// Keep the changes made since `begin`.
+    fn commit(&mut self) {
+        self.journal = None;
+    }
+
+    /// Undo the changes made since `begin`, dropping the pages it allocated.
+    fn rollback(&mut self) {
+        if let Some(journal) = self.journal.take() {
+            for (page_num, page) in journal.pages {
+                *self.slot_mut(page_num) = page;
+            }
+            for page_num in journal.num_pages..self.num_pages {
+                *self.slot_mut(page_num) = PageSlot::default();
+            }
+            self.num_pages = journal.num_pages;
+        }
+    }
+}
+
+pub struct Table {
+    root_page_num: usize,
+    pager: Pager
+}
+
+impl Table {
+
+    fn new(pager: Pager) -> Self {
+        Table {
+            pager,
+            root_page_num: 0
+        }
+    }
+
+    /// The table as it is now, unchanged by the statements run on this one from then on. Pages
+    /// are only copied when this table changes them.
+    fn snapshot(&self) -> Table {
+        Table {
+            pager: self.pager.snapshot(),
+            root_page_num: self.root_page_num
+        }
+    }
+
+    /// Find the position of the key in the table from root page to leaf page according.
+    /// The position contains page number and cell number, if the key does not exist in any leaf
+    /// page, then the position the key could be inserted will be returned.
+    pub fn find(&self, key: u32) -> (usize, usize) {
+        let root_page_num = self.root_page_num;
+        let page = self.pager.get_page_view(root_page_num);
+        if page.is_none() {
+            return (0, 0);
+        }
+        self.find_by_page(page.unwrap(), key, root_page_num)
+    }
+
+    fn find_by_page_num(&self, page_num: usize, key: u32) -> (usize, usize) {
+        let page = self.pager.get_page_view(page_num);
+        if page.is_none() {
+            println!("page {} not exist", page_num);
+            process::exit(0x0010);
+        }
+        self.find_by_page(page.unwrap(), key, page_num)
+    }
+
+    /// Find the page number and cell number of the given key, the whole search process starts from
+    /// the root page, which can be an internal node or a leaf node.
+    fn find_by_page(&self, page: &Page, key: u32, page_num: usize) -> (usize, usize) {
+        if *page.get_node_type() == NODE_LEAF {
+            self.leaf_node_find(page, key, page_num)
+        } else {
+            self.internal_node_find(page, key)
+        }
+    }
+
+    pub fn internal_node_find(&self, page: &Page, key: u32) -> (usize, usize) {
+        let cell_index = page.internal_node_find_child(key);
+        if page.get_internal_node_key(cell_index) >= key {
+            let child_page_num = page.get_internal_node_child(cell_index);
+            return self.find_by_page_num(child_page_num, key);
+        }
+        let right_child_num = page.get_internal_node_right_child();
+        self.find_by_page_num(right_child_num, key)
+    }
+
+    /// Binary searches this leaf node(page) for the given key.
+    ///
+    /// If the value is found, then the page number and cell number are returned, if the given key
+    /// is not found, then the position the key could be inserted is returned.
+    fn leaf_node_find(&self, page: &Page, key: u32, page_num: usize) -> (usize, usize) {
+        (page_num, page.leaf_node_find(key))
+    }
+
+    /// Add the node split off a child to the parent, right after that child. The child keeps the
+    /// keys up to `split_key` and the new node takes the ones above, which were bounded by the key
+    /// of the child in the parent.
+    pub fn internal_node_insert(&mut self, parent_page_num: usize, child_page_num: usize, new_page_num: usize, split_key: u32) {
+        // every child of the parent with the key bounding it, the right child has no key of its own
+        let mut children = Vec::new();
+        {
+            let parent = self.pager.get_page_view(parent_page_num).unwrap();
+            let num_keys = parent.get_internal_node_num_keys();
+            for i in 0..num_keys {
+                children.push((parent.get_internal_node_child(i), parent.get_internal_node_key(i)));
+            }
+            children.push((parent.get_internal_node_right_child(), u32::MAX));
+        }
+        let index = children.iter().position(|&(page_num, _)| page_num == child_page_num).unwrap();
+        let key = children[index].1;
+        children[index].1 = split_key;
+        children.insert(index + 1, (new_page_num, key));
+
+        if children.len() - 1 <= self.pager.internal_node_max_cells() {
+            self.write_internal_node(parent_page_num, &children);
+            self.pager.get_page(new_page_num).set_node_parent(parent_page_num);
+        } else {
+            self.internal_node_split(parent_page_num, &children);
+        }
+    }
+
+    /// Split an internal node that has too many children. The node keeps the lower half of them
+    /// and a new node takes the upper half, which is then added to the parent, splitting the
+    /// parent in turn if it is full as well. Splitting the root adds a level to the tree.
+    fn internal_node_split(&mut self, page_num: usize, children: &[(usize, u32)]) {
+        let (left, right) = children.split_at(children.len().div_ceil(2));
+        let left_max = left[left.len() - 1].1;
+        let (is_root, parent_page_num) = {
+            let node = self.pager.get_page_view(page_num).unwrap();
+            (node.is_node_root(), node.get_node_parent())
+        };
+
+        if is_root {
+            // move both halves out of the root, which gets the two of them as its only children
+            let left_page_num = self.pager.get_unused_page_num();
+            self.write_internal_node(left_page_num, left);
+            self.set_children_parent(left, left_page_num);
+            let right_page_num = self.pager.get_unused_page_num();
+            self.write_internal_node(right_page_num, right);
+            self.set_children_parent(right, right_page_num);
+            self.write_internal_node(page_num, &[(left_page_num, left_max), (right_page_num, u32::MAX)]);
+            self.set_children_parent(&[(left_page_num, left_max), (right_page_num, u32::MAX)], page_num);
+        } else {
+            self.write_internal_node(page_num, left);
+            let new_page_num = self.pager.get_unused_page_num();
+            self.write_internal_node(new_page_num, right);
+            self.set_children_parent(right, new_page_num);
+            self.pager.get_page(new_page_num).set_node_parent(parent_page_num);
+            self.internal_node_insert(parent_page_num, page_num, new_page_num, left_max);
+        }
+    }
+
+    /// Overwrite the page with an internal node holding the given children and their keys, the
+    /// last child becoming the right child. The root flag and the parent pointer are kept.
+    fn write_internal_node(&mut self, page_num: usize, children: &[(usize, u32)]) {
+        let num_keys = children.len() - 1;
+        let node = self.pager.get_page(page_num);
+        let (is_root, parent_page_num) = (node.is_node_root(), node.get_node_parent());
+        node.initialize_internal_node();
+        node.set_node_root(is_root);
+        node.set_node_parent(parent_page_num);
+        node.set_internal_node_num_keys(num_keys);
+        for (i, &(child_page_num, key)) in children[..num_keys].iter().enumerate() {
+            node.set_internal_node_child(i, child_page_num);
+            node.set_internal_node_key(i, key);
+        }
+        node.set_internal_node_right_child(children[num_keys].0);
+    }
+
+    fn set_children_parent(&mut self, children: &[(usize, u32)], parent_page_num: usize) {
+        for &(child_page_num, _) in children {
+            self.pager.get_page(child_page_num).set_node_parent(parent_page_num);
+        }
+    }
+
+    /// Number of levels of the tree, 1 while the root is a leaf.
+    pub fn height(&self) -> usize {
+        let mut height = 1;
+        let mut page = self.pager.get_page_view(self.root_page_num).unwrap();
+        while !page.is_leaf_node() {
+            page = self.pager.get_page_view(page.get_internal_node_child(0)).unwrap();
+            height += 1;
+        }
+        height
+    }
+
+    /// Whether the pages left can not take one more row. An insert splits at most one node per
+    /// level plus the root, which takes two new pages.
+    fn is_full(&self) -> bool {
+        self.pager.num_pages + self.height() + 1 > TABLE_MAX_PAGES
+    }
+
+    /// Change the setting named by a pragma if given a value, and return its value. The name and
+    /// the value are ones the code generator accepted.
+    fn pragma(&mut self, name: &str, value: Option<&Value>) -> Result<Value, ExecuteResult> {
+        match name {
+            "page_size" => {
+                if let Some(Value::Integer(page_size)) = value {
+                    if self.pager.read_only {
+                        return Err(ExecuteResult::EXECUTE_READ_ONLY);
+                    }
+                    if !self.pager.set_page_size(*page_size as usize) {
+                        return Err(ExecuteResult::EXECUTE_PAGE_SIZE_FIXED);
+                    }
+                }
+                Ok(Value::Integer(self.pager.page_size as i64))
+            },
+            "compression" => {
+                if let Some(Value::Integer(on)) = value {
+                    if self.pager.read_only {
+                        return Err(ExecuteResult::EXECUTE_READ_ONLY);
+                    }
+                    if self.pager.compression != (*on != 0) {
+                        self.pager.compression = *on != 0;
+                        self.pager.reformat = true;
+                    }
+                }
+                Ok(Value::Integer(self.pager.compression as i64))
+            },
+            "key" => {
+                if let Some(Value::Text(passphrase)) = value {
+                    self.pager.set_key(passphrase)?;
+                }
+                Ok(Value::Null)
+            },
+            "rekey" => {
+                if let Some(Value::Text(passphrase)) = value {
+                    if self.pager.read_only {
+                        return Err(ExecuteResult::EXECUTE_READ_ONLY);
+                    }
+                    self.pager.cipher = (!passphrase.is_empty()).then(|| Arc::new(PageCipher::new(passphrase)));
+                    self.pager.reformat = true;
+                }
+                Ok(Value::Null)
+            },
+            "verify_checksums" => {
+                if let Some(Value::Integer(on)) = value {
+                    self.pager.verify_checksums = *on != 0;
+                }
+                Ok(Value::Integer(self.pager.verify_checksums as i64))
+            },
+            _ => Ok(Value::Null)
+        }
+    }
+
+    /// Write every node from the root down with its page number, how full it is and its
+    /// parent pointer, the keys of each node under it.
+    pub fn print_tree(&self, out: &mut impl Write) -> io::Result<()> {
+        fn describe(page: &Page, page_num: usize, size: usize, max_size: usize) -> String {
+            let parent = if page.is_node_root() {
+                String::from("root")
+            } else {
+                format!("parent {}", page.get_node_parent())
+            };
+            format!("page {}, size {}, {}% full, {}", page_num, size, size * 100 / max_size, parent)
+        }
+        fn print_tree_node(pager: &Pager, page_num: usize, indentation_level: usize, out: &mut impl Write) -> io::Result<()> {
+            if let Some(page) = pager.get_page_view(page_num) {
+                let indent = " ".repeat(indentation_level);
+                match page.get_node_type() {
+                    NodeType::NODE_LEAF => {
+                        let num_keys = page.leaf_node_num_cells();
+                        writeln!(out, "{}- leaf ({})", indent, describe(page, page_num, num_keys, page.leaf_node_max_cells()))?;
+                        for i in 0..num_keys {
+                            writeln!(out, "{} {}", indent, page.leaf_node_key(i))?;
+                        }
+                    },
+                    NodeType::NODE_INTERNAL => {
+                        let num_keys = page.get_internal_node_num_keys();
+                        writeln!(out, "{}- internal ({})", indent, describe(page, page_num, num_keys, page.internal_node_max_cells()))?;
+                        for i in 0..num_keys {
+                            let child = page.get_internal_node_child(i);
+                            print_tree_node(pager, child, indentation_level + 1, out)?;
+                            writeln!(out, "{} - key {}", indent, page.get_internal_node_key(i))?;
+                        }
+                        let child = page.get_internal_node_right_child();
+                        print_tree_node(pager, child, indentation_level + 1, out)?;
+                    }
+                }
+            }
+            Ok(())
+        }
+
+        print_tree_node(&self.pager, self.root_page_num, 0, out)
+    }
+}
+
+/// The table a statement runs on: shared when the statement only reads it, so that several
+/// threads can read it at once, held alone when the statement changes it.
+pub(crate) enum TableRef<'a> {
+    Shared(&'a Table),
+    Exclusive(&'a mut Table)
+}
+
+impl TableRef<'_> {
+
+    /// A shorter borrow of the same table, for a cursor or a nested call.
+    pub(crate) fn reborrow(&mut self) -> TableRef<'_> {
+        match self {
+            TableRef::Shared(table) => TableRef::Shared(table),
+            TableRef::Exclusive(table) => TableRef::Exclusive(table)
+        }
+    }
+}
+
+impl Deref for TableRef<'_> {
+    type Target = Table;
+
+    fn deref(&self) -> &Table {
+        match self {
+            TableRef::Shared(table) => table,
+            TableRef::Exclusive(table) => table
+        }
+    }
+}
+
+impl DerefMut for TableRef<'_> {
+    /// Panics on a shared table: only statements that do not change the table are run on one.
+    fn deref_mut(&mut self) -> &mut Table {
+        match self {
+            TableRef::Shared(_) => panic!("a statement run on a shared table tried to change it"),
+            TableRef::Exclusive(table) => table
+        }
+    }
+}
+
+pub struct Cursor<'a> {
+    table: TableRef<'a>,
+    page_num: usize,
+    cell_num: usize,
+    end_of_table: bool
+}
+
+impl <'a> Cursor<'a> {
+
+    pub(crate) fn table_start(table: TableRef<'a>) -> Self {
+        let root_page_num = table.root_page_num;
+
+        let leaf_page_num = table.pager.get_leftmost_leaf_page_num(root_page_num);
+
+        let mut cursor = Cursor {
+            table,
+            cell_num: 0,
+            page_num: leaf_page_num,
+            end_of_table: false
+        };
+        cursor.skip_to_valid_cell();
+        cursor
+    }
+
+    pub fn get_page(&mut self) -> &mut Page{
+        self.table.pager.get_page(self.page_num)
+    }
+
+    pub fn get_page_view(&self) -> Option<&Page> {
+        self.table.pager.get_page_view(self.page_num)
+    }
+
+    pub fn advance(&mut self) {
+        self.cell_num += 1;
+        self.skip_to_valid_cell();
+    }
+
+    /// Move past the end of the current leaf node to the first cell of the next non-empty leaf,
+    /// leaf nodes can be left empty by deletes. Nothing is done if the cursor points to a cell.
+    pub fn skip_to_valid_cell(&mut self) {
+        loop {
+            let page = self.table.pager.get_page_view(self.page_num).unwrap();
+            if self.cell_num < page.leaf_node_num_cells() {
+                return;
+            }
+            /* Advance to next leaf node */
+            let next_page_num = page.get_leaf_node_next_leaf();
+            if next_page_num == 0 {
+                /* This was rightmost leaf */
+                self.end_of_table = true;
+                return;
+            }
+            self.page_num = next_page_num;
+            self.cell_num = 0;
+        }
+    }
+
+    /// Remove the cell at the cursor position, the cursor then points to the cell that followed
+    /// it. Parent keys are left as they are, they remain upper bounds of their children.
+    pub fn leaf_node_delete(&mut self) {
+        let cell_num = self.cell_num;
+        let page = self.get_page();
+        let num_cells = page.leaf_node_num_cells();
+        for i in cell_num + 1..num_cells {
+            unsafe {
+                std::ptr::copy_nonoverlapping(page.leaf_node_cell(i),
+                                              page.leaf_node_cell(i - 1) as *mut u8,
+                                              LEAF_NODE_CELL_SIZE);
+            }
+        }
+        page.set_leaf_node_num_cells(num_cells - 1);
+        self.skip_to_valid_cell();
+    }
+
+    /// Overwrite the row at the cursor position, which must have the same key.
+    pub fn leaf_node_replace(&mut self, value: &Row) {
+        let cell_num = self.cell_num;
+        let page = self.get_page();
+        unsafe { serialize_row(page.leaf_node_value(cell_num), value) }
+    }
+
+    pub fn cursor_value(&mut self) -> Box<Row> {
+        let cell_num = self.cell_num;
+        let page = self.get_page_view().unwrap();
+        unsafe { page.row_mut_slot(cell_num) }
+    }
+
+    /// Insert the key/value pair at the cursor position, splitting the leaf node if it is full.
+    ///
+    /// # Safety
+    ///
+    /// The cursor must point to a leaf node, and its cell number must not be greater than the
+    /// number of cells in that node.
+    pub unsafe fn leaf_node_insert(&mut self, key: u32, value: &Row) {
+        let cell_num = self.cell_num;
+        let page = self.get_page();
+        let num_cells = page.leaf_node_num_cells();
+        if page.is_full() {
+            self.leaf_node_split_and_insert(value.id, value);
+            return;
+        }
+        if cell_num < num_cells {
+            // shift cell from cell_num to num_cells to right to make room for new cell
+            for i in (cell_num + 1..=num_cells).rev() {
+                std::ptr::copy_nonoverlapping(page.leaf_node_cell(i - 1),
+                                              page.leaf_node_cell(i) as *mut u8,
+                                              LEAF_NODE_CELL_SIZE);
+            }
+        }
+        page.set_leaf_node_num_cells(num_cells + 1);
+        page.set_leaf_node_key(cell_num, key);
+
+        let cell = page.leaf_node_value(cell_num);
+        serialize_row(cell, value);
+    }
+
+    /// Create a new node and move half the cells over.
+    ///
+    /// Insert the new value in one of the two nodes.
+    ///
+    /// Update parent or create a new parent.
+    ///
+    /// The implementation of this method is different from the origin c code which can be found in
+    /// [Part 10 - Splitting a Leaf Node](https://cstack.github.io/db_tutorial/parts/part10.html#splitting-algorithm).
+    /// Because of the reference borrow checker mechanism of Rust，only one mutable reference can be
+    /// borrowed at one time, so the copy page data process should be splitted into two code block.
+    fn leaf_node_split_and_insert(&mut self, key: u32, value: &Row) {
+        // create a new right node
+        let value_cell_num = self.cell_num;
+        // page that will be created
+        let new_page_num = self.table.pager.get_unused_page_num();
+        {
+            let old_node = self.get_page_view().unwrap();
+            let old_next_page_num = old_node.get_leaf_node_next_leaf();
+            let old_node_parent_num = old_node.get_node_parent();
+            let old_node_ptr = old_node as *const Page;
+            // create a new node
+            let new_node = self.table.pager.get_page(new_page_num);
+            // init and copy cells to new right node from old node
+            new_node.initialize_leaf_node();
+            new_node.set_node_parent(old_node_parent_num);
+            new_node.set_leaf_node_next_leaf(old_next_page_num);
+            let page_size = new_node.page_size();
+            let left_split_count = leaf_node_left_split_count(page_size);
+            copy_page_data((left_split_count..leaf_node_max_cells(page_size) + 1).rev(), old_node_ptr, new_node, key, value, value_cell_num);
+            new_node.set_leaf_node_num_cells(leaf_node_right_split_count(page_size));
+        }
+
+        let is_node_root;
+        {
+            // Move cell that still in old node to new position.
+            // for example, the node [1, 3, 5, 7, 9] is full, and cell 2 is being inserted now,
+            // so we should split this node, and [5, 7, 9] is the new node. At the same time,
+            // cell 3 should be moved to the next space, after the, cell 2 can be inserted into
+            // the old node. So the old node is [1, 2, 3] after inserting is finished.
+            let old_node = self.get_page();
+            is_node_root = old_node.is_node_root();
+            let left_split_count = leaf_node_left_split_count(old_node.page_size());
+            copy_page_data((0..left_split_count).rev(), old_node as *const Page, old_node, key, value, value_cell_num);
+            old_node.set_leaf_node_num_cells(left_split_count);
+            old_node.set_leaf_node_next_leaf(new_page_num);
+        }
+
+        if is_node_root {
+            // The old leaf node is the root node, then a new root node should be created.
+            self.create_new_node(new_page_num);
+        } else {
+            let old_node = self.get_page();
+            let parent_page_num = old_node.get_node_parent();
+            let new_max = old_node.get_node_max_key();
+            self.table.internal_node_insert(parent_page_num, self.page_num, new_page_num, new_max);
+        }
+    }
+
+    fn create_new_node(&mut self, right_child_page_num: usize) {
+        // create new root node
+        let left_child_page_num = self.table.pager.get_unused_page_num();
+        let node_max_key;
+        {
+            let old_node = self.get_page_view().unwrap();
+            let old_node_ptr = old_node as *const Page;
+            let left_child = self.table.pager.get_page(left_child_page_num);
+            unsafe {
+                left_child.buf.to_mut().copy_from_slice(&(*old_node_ptr).buf);
+                left_child.set_node_root(false);
+            }
+            node_max_key = left_child.get_node_max_key();
+        }
+
+        let old_node = self.get_page();
+        old_node.initialize_internal_node();
+        old_node.set_node_root(true);
+        old_node.set_internal_node_num_keys(1);
+        old_node.set_internal_node_child(0, left_child_page_num);
+        old_node.set_internal_node_key(0, node_max_key);
+        old_node.set_internal_node_right_child(right_child_page_num);
+
+        let root_page_num = self.table.root_page_num;
+        {
+            let left_child = self.table.pager.get_page(left_child_page_num);
+            left_child.set_node_parent(root_page_num);
+        }
+        {
+            let right_child = self.table.pager.get_page(right_child_page_num);
+            right_child.set_node_parent(root_page_num);
+        }
+    }
+}
+
+unsafe fn serialize_row(cell: *mut u8, source: &Row) {
+    std::ptr::write_unaligned(cell as *mut u32, source.id);
+
+    std::ptr::write((cell as usize + USERNAME_OFFSET) as *mut [u8; USERNAME_SIZE], [0u8; USERNAME_SIZE]);
+    std::ptr::copy(source.username.as_ptr(), (cell as usize + USERNAME_OFFSET) as *mut u8, source.username.len());
+
+    std::ptr::write((cell as usize + EMAIL_OFFSET) as *mut [u8; EMAIL_SIZE], [0u8; EMAIL_SIZE]);
+    std::ptr::copy(source.email.as_ptr(), (cell as usize + EMAIL_OFFSET) as *mut u8, source.email.len());
+}
+
+fn copy_page_data(rang: Rev<Range<usize>>, src_ptr: *const Page, dst_page: &mut Page, key: u32, value: &Row, value_cell_num: usize) {
+    let left_split_count = leaf_node_left_split_count(dst_page.page_size());
+    for i in rang {
+        let index_within_node = i % left_split_count;
+        let destination = dst_page.leaf_node_cell(index_within_node);
+        unsafe {
+            if i == value_cell_num {
+                dst_page.set_leaf_node_key(index_within_node, key);
+                let destination = dst_page.leaf_node_value(index_within_node);
+                serialize_row(destination, value);
+            } else if i > value_cell_num {
+                std::ptr::copy((*src_ptr).leaf_node_cell(i - 1), destination as *mut u8, LEAF_NODE_CELL_SIZE);
+            } else {
+                std::ptr::copy((*src_ptr).leaf_node_cell(i), destination as *mut u8, LEAF_NODE_CELL_SIZE)
+            }
+        }
+    }
+}
+
+const ID_SIZE: usize = std::mem::size_of::<u32>();
+const USERNAME_SIZE: usize = 32;
+const EMAIL_SIZE: usize = 255;
+const ID_OFFSET: usize = 0;
+const USERNAME_OFFSET: usize = ID_OFFSET + ID_SIZE;
+const EMAIL_OFFSET: usize = USERNAME_OFFSET + USERNAME_SIZE;
+const ROW_SIZE: usize = ID_SIZE + USERNAME_SIZE + EMAIL_SIZE;
+/// Size of the pages of a new file unless `pragma page_size` sets another, and of every page of
+/// a file written before files had a header.
+pub const DEFAULT_PAGE_SIZE: usize = 4096;
+/// Page sizes are powers of two between these two.
+pub const MIN_PAGE_SIZE: usize = 512;
+pub const MAX_PAGE_SIZE: usize = 65536;
+const TABLE_MAX_PAGES: usize = 10000;
+
+/// Every page ends with the checksum of the bytes before it.
+const PAGE_CHECKSUM_SIZE: usize = std::mem::size_of::<u32>();
+
+/// File Header Layout, taking up the first page of the file:
+/// MAGIC|PAGE SIZE|FLAGS|PAGE MAP OFFSET|PAGE MAP LENGTH|SALT|KEY CHECK|zeros up to the page size
+const FILE_MAGIC: &[u8; 16] = b"db_tutorial_rust";
+const FILE_HEADER_PAGE_SIZE_OFFSET: usize = FILE_MAGIC.len();
+const FILE_HEADER_PAGE_SIZE_SIZE: usize = std::mem::size_of::<u32>();
+const FILE_HEADER_FLAGS_OFFSET: usize = FILE_HEADER_PAGE_SIZE_OFFSET + FILE_HEADER_PAGE_SIZE_SIZE;
+const FILE_HEADER_FLAGS_SIZE: usize = std::mem::size_of::<u32>();
+const FILE_HEADER_PAGE_MAP_OFFSET: usize = FILE_HEADER_FLAGS_OFFSET + FILE_HEADER_FLAGS_SIZE;
+const FILE_HEADER_PAGE_MAP_OFFSET_SIZE: usize = std::mem::size_of::<u64>();
+const FILE_HEADER_PAGE_MAP_LEN_OFFSET: usize = FILE_HEADER_PAGE_MAP_OFFSET + FILE_HEADER_PAGE_MAP_OFFSET_SIZE;
+const FILE_HEADER_PAGE_MAP_LEN_SIZE: usize = std::mem::size_of::<u32>();
+const FILE_HEADER_SALT_OFFSET: usize = FILE_HEADER_PAGE_MAP_LEN_OFFSET + FILE_HEADER_PAGE_MAP_LEN_SIZE;
+const FILE_HEADER_KEY_CHECK_OFFSET: usize = FILE_HEADER_SALT_OFFSET + cipher::SALT_SIZE;
+const FILE_HEADER_SIZE: usize = FILE_HEADER_KEY_CHECK_OFFSET + cipher::KEY_CHECK_SIZE;
+
+/// Set in the flags of a file whose pages are compressed, or encrypted, each page being stored
+/// where the page map at the end of the file says.
+const FILE_FLAG_COMPRESSED: u32 = 1;
+const FILE_FLAG_ENCRYPTED: u32 = 2;
+
+/// Common Node Header Layout:
+/// NODE TYPE|IS ROOT|PARENT POINTER
+const NODE_TYPE_SIZE: usize = std::mem::size_of::<NodeType>();
+const NODE_TYPE_OFFSET: usize = 0;
+const IS_ROOT_SIZE: usize = std::mem::size_of::<bool>();
+const IS_ROOT_OFFSET: usize = NODE_TYPE_SIZE;
+const PARENT_POINTER_SIZE: usize = std::mem::size_of::<usize>();
+const PARENT_POINTER_OFFSET: usize = IS_ROOT_SIZE + IS_ROOT_OFFSET;
+const COMMON_NODE_HEADER_SIZE: usize = NODE_TYPE_SIZE + IS_ROOT_SIZE + PARENT_POINTER_SIZE;
+
+/// Leaf Node Header Layout:
+/// Common Node Header|Cell num of Leaf Node
+const LEAF_NODE_NUM_CELLS_SIZE: usize = std::mem::size_of::<usize>();
+const LEAF_NODE_NUM_CELLS_OFFSET: usize = COMMON_NODE_HEADER_SIZE;
+const LEAF_NODE_NEXT_LEAF_SIZE: usize = std::mem::size_of::<usize>();
+const LEAF_NODE_NEXT_LEAF_OFFSET: usize = LEAF_NODE_NUM_CELLS_OFFSET + LEAF_NODE_NUM_CELLS_SIZE;
+const LEAF_NODE_HEADER_SIZE: usize = COMMON_NODE_HEADER_SIZE + LEAF_NODE_NUM_CELLS_SIZE + LEAF_NODE_NEXT_LEAF_SIZE;
+
+/// Leaf Node Body Layout:
+/// [Leaf Node Key|Leaf Node Value]
+const LEAF_NODE_KEY_SIZE: usize = std::mem::size_of::<u32>();
+const LEAF_NODE_KEY_OFFSET: usize = 0;
+const LEAF_NODE_VALUE_SIZE: usize = ROW_SIZE;
+const LEAF_NODE_VALUE_OFFSET: usize = LEAF_NODE_KEY_OFFSET + LEAF_NODE_KEY_SIZE;
+const LEAF_NODE_CELL_SIZE: usize = LEAF_NODE_KEY_SIZE + LEAF_NODE_VALUE_SIZE;
+
+const fn leaf_node_space_for_cells(page_size: usize) -> usize {
+    page_size - LEAF_NODE_HEADER_SIZE - PAGE_CHECKSUM_SIZE
+}
+
+const fn leaf_node_max_cells(page_size: usize) -> usize {
+    leaf_node_space_for_cells(page_size) / LEAF_NODE_CELL_SIZE
+}
+
+const fn leaf_node_right_split_count(page_size: usize) -> usize {
+    leaf_node_max_cells(page_size).div_ceil(2)
+}
+
+const fn leaf_node_left_split_count(page_size: usize) -> usize {
+    (leaf_node_max_cells(page_size) + 1) - leaf_node_right_split_count(page_size)
+}
+
+/// Internal Node Header Layout
+const INTERNAL_NODE_NUM_KEYS_SIZE: usize = std::mem::size_of::<usize>();
+const INTERNAL_NODE_NUM_KEYS_OFFSET: usize = COMMON_NODE_HEADER_SIZE;
+const INTERNAL_NODE_RIGHT_CHILD_SIZE: usize = std::mem::size_of::<usize>();
+const INTERNAL_NODE_RIGHT_CHILD_OFFSET: usize = INTERNAL_NODE_NUM_KEYS_OFFSET + INTERNAL_NODE_NUM_KEYS_SIZE;
+const INTERNAL_NODE_HEADER_SIZE: usize = COMMON_NODE_HEADER_SIZE + INTERNAL_NODE_NUM_KEYS_SIZE + INTERNAL_NODE_RIGHT_CHILD_SIZE;
+
+/// Internal Node Body Layout
+const INTERNAL_NODE_KEY_SIZE: usize = std::mem::size_of::<u32>();
+const INTERNAL_NODE_CHILD_SIZE: usize = std::mem::size_of::<usize>();
+const INTERNAL_NODE_CELL_SIZE: usize = INTERNAL_NODE_KEY_SIZE + INTERNAL_NODE_CHILD_SIZE;
+
+const fn internal_node_max_cells(page_size: usize) -> usize {
+    (page_size - INTERNAL_NODE_HEADER_SIZE - PAGE_CHECKSUM_SIZE) / INTERNAL_NODE_CELL_SIZE
+}
+
+/// How the pages of a database file are laid out, as recorded in its header.
+struct FileHeader {
+    page_size: usize,
+    /// Offset of the first page, 0 when the file has no header.
+    header_size: u64,
+    /// Where every page is stored when the pages are compressed or encrypted, `None` when they
+    /// follow the header one after the other.
+    page_map: Option<PageMap>,
+    compressed: bool,
+    encryption: Option<Encryption>
+}
+
+/// The page map of a compressed or encrypted file, stored after the pages.
+struct PageMap {
+    offset: u64,
+    extents: Vec<Extent>
+}
+
+/// What the header of an encrypted file holds to derive its key from the passphrase and tell
+/// whether it is the right one.
+#[derive(Clone, Copy)]
+struct Encryption {
+    salt: [u8; cipher::SALT_SIZE],
+    key_check: [u8; cipher::KEY_CHECK_SIZE]
+}
+
+impl Encryption {
+    fn new(cipher: &PageCipher) -> Encryption {
+        Encryption { salt: cipher.salt(), key_check: cipher.key_check() }
+    }
+}
+
+impl FileHeader {
+
+    /// The header of a file whose pages follow it as they are in memory.
+    fn plain(page_size: usize) -> FileHeader {
+        FileHeader { page_size, header_size: page_size as u64, page_map: None, compressed: false, encryption: None }
+    }
+
+    /// Read the header at the start of the file, and the page map if its pages are compressed
+    /// or encrypted. An empty file gets the default page size, and a file not starting with the
+    /// magic bytes was written before files had a header, with pages of the default size from
+    /// its first byte.
+    fn read(file: &mut File) -> Result<FileHeader, String> {
+        let default = FileHeader::plain(DEFAULT_PAGE_SIZE);
+        let file_len = file.metadata().map_err(|e| e.to_string())?.len();
+        if file_len == 0 {
+            return Ok(default);
+        }
+        let mut bytes = [0; FILE_HEADER_SIZE];
+        file.seek(SeekFrom::Start(0))
+            .and_then(|_| file.read_exact(&mut bytes))
+            .map_err(|e| format!("Error reading file header: {}.", e))?;
+        if &bytes[..FILE_MAGIC.len()] != FILE_MAGIC {
+            return Ok(FileHeader { header_size: 0, ..default });
+        }
+        let field = |offset: usize, size: usize| {
+            let mut value = [0; 8];
+            value[..size].copy_from_slice(&bytes[offset..offset + size]);
+            u64::from_le_bytes(value)
+        };
+        let page_size = field(FILE_HEADER_PAGE_SIZE_OFFSET, FILE_HEADER_PAGE_SIZE_SIZE) as usize;
+        if !is_valid_page_size(page_size) {
+            return Err(format!("Invalid page size {} in file header.", page_size));
+        }
+        let flags = field(FILE_HEADER_FLAGS_OFFSET, FILE_HEADER_FLAGS_SIZE) as u32;
+        if flags & !(FILE_FLAG_COMPRESSED | FILE_FLAG_ENCRYPTED) != 0 {
+            return Err(format!("Unknown flags {:#x} in file header.", flags));
+        }
+        if flags == 0 {
+            return Ok(FileHeader::plain(page_size));
+        }
+        let offset = field(FILE_HEADER_PAGE_MAP_OFFSET, FILE_HEADER_PAGE_MAP_OFFSET_SIZE);
+        let len = field(FILE_HEADER_PAGE_MAP_LEN_OFFSET, FILE_HEADER_PAGE_MAP_LEN_SIZE) as usize;
+        if len > TABLE_MAX_PAGES || offset.saturating_add((len * compress::EXTENT_SIZE) as u64) > file_len {
+            return Err("Invalid page map in file header.".to_string());
+        }
+        let extents = compress::read_page_map(file, offset, len)
+            .map_err(|e| format!("Error reading page map: {}.", e))?;
+        let encryption = (flags & FILE_FLAG_ENCRYPTED != 0).then(|| Encryption {
+            salt: bytes[FILE_HEADER_SALT_OFFSET..FILE_HEADER_KEY_CHECK_OFFSET].try_into().unwrap(),
+            key_check: bytes[FILE_HEADER_KEY_CHECK_OFFSET..FILE_HEADER_SIZE].try_into().unwrap()
+        });
+        Ok(FileHeader {
+            page_map: Some(PageMap { offset, extents }),
+            compressed: flags & FILE_FLAG_COMPRESSED != 0,
+            encryption,
+            ..FileHeader::plain(page_size)
+        })
+    }
+
+    /// The header page, the magic bytes, the page size, where the page map is and the salt of
+    /// the key followed by zeros.
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut bytes = vec![0; self.header_size as usize];
+        let mut set = |offset: usize, size: usize, value: u64| {
+            bytes[offset..offset + size].copy_from_slice(&value.to_le_bytes()[..size]);
+        };
+        set(FILE_HEADER_PAGE_SIZE_OFFSET, FILE_HEADER_PAGE_SIZE_SIZE, self.page_size as u64);
+        if let Some(page_map) = &self.page_map {
+            let mut flags = 0;
+            if self.compressed {
+                flags |= FILE_FLAG_COMPRESSED;
+            }
+            if self.encryption.is_some() {
+                flags |= FILE_FLAG_ENCRYPTED;
+            }
+            set(FILE_HEADER_FLAGS_OFFSET, FILE_HEADER_FLAGS_SIZE, flags as u64);
+            set(FILE_HEADER_PAGE_MAP_OFFSET, FILE_HEADER_PAGE_MAP_OFFSET_SIZE, page_map.offset);
+            set(FILE_HEADER_PAGE_MAP_LEN_OFFSET, FILE_HEADER_PAGE_MAP_LEN_SIZE, page_map.extents.len() as u64);
+        }
+        if let Some(encryption) = &self.encryption {
+            bytes[FILE_HEADER_SALT_OFFSET..FILE_HEADER_KEY_CHECK_OFFSET].copy_from_slice(&encryption.salt);
+            bytes[FILE_HEADER_KEY_CHECK_OFFSET..FILE_HEADER_SIZE].copy_from_slice(&encryption.key_check);
+        }
+        bytes[..FILE_MAGIC.len()].copy_from_slice(FILE_MAGIC);
+        bytes
+    }
+
+    /// Number of pages stored in a file of that length, `None` if it is not a whole number of
+    /// pages.
+    fn num_pages(&self, file_len: u64) -> Option<usize> {
+        match &self.page_map {
+            Some(page_map) => Some(page_map.extents.len()),
+            None => {
+                let pages_len = file_len.saturating_sub(self.header_size);
+                pages_len.is_multiple_of(self.page_size as u64).then_some((pages_len / self.page_size as u64) as usize)
+            }
+        }
+    }
+
+    /// Where the pages of the file end, the page map included.
+    fn pages_end(&self, num_pages: usize) -> u64 {
+        match &self.page_map {
+            Some(page_map) => page_map.offset + (page_map.extents.len() * compress::EXTENT_SIZE) as u64,
+            None => self.header_size + (num_pages * self.page_size) as u64
+        }
+    }
+
+}
+
+/// Where and how the pages are stored in a database file.
+struct StoredPages<'a> {
+    page_size: usize,
+    header_size: u64,
+    page_map: Option<&'a [Extent]>,
+    compressed: bool,
+    /// The key of an encrypted file, `None` for a file whose pages are not encrypted.
+    cipher: Option<&'a PageCipher>
+}
+
+impl StoredPages<'_> {
+
+    /// Read the page from the file, decrypting then decompressing it when it is stored that
+    /// way. `None` if the stored page does not decrypt or decompress.
+    fn read(&self, file: &mut File, page_num: usize) -> io::Result<Option<Page>> {
+        let Some(page_map) = self.page_map else {
+            let mut page = Page::new(self.page_size);
+            file.seek(SeekFrom::Start(self.header_size + (page_num * self.page_size) as u64))?;
+            file.read_exact(page.buf.to_mut())?;
+            return Ok(Some(page));
+        };
+        let Some(extent) = page_map.get(page_num) else {
+            return Ok(None);
+        };
+        let mut image = vec![0; extent.len as usize];
+        file.seek(SeekFrom::Start(extent.offset))?;
+        match file.read_exact(&mut image) {
+            Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            result => result?
+        }
+        if let Some(cipher) = self.cipher {
+            match cipher.decrypt(page_num, &image) {
+                Some(decrypted) => image = decrypted,
+                None => return Ok(None)
+            }
+        }
+        let buf = if self.compressed {
+            compress::decompress_page(&image, self.page_size)
+        } else {
+            Some(image).filter(|image| image.len() == self.page_size)
+        };
+        Ok(buf.map(|buf| Page { buf: PageBuf::Owned(buf), corrupt: false }))
+    }
+}
+
+/// Whether pages can have this size: a power of two from `MIN_PAGE_SIZE` to `MAX_PAGE_SIZE`.
+pub fn is_valid_page_size(page_size: usize) -> bool {
+    page_size.is_power_of_two() && (MIN_PAGE_SIZE..=MAX_PAGE_SIZE).contains(&page_size)
+}
+
+/// Why a file with a corrupt page is not written.
+fn corrupt_page_error(page_num: usize) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("page {} is corrupt, the file is left as it was", page_num))
+}
+
+/// Lock the file against other processes, failing straight away if one holds a conflicting lock.
+fn lock_now(file: &File, exclusive: bool) -> io::Result<()> {
+    lock_file(file, exclusive, None).map_err(|e| match e {
+        OpenError::Locked => io::Error::new(io::ErrorKind::WouldBlock, "database is locked"),
+        OpenError::Corrupt(message) => io::Error::new(io::ErrorKind::InvalidData, message),
+        OpenError::Io(e) => e
+    })
+}
+
+/// Sync the directory of the file, for a file renamed to it to be found there after a crash.
+fn sync_dir(file_name: &str) -> io::Result<()> {
+    if cfg!(unix) {
+        let dir = Path::new(file_name).parent().filter(|dir| !dir.as_os_str().is_empty());
+        File::open(dir.unwrap_or(Path::new(".")))?.sync_all()?;
+    }
+    Ok(())
+}
+
+/// Whether the open file is still the one at the path, which another connection may have
+/// replaced by renaming a new file to it while this one waited for the lock on the old one.
+fn is_file_at(file: &File, file_name: &str) -> io::Result<bool> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        let (open, at_path) = (file.metadata()?, fs::metadata(file_name)?);
+        Ok(open.dev() == at_path.dev() && open.ino() == at_path.ino())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = (file, file_name);
+        Ok(true)
+    }
+}
+
+/// Take an advisory lock on the whole file, shared to read it or exclusive to write it, trying
+/// again until `busy_timeout` has passed while another process holds a conflicting lock.
+fn lock_file(file: &File, exclusive: bool, busy_timeout: Option<Duration>) -> Result<(), OpenError> {
+    let start = Instant::now();
+    loop {
+        let result = if exclusive { file.try_lock() } else { file.try_lock_shared() };
+        match result {
+            Ok(()) => return Ok(()),
+            Err(TryLockError::Error(e)) => return Err(OpenError::Io(e)),
+            Err(TryLockError::WouldBlock) => {
+                let elapsed = start.elapsed();
+                match busy_timeout {
+                    Some(timeout) if elapsed < timeout => thread::sleep((timeout - elapsed).min(Duration::from_millis(10))),
+                    _ => return Err(OpenError::Locked)
+                }
+            }
+        }
+    }
+}
+
+fn pager_open(file_name: &str, options: &ConnectionOptions) -> Result<Pager, OpenError> {
+    // todo return Box<Pager>
+    let file = loop {
+        let file = OpenOptions::new()
+            .write(!options.read_only)
+            .create(!options.read_only)
+            .truncate(false)
+            .read(true)
+            .open(file_name)?;
+        // held until the file is closed, when the connection is dropped
+        lock_file(&file, !options.read_only, options.busy_timeout)?;
+        if is_file_at(&file, file_name)? {
+            break file;
+        }
+    };
+
+    let mut pager = Pager::new(file, file_name, options)?;
+    if pager.num_pages == 0 {
+        let root_node = pager.get_page(0);
+        root_node.initialize_leaf_node();
+        root_node.set_node_root(true);
+    }
+    Ok(pager)
+}
+
+fn db_open(file_name: &str, options: &ConnectionOptions) -> Result<Table, OpenError> {
+    let pager = pager_open(file_name, options)?;
+    Ok(Table::new(pager))
+}
+
+fn db_close(table: &mut Table) -> io::Result<()> {
+    if table.pager.read_only {
+        return Ok(());
+    }
+    if table.pager.compression || table.pager.cipher.is_some() || table.pager.page_map.is_some() {
+        // compressed and encrypted pages change size, they are not written over the old ones
+        table.pager.write_pages()?;
+    } else if table.pager.header_size == 0 && !table.pager.dirty.is_empty() {
+        // a file from before pages had checksums is written again as a whole, with a header,
+        // rather than leaving checksums on the pages that happen to be written back
+        table.pager.rewrite()?;
+    } else {
+        table.pager.write_header();
+        for i in 0..table.pager.num_pages {
+            table.pager.pager_flush(i);
+        }
+        table.pager.dirty.clear();
+    }
+    table.pager.close();
+    Ok(())
+}
+
+/// Prepare `insert [or ...] into ...`, or the original `insert <id> <username> <email>`.
+fn prepare_insert(command: &str) -> Result<Statement, PrepareResult> {
+    let second_word = command.split_whitespace().nth(1);
+    if second_word.is_some_and(|word| word.eq_ignore_ascii_case("into") || word.eq_ignore_ascii_case("or")) {
+        let (insert, parameters) = parse(command, Parser::parse_insert)?;
+        return Ok(Statement::new(StatementType::STATEMENT_INSERT, codegen::compile_insert(&insert)?, parameters));
+    }
+    let splits: Vec<&str> = command.split(" ").collect();
+    if splits.len() < 4 {
+        return Err(PREPARE_SYNTAX_ERROR);
+    }
+    let id: i32 = splits[1].trim().parse().map_err(|_| PREPARE_SYNTAX_ERROR)?;
+    if id < 0 {
+        return Err(PREPARE_NEGATIVE_ID);
+    }
+    let id = id as u32;
+    let username = splits[2].trim();
+    if username.len() > USERNAME_SIZE {
+        return Err(PREPARE_STRING_TOO_LONG);
+    }
+
+    let email = splits[3].trim();
+    if email.len() > EMAIL_SIZE {
+        return Err(PREPARE_STRING_TOO_LONG);
+    }
+    let insert = Insert {
+        table: String::from(schema::USERS.name),
+        source: InsertSource::Values(vec![vec![
+            Expr::Literal(Value::Integer(id as i64)),
+            Expr::Literal(Value::Text(String::from(username))),
+            Expr::Literal(Value::Text(String::from(email)))
+        ]]),
+        on_conflict: OnConflict::Abort,
+        conflict_target: None
+    };
+    Ok(Statement::new(StatementType::STATEMENT_INSERT, codegen::compile_insert(&insert)?, Vec::new()))
+}
+
+/// Parse a whole statement with the given parser method, returning the statement and the names
+/// of the parameters it contains.
+fn parse<T>(command: &str, parse_fn: impl FnOnce(&mut Parser) -> Result<T, String>) -> Result<(T, Vec<Option<String>>), PrepareResult> {
+    let mut parser = Parser::new(command).map_err(|_| PREPARE_SYNTAX_ERROR)?;
+    let statement = parse_fn(&mut parser)
+        .and_then(|statement| parser.finish().map(|_| statement))
+        .map_err(|_| PREPARE_SYNTAX_ERROR)?;
+    Ok((statement, parser.parameters))
+}
+
+fn prepare_select(command: &str) -> Result<Statement, PrepareResult> {
+    let (select, parameters) = parse(command, Parser::parse_select)?;
+    Ok(Statement::new(StatementType::STATEMENT_SELECT, codegen::compile_select(&select)?, parameters))
+}
+
+fn prepare_delete(command: &str) -> Result<Statement, PrepareResult> {
+    let (delete, parameters) = parse(command, Parser::parse_delete)?;
+    Ok(Statement::new(StatementType::STATEMENT_DELETE, codegen::compile_delete(&delete)?, parameters))
+}
+
+fn prepare_pragma(command: &str) -> Result<Statement, PrepareResult> {
+    let (pragma, parameters) = parse(command, Parser::parse_pragma)?;
+    Ok(Statement::new(StatementType::STATEMENT_PRAGMA, codegen::compile_pragma(&pragma)?, parameters))
+}
+
+/// Prepare the statement following `explain` or `explain query plan`.
+fn prepare_explain(command: &str) -> Result<Statement, PrepareResult> {
+    let command = strip_keyword(command, "explain").ok_or(PREPARE_SYNTAX_ERROR)?;
+    let query_plan = strip_keyword(command, "query")
+        .and_then(|rest| strip_keyword(rest, "plan"));
+    let (explain, command) = match query_plan {
+        Some(rest) => (ExplainMode::EXPLAIN_QUERY_PLAN, rest),
+        None => (ExplainMode::EXPLAIN_PROGRAM, command)
+    };
+    if strip_keyword(command, "explain").is_some() {
+        // an explain is not a program that can be explained
+        return Err(PREPARE_SYNTAX_ERROR);
+    }
+    let mut statement = prepare_statement(command)?;
+    statement.explain = explain;
+    Ok(statement)
+}
+
+/// The rest of the command if it starts with the keyword, in any case.
+fn strip_keyword<'a>(command: &'a str, keyword: &str) -> Option<&'a str> {
+    let command = command.trim_start();
+    command.get(..keyword.len())
+        .filter(|word| word.eq_ignore_ascii_case(keyword))
+        .map(|_| command[keyword.len()..].trim_start())
+}
+
+pub fn prepare_statement(command: &str) -> Result<Statement, PrepareResult> {
+    let parser = Parser::new(command).map_err(|_| PREPARE_SYNTAX_ERROR)?;
+    match parser.first_keyword().as_deref() {
+        Some("explain") => prepare_explain(command),
+        Some("insert") => prepare_insert(command),
+        Some("select") => prepare_select(command),
+        Some("delete") => prepare_delete(command),
+        Some("pragma") => prepare_pragma(command),
+        _ => Err(PREPARE_UNRECOGNIZED_STATEMENT)
+    }
+}
+
+/// Print the program or the query plan of a statement under `explain`, returns false if the
+/// statement is to be run instead.
+fn explain_statement(statement: &Statement) -> bool {
+    let program = &statement.program;
+    match statement.explain {
+        ExplainMode::EXPLAIN_PROGRAM => print!("{}", program),
+        ExplainMode::EXPLAIN_QUERY_PLAN => {
+            if !program.plan.is_empty() {
+                println!("QUERY PLAN");
+                for (i, line) in program.plan.iter().enumerate() {
+                    let branch = if i + 1 == program.plan.len() { "`--" } else { "|--" };
+                    println!("{}{}", branch, line);
+                }
+            }
+        },
+        ExplainMode::EXPLAIN_NONE => return false
+    }
+    true
+}
+
+/// Run the compiled statement with its bound parameters, calling `on_row` with every row it
+/// outputs. Under `explain` the program or the query plan is printed instead.
+///
+/// A statement that fails leaves the table as it was before it started, even if it had already
+/// changed some of the rows.
+pub fn execute_statement(statement: &Statement, table: &mut Table, on_row: impl FnMut(&[Value])) -> ExecuteResult {
+    if statement.stmt_type == StatementType::STATEMENT_UNSUPPORTED {
+        return EXECUTE_FAIL;
+    }
+    if explain_statement(statement) {
+        return EXECUTE_SUCCESS;
+    }
+    let writes = matches!(statement.stmt_type, StatementType::STATEMENT_INSERT | StatementType::STATEMENT_DELETE);
+    if writes && table.pager.read_only {
+        return ExecuteResult::EXECUTE_READ_ONLY;
+    }
+    table.pager.begin();
+    let result = Vm::new(TableRef::Exclusive(table)).run(&statement.program, &statement.parameters, on_row);
+    if result == EXECUTE_SUCCESS {
+        table.pager.commit();
+    } else {
+        table.pager.rollback();
+    }
+    table.pager.drop_corrupt_pages();
+    result
+}
+
+/// Run a `select` on a snapshot of the table, which other threads may be reading at the same
+/// time. Nothing is journaled since nothing changes, and the pages found corrupt are dropped with
+/// the snapshot.
+fn execute_read(statement: &Statement, table: &Table, on_row: impl FnMut(&[Value])) -> ExecuteResult {
+    if explain_statement(statement) {
+        return EXECUTE_SUCCESS;
+    }
+    Vm::new(TableRef::Shared(table)).run(&statement.program, &statement.parameters, on_row)
+}
+
+/// Why nothing can be read from an encrypted database before its key is given.
+const NO_KEY_MESSAGE: &str = "the database is encrypted and its key was not given";
+
+/// The first page found corrupt by a read outside of a statement, dropping them all.
+fn take_corruption(table: &mut Table) -> Option<usize> {
+    let page_num = table.pager.first_corrupt_page();
+    table.pager.drop_corrupt_pages();
+    page_num
+}
+
+/// An open database file, the entry point of the library.
+///
+/// Statements are prepared once and can then be executed any number of times, with new values
+/// bound to their parameters in between:
+///
+/// ```no_run
+/// use db_tutorial_rust::Connection;
+///
+/// let connection = Connection::open("users.db").unwrap();
+/// let mut stmt = connection.prepare("select username from users where id = ?").unwrap();
+/// for id in 1..=3i64 {
+///     stmt.bind(1, id);
+///     connection.execute(&stmt, |row| println!("{}", row[0]));
+///     stmt.reset();
+/// }
+/// connection.close().unwrap();
+/// ```
+///
+/// A connection can be shared by several threads. Every `select` reads the table as it was when
+/// it started, so any number of them run at the same time as each other and as the statement
+/// changing the table, the statements that change it running one at a time:
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use std::thread;
+/// use db_tutorial_rust::Connection;
+///
+/// let connection = Arc::new(Connection::open("users.db").unwrap());
+/// let readers: Vec<_> = (0..4).map(|_| {
+///     let connection = Arc::clone(&connection);
+///     thread::spawn(move || {
+///         let stmt = connection.prepare("select username from users").unwrap();
+///         connection.execute(&stmt, |row| println!("{}", row[0]));
+///     })
+/// }).collect();
+/// let stmt = connection.prepare("insert into users values (100, 'alice', 'alice@example.com')").unwrap();
+/// connection.execute(&stmt, |_| {});
+/// readers.into_iter().for_each(|reader| reader.join().unwrap());
+/// connection.close().unwrap();
+/// ```
+pub struct Connection {
+    /// Locked by a statement changing the table for as long as it runs.
+    table: Mutex<Table>,
+    /// A snapshot of the table as the last statement changing it left it, replaced when the next
+    /// one is over. Locked by a `select` only while it takes its own snapshot from it, so that
+    /// it never waits for a statement still changing the table.
+    committed: Mutex<Table>,
+    /// Read-locked by every `select` for as long as it runs on its snapshot, write-locked to
+    /// write the pages to the file, which must not change under the pages the snapshots have yet
+    /// to read or view through the mapping of the file.
+    snapshots: RwLock<()>,
+    /// Held by every backup in progress, the connection refusing to be closed or restored until
+    /// they are all dropped.
+    backups: Arc<()>
+}
+
+/// How a database file is opened.
+#[derive(Clone, Default, Debug)]
+pub struct ConnectionOptions {
+    /// Read pages straight from a memory mapping of the file instead of copying each one into
+    /// memory, only changed pages being copied. The file must not be truncated by another
+    /// process while it is open.
+    pub mmap: bool,
+    /// Open the file without writing to it, statements that would change it failing with
+    /// `EXECUTE_READ_ONLY`. The file must exist. Any number of processes can read a file at the
+    /// same time, but not while one has it open for writing.
+    pub read_only: bool,
+    /// How long to wait for another process to close the file when it is locked, `None` to fail
+    /// with `OpenError::Locked` straight away.
+    pub busy_timeout: Option<Duration>
+}
+
+impl Connection {
+
+    pub fn open(file_name: &str) -> Result<Self, OpenError> {
+        Connection::open_with(file_name, &ConnectionOptions::default())
+    }
+
+    /// Open the file, creating it unless it is opened read-only, and lock it against other
+    /// processes until the connection is dropped.
+    pub fn open_with(file_name: &str, options: &ConnectionOptions) -> Result<Self, OpenError> {
+        let table = db_open(file_name, options)?;
+        Ok(Connection {
+            committed: Mutex::new(table.snapshot()),
+            table: Mutex::new(table),
+            snapshots: RwLock::new(()),
+            backups: Arc::new(())
+        })
+    }
+
+    /// The table as the last statement changing it left it, which later ones do not change.
+    fn snapshot(&self) -> Table {
+        self.committed.lock().unwrap().snapshot()
+    }
+
+    /// Let the statements reading the table see it as it is now, once a statement changing it
+    /// is over.
+    fn publish(&self, table: &Table) {
+        *self.committed.lock().unwrap() = table.snapshot();
+    }
+
+    pub fn prepare(&self, sql: &str) -> Result<Statement, PrepareResult> {
+        prepare_statement(sql)
+    }
+
+    /// Run the statement, calling `on_row` with every row it outputs. A `select` runs on a
+    /// snapshot of the table as the last statement changing it left it, without waiting for the
+    /// one running, any other statement waits for the one running before it.
+    pub fn execute(&self, statement: &Statement, on_row: impl FnMut(&[Value])) -> ExecuteResult {
+        if statement.stmt_type == StatementType::STATEMENT_SELECT {
+            let _snapshot = self.snapshots.read().unwrap();
+            execute_read(statement, &self.snapshot(), on_row)
+        } else {
+            let table = &mut *self.table.lock().unwrap();
+            let result = execute_statement(statement, table, on_row);
+            self.publish(table);
+            result
+        }
+    }
+
+    /// Load rows sorted by id into the empty table, building its tree bottom-up with every node
+    /// filled to `fill_factor` of its capacity. Nothing is loaded if any of the rows is rejected.
+    pub fn bulk_load(&self, rows: impl IntoIterator<Item = [Value; 3]>, fill_factor: f64) -> ExecuteResult {
+        let table = &mut *self.table.lock().unwrap();
+        if table.pager.read_only {
+            return ExecuteResult::EXECUTE_READ_ONLY;
+        }
+        if table.pager.needs_key() {
+            return ExecuteResult::EXECUTE_NO_KEY;
+        }
+        table.pager.begin();
+        let mut result = bulk::bulk_load(table, rows, fill_factor);
+        if let Some(page_num) = table.pager.first_corrupt_page() {
+            result = ExecuteResult::EXECUTE_CORRUPT_PAGE(page_num);
+        }
+        if result == EXECUTE_SUCCESS {
+            table.pager.commit();
+        } else {
+            table.pager.rollback();
+        }
+        table.pager.drop_corrupt_pages();
+        self.publish(table);
+        result
+    }
+
+    /// Names of the tables of the database.
+    pub fn table_names(&self) -> Vec<&'static str> {
+        schema::TABLES.iter().map(|t| t.name).collect()
+    }
+
+    /// The `create table` statement of the table, `None` if there is no such table.
+    pub fn table_schema(&self, table: &str) -> Option<String> {
+        schema::TableSchema::find(table).map(|t| t.to_string())
+    }
+
+    /// Names of the indexes of the table, `None` if there is no such table.
+    pub fn index_names(&self, table: &str) -> Option<Vec<String>> {
+        schema::TableSchema::find(table).map(|t| t.index_names())
+    }
+
+    /// Insert the CSV records read from `input` into the table, passing every rejected record to
+    /// `on_error` with its line number. Every row is inserted by a statement of its own, letting
+    /// the other threads run theirs in between. Returns the number of rows inserted.
+    pub fn import_csv(&self, table: &str, input: impl BufRead, on_error: impl FnMut(usize, CsvError)) -> Result<usize, CsvError> {
+        if self.snapshot().pager.needs_key() {
+            return Err(CsvError::Execute(ExecuteResult::EXECUTE_NO_KEY));
+        }
+        csv::import(table, input, on_error, |statement| self.execute(statement, |_| {}))
+    }
+
+    /// Write the rows of the table to `output` as CSV, after a header naming the columns, from
+    /// a snapshot of the table. Returns the number of rows written.
+    pub fn export_csv(&self, table: &str, output: impl Write) -> Result<usize, CsvError> {
+        let _snapshot = self.snapshots.read().unwrap();
+        csv::export(&mut self.snapshot(), table, output)
+    }
+
+    /// Write the nodes of the B-tree to `out`, failing with `EXECUTE_CORRUPT_PAGE` if one of
+    /// them has a wrong checksum, in which case it is written as an empty leaf.
+    pub fn print_tree(&self, out: &mut impl Write) -> io::Result<ExecuteResult> {
+        let _snapshot = self.snapshots.read().unwrap();
+        let table = &mut self.snapshot();
+        if table.pager.needs_key() {
+            return Ok(ExecuteResult::EXECUTE_NO_KEY);
+        }
+        table.print_tree(out)?;
+        Ok(take_corruption(table).map_or(EXECUTE_SUCCESS, ExecuteResult::EXECUTE_CORRUPT_PAGE))
+    }
+
+    /// Measure the shape of the B-tree and how well its pages are used.
+    pub fn tree_stats(&self) -> Result<TreeStats, ExecuteResult> {
+        let _snapshot = self.snapshots.read().unwrap();
+        let table = &mut self.snapshot();
+        if table.pager.needs_key() {
+            return Err(ExecuteResult::EXECUTE_NO_KEY);
+        }
+        let stats = stats::tree_stats(table);
+        match take_corruption(table) {
+            Some(page_num) => Err(ExecuteResult::EXECUTE_CORRUPT_PAGE(page_num)),
+            None => Ok(stats)
+        }
+    }
+
+    /// Check the invariants of the B-tree, returning one message per violation, each naming
+    /// the page it was found on. Pages with a wrong checksum are reported and not descended.
+    pub fn integrity_check(&self) -> Vec<String> {
+        let _snapshot = self.snapshots.read().unwrap();
+        let table = &mut self.snapshot();
+        if table.pager.needs_key() {
+            return vec![String::from(NO_KEY_MESSAGE)];
+        }
+        let errors = check::integrity_check(table);
+        take_corruption(table);
+        errors
+    }
+
+    /// Size in bytes of the pages of the file.
+    pub fn page_size(&self) -> usize {
+        self.committed.lock().unwrap().pager.page_size
+    }
+
+    /// Start copying the database to the file, as it is now, without holding up the statements
+    /// changing it. The file is replaced by the copy once `Backup::step` has written all of it.
+    /// The connection can not be closed or restored until the backup is dropped.
+    pub fn backup(&self, file_name: &str) -> io::Result<Backup> {
+        let _snapshots = self.snapshots.read().unwrap();
+        let snapshot = self.snapshot();
+        if snapshot.pager.needs_key() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, NO_KEY_MESSAGE));
+        }
+        Backup::new(snapshot, Arc::clone(&self.backups), file_name)
+    }
+
+    /// Fail if a backup is in progress, called with the snapshots write-locked so that none
+    /// starts in the meantime.
+    fn check_no_backup(&self) -> io::Result<()> {
+        if Arc::strong_count(&self.backups) > 1 {
+            return Err(io::Error::new(io::ErrorKind::ResourceBusy, "backup in progress"));
+        }
+        Ok(())
+    }
+
+    /// Copy the database to the file, as it is now, `BACKUP_STEP_PAGES` pages at a time. Returns
+    /// the number of pages copied.
+    pub fn backup_to(&self, file_name: &str) -> io::Result<usize> {
+        let mut backup = self.backup(file_name)?;
+        while !backup.step(backup::BACKUP_STEP_PAGES)? {}
+        Ok(backup.page_count())
+    }
+
+    /// Replace the content of the database with a copy written by `backup_to`, once the running
+    /// statements are over. A copy encrypted with a salt other than the one of the database
+    /// needs its passphrase. Fails if a backup is in progress. Returns the number of pages
+    /// restored.
+    pub fn restore_from(&self, file_name: &str, passphrase: Option<&str>) -> io::Result<usize> {
+        let _snapshots = self.snapshots.write().unwrap();
+        self.check_no_backup()?;
+        let table = &mut *self.table.lock().unwrap();
+        if table.pager.needs_key() {
+            return Err(io::Error::new(io::ErrorKind::PermissionDenied, NO_KEY_MESSAGE));
+        }
+        let result = backup::restore(table, file_name, passphrase);
+        self.publish(table);
+        result
+    }
+
+    /// Write every page back to the database file, once the running statements are over. Fails
+    /// if a backup is in progress.
+    pub fn close(&self) -> io::Result<()> {
+        let _snapshots = self.snapshots.write().unwrap();
+        self.check_no_backup()?;
+        let table = &mut *self.table.lock().unwrap();
+        let result = db_close(table);
+        self.publish(table);
+        result
+    }
+}
+
+pub fn print_constants(page_size: usize) {
+    println!("PAGE_SIZE: {}", page_size);
+    println!("ROW_SIZE: {}", ROW_SIZE);
+    println!("COMMON_NODE_HEADER_SIZE: {}", COMMON_NODE_HEADER_SIZE);
+    println!();
+    println!("LEAF_NODE_HEADER_SIZE: {}", LEAF_NODE_HEADER_SIZE);
+    println!("LEAF_NODE_CELL_SIZE: {}", LEAF_NODE_CELL_SIZE);
+    println!("LEAF_NODE_SPACE_FOR_CELLS: {}", leaf_node_space_for_cells(page_size));
+    println!("LEAF_NODE_MAX_CELLS: {}", leaf_node_max_cells(page_size));
+    println!();
+    println!("INTERNAL_NODE_HEADER_SIZE: {}", INTERNAL_NODE_HEADER_SIZE);
+    println!("INTERNAL_NODE_KEY_SIZE: {}", INTERNAL_NODE_KEY_SIZE);
+    println!("INTERNAL_NODE_CHILD_SIZE: {}", INTERNAL_NODE_CHILD_SIZE);
+    println!("INTERNAL_NODE_CELL_SIZE: {}", INTERNAL_NODE_CELL_SIZE);
+    println!("INTERNAL_NODE_MAX_CELLS: {}", internal_node_max_cells(page_size));
+}
@@ -1,1082 +1,780 @@
-use std::fs::{File, OpenOptions};
-use std::{env, io};
-use std::cell::RefCell;
-use std::io::{Read, Seek, SeekFrom, Write};
-use std::iter::Rev;
-use std::ops::Range;
+#![allow(non_camel_case_types)]
+
+use std::{env, fs, io};
+use std::io::{BufRead, BufReader, BufWriter, IsTerminal, Write};
+use std::cell::Cell;
+use std::path::PathBuf;
 use std::process;
-use crate::ExecuteResult::{EXECUTE_DUPLICATE_KEY, EXECUTE_FAIL, EXECUTE_SUCCESS, EXECUTE_TABLE_FULL};
-use crate::NodeType::{NODE_INTERNAL, NODE_LEAF};
-use crate::PrepareResult::{PREPARE_NEGATIVE_ID, PREPARE_STRING_TOO_LONG, PREPARE_SUCCESS, PREPARE_SYNTAX_ERROR, PREPARE_UNRECOGNIZED_STATEMENT};
+use std::time::Duration;
+use rustyline::completion::Completer;
+use rustyline::config::{CompletionType, Config};
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::history::FileHistory;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+use db_tutorial_rust::{formatter, print_constants, Connection, ConnectionOptions, CsvError, ExecuteResult, OpenError, OutputMode, OutputOptions, PrepareResult, Value, DEFAULT_FILL_FACTOR, KEYWORDS};
+use db_tutorial_rust::ExecuteResult::{EXECUTE_CORRUPT_PAGE, EXECUTE_DUPLICATE_KEY, EXECUTE_ID_OUT_OF_RANGE, EXECUTE_INTEGER_OVERFLOW, EXECUTE_NOT_ENCRYPTED, EXECUTE_NOT_NULL, EXECUTE_NO_KEY, EXECUTE_PAGE_SIZE_FIXED, EXECUTE_READ_ONLY, EXECUTE_STRING_TOO_LONG, EXECUTE_SUCCESS, EXECUTE_TABLE_FULL, EXECUTE_TABLE_NOT_EMPTY, EXECUTE_TYPE_MISMATCH, EXECUTE_UNSORTED, EXECUTE_WRONG_KEY};
+use db_tutorial_rust::PrepareResult::{PREPARE_INVALID_CONFLICT_TARGET, PREPARE_INVALID_PRAGMA_VALUE, PREPARE_NEGATIVE_ID, PREPARE_PRIMARY_KEY_UPDATE, PREPARE_STRING_TOO_LONG, PREPARE_SYNTAX_ERROR, PREPARE_TYPE_MISMATCH, PREPARE_UNKNOWN_COLUMN, PREPARE_UNKNOWN_FUNCTION, PREPARE_UNKNOWN_PRAGMA, PREPARE_UNKNOWN_TABLE, PREPARE_UNRECOGNIZED_STATEMENT, PREPARE_VALUE_COUNT_MISMATCH};
 
 #[derive(PartialEq)]
-pub enum MetaCommandResult {
+enum MetaCommandResult {
     META_COMMAND_SUCCESS,
+    META_COMMAND_FAILED,
     META_COMMAND_UNRECOGNIZED_COMMAND
 }
 
-#[derive(PartialEq)]
-pub enum PrepareResult {
-    PREPARE_SUCCESS,
-    PREPARE_UNRECOGNIZED_STATEMENT,
-    PREPARE_SYNTAX_ERROR,
-    PREPARE_STRING_TOO_LONG,
-    PREPARE_NEGATIVE_ID
-}
-
-#[derive(PartialEq)]
-pub enum ExecuteResult {
-    EXECUTE_SUCCESS,
-    EXECUTE_FAIL,
-    EXECUTE_TABLE_FULL,
-    EXECUTE_DUPLICATE_KEY
-}
-
-#[derive(PartialEq)]
-pub enum StatementType {
-    STATEMENT_INSERT,
-    STATEMENT_SELECT,
-    STATEMENT_UNSUPPORTED
-}
-
-#[derive(PartialEq)]
-pub enum NodeType {
-    NODE_INTERNAL,
-    NODE_LEAF
-}
-
-pub struct Statement {
-    stmt_type: StatementType,
-    row_to_insert: Option<Row>
-}
-
-#[derive(Clone)]
-pub struct Row {
-    id: u32,
-    username: String,
-    email: String
+/// State of the shell carried from one command to the next.
+struct Shell {
+    connection: Connection,
+    options: OutputOptions,
+    /// Whether a command has failed, in which case the process exits with a failure status.
+    failed: bool
 }
 
-pub struct Page {
-    buf: [u8; PAGE_SIZE]
+/// Every meta-command with its arguments and what it does, listed by `.help` and offered by
+/// completion.
+const META_COMMANDS: [(&str, &str, &str); 19] = [
+    (".backup", "FILE", "Copy the database to a file"),
+    (".btree", "", "Print the pages of the B-tree"),
+    (".check", "", "Check the B-tree for broken invariants"),
+    (".constants", "", "Print the sizes of the page layout"),
+    (".dump", "[TABLE]", "Print insert statements recreating the rows"),
+    (".exit", "", "Write the database and exit"),
+    (".export", "TABLE FILE", "Write the rows of the table to a CSV file"),
+    (".headers", "on|off", "Print column names before list and CSV output"),
+    (".help", "", "Print this list"),
+    (".import", "FILE TABLE", "Insert the rows of a CSV file into the table"),
+    (".indexes", "[TABLE]", "List the indexes of the table, or of every table"),
+    (".load", "FILE [FILL_PERCENT]", "Bulk load sorted `id username email` lines into an empty table"),
+    (".mode", "MODE", "Set the output mode: table, csv, json, line, markdown or list"),
+    (".nullvalue", "STRING", "Print STRING in place of NULL values"),
+    (".read", "FILE", "Run the commands of a file"),
+    (".restore", "FILE [KEY_FILE]", "Replace the database with a copy made by .backup"),
+    (".schema", "[TABLE]", "Print the create statement of the table, or of every table"),
+    (".stats", "", "Print the height, page counts and fill of the B-tree"),
+    (".tables", "", "List the tables"),
+];
+
+/// Completes meta-commands at the start of a line, and keywords and table names elsewhere.
+struct ShellHelper {
+    tables: Vec<&'static str>
 }
 
-impl Page {
-
-    fn new() -> Self {
-        Page {
-            buf: [0; PAGE_SIZE]
-        }
-    }
-
-    unsafe fn row_mut_slot(&self, cell_num: usize) -> Box<Row> {
-        fn read_end_idx(bytes: &[u8]) -> usize {
-            for i in (0..bytes.len()).rev() {
-                if bytes[i] != 0 {
-                    return i;
-                }
-            }
-            0
-        }
-        let cell = self.leaf_node_value(cell_num);
-
-        let id = std::ptr::read(cell as *const u32);
-        let username_bytes = std::ptr::read((cell as usize + USERNAME_OFFSET) as *const [u8; USERNAME_SIZE]);
-        let email_bytes = std::ptr::read((cell as usize + EMAIL_OFFSET) as *const [u8; EMAIL_SIZE]);
-
-        Box::new(Row {
-            id,
-            username: String::from_utf8_unchecked(Vec::from(&username_bytes[0..=read_end_idx(&username_bytes)])),
-            email: String::from_utf8_unchecked(Vec::from(&email_bytes[0..=read_end_idx(&email_bytes)]))
-        })
-    }
-
-    fn load(&mut self, bytes: &[u8]) {
-        let mut idx = 0;
-        while idx + ROW_SIZE <= bytes.len() {
-            let mut reader = std::io::Cursor::new(&bytes[idx..idx + ROW_SIZE]);
-            let mut id_bytes = [0; ID_SIZE];
-            reader.read_exact(&mut id_bytes);
-            let mut username_bytes = [0; USERNAME_SIZE];
-            reader.read_exact(&mut username_bytes);
-            let mut email_bytes = [0; EMAIL_SIZE];
-            reader.read_exact(&mut email_bytes);
-            idx += ROW_SIZE;
-        }
-    }
-
-    unsafe fn leaf_node_mut_num_cells(&self) -> *mut usize {
-        self.index(LEAF_NODE_NUM_CELLS_OFFSET) as *mut usize
-    }
-
-    fn leaf_node_num_cells(&self) -> usize {
-        unsafe {*self.leaf_node_mut_num_cells()}
-    }
-
-    fn set_leaf_node_num_cells(&mut self, num_cells: usize) {
-        unsafe {
-            *self.leaf_node_mut_num_cells() = num_cells
-        }
-    }
-
-    fn index(&self, offset: usize) -> isize {
-        let ptr = self.buf.as_ptr();
-        unsafe {
-            (ptr as isize).checked_add(offset as isize).unwrap()
-        }
-    }
-
-    fn leaf_node_cell(&self, cell_num: usize) -> *const u8 {
-        (self.index(LEAF_NODE_HEADER_SIZE + cell_num * LEAF_NODE_CELL_SIZE)) as *const u8
-    }
-
-    fn leaf_node_key(&self, cell_num: usize) -> u32 {
-        unsafe { *(self.leaf_node_cell(cell_num) as *mut u32) }
-    }
-
-    fn set_leaf_node_key(&self, cell_num: usize, key: u32) {
-        unsafe { *(self.leaf_node_cell(cell_num) as *mut u32) = key }
-    }
-
-    fn leaf_node_value(&self, cell_num: usize) -> *mut u8 {
-        self.index(LEAF_NODE_HEADER_SIZE + cell_num * LEAF_NODE_CELL_SIZE + LEAF_NODE_KEY_SIZE) as *mut u8
-    }
-
-    fn initialize_leaf_node(&mut self) {
-        self.set_node_type(NODE_LEAF);
-        self.set_node_root(false);
-        self.set_leaf_node_next_leaf(0);
-        let ptr = self.index(LEAF_NODE_NUM_CELLS_OFFSET) as *mut usize;
-        unsafe {
-            *ptr = 0;
-        }
-    }
-
-    fn initialize_internal_node(&mut self) {
-        self.set_node_type(NODE_INTERNAL);
-        self.set_node_root(false);
-        let ptr = self.index(INTERNAL_NODE_NUM_KEYS_OFFSET) as *mut usize;
-        unsafe {
-            *ptr = 0;
-        }
-    }
-
-    fn is_full(&self) -> bool {
-        self.leaf_node_num_cells() >= LEAF_NODE_MAX_CELLS
-    }
-
-    fn is_leaf_node(&self) -> bool {
-        *(self.get_node_type()) == NodeType::NODE_LEAF
-    }
-
-    fn get_node_type<'a>(&self) -> &'a NodeType {
-        unsafe { &*(self.index(NODE_TYPE_OFFSET) as *const NodeType) }
-    }
-
-    fn set_node_type(&mut self, node_type: NodeType) {
-        let ptr = self.index(NODE_TYPE_OFFSET) as *mut u8;
-        unsafe {
-            *ptr = node_type as u8;
-        }
-    }
-
-    pub fn is_node_root(&self) -> bool {
-        unsafe { *(self.index(IS_ROOT_OFFSET) as *const bool) }
-    }
-
-    pub fn set_node_root(&mut self, is_root: bool) {
-        unsafe {
-            *(self.index(IS_ROOT_OFFSET) as *mut bool) = is_root;
-        }
-    }
-
-    fn internal_node_right_child(&self) -> isize {
-        self.index(INTERNAL_NODE_RIGHT_CHILD_OFFSET)
-    }
-
-    pub fn set_internal_node_right_child(&mut self, internal_node_right_child: usize) {
-        unsafe {
-            *(self.internal_node_right_child() as *mut usize) = internal_node_right_child;
-        }
-    }
-
-    pub fn get_internal_node_right_child(&self) -> usize {
-        unsafe {
-            *(self.internal_node_right_child() as *mut usize)
-        }
-    }
-
-    pub fn set_internal_node_num_keys(&mut self, num_keys: usize) {
-        unsafe {
-            *(self.index(INTERNAL_NODE_NUM_KEYS_OFFSET) as *mut usize) = num_keys;
-        }
-    }
-
-    pub fn get_internal_node_num_keys(&self) -> usize {
-        unsafe {
-            *(self.index(INTERNAL_NODE_NUM_KEYS_OFFSET) as *const usize)
-        }
-    }
-
-    pub fn increase_internal_node_num_keys(&mut self, incr: usize) {
-        let origin_num_keys = self.get_internal_node_num_keys();
-        self.set_internal_node_num_keys(origin_num_keys + incr);
-    }
-
-    pub fn internal_node_cell(&self, cell_num: usize) -> isize {
-        self.index(INTERNAL_NODE_HEADER_SIZE + cell_num * INTERNAL_NODE_CELL_SIZE)
-    }
-
-    fn set_internal_node_cell(&mut self, cell_num: usize, page_num: usize) {
-        unsafe { *(self.internal_node_cell(cell_num) as *mut usize) = page_num }
-    }
-
-    fn get_internal_node_cell(&self, cell_num: usize) -> usize {
-        unsafe { *(self.internal_node_cell(cell_num) as *const usize) }
-    }
-
-    pub fn set_internal_node_child(&mut self, child_num: usize, child_page_num: usize) {
-        let num_keys = self.get_internal_node_num_keys();
-        if child_num > num_keys {
-            println!("Tried to access child_num {} > num_keys {}", child_num, num_keys);
-            process::exit(0x0010);
-        } else if child_num == num_keys {
-            self.set_internal_node_right_child(child_page_num);
-        } else {
-            self.set_internal_node_cell(child_num, child_page_num);
-        }
-    }
+impl Completer for ShellHelper {
+    type Candidate = String;
 
-    pub fn get_internal_node_child(&self, child_num: usize) -> usize {
-        let num_keys = self.get_internal_node_num_keys();
-        if child_num > num_keys {
-            println!("Tried to access child_num {}", child_num);
-            process::exit(0x0010);
-        } else if child_num == num_keys {
-            self.get_internal_node_right_child()
+    fn complete(&self, line: &str, pos: usize, _: &Context<'_>) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(|c: char| !(c.is_alphanumeric() || c == '_' || c == '.'))
+            .map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+        let candidates: Vec<&str> = if start == 0 && word.starts_with('.') {
+            META_COMMANDS.iter().map(|&(name, _, _)| name).collect()
         } else {
-            self.get_internal_node_cell(child_num)
-        }
-    }
-
-    pub fn set_internal_node_key(&mut self, key_num: usize, key_val: u32) {
-        unsafe {
-            *((self.internal_node_cell(key_num) + INTERNAL_NODE_CHILD_SIZE as isize) as *mut u32) = key_val;
-        }
+            KEYWORDS.iter().chain(&self.tables).copied().collect()
+        };
+        let mut matches: Vec<String> = candidates.into_iter()
+            .filter(|c| c.len() >= word.len() && c[..word.len()].eq_ignore_ascii_case(word))
+            .map(String::from)
+            .collect();
+        matches.sort();
+        Ok((start, matches))
     }
+}
 
-    fn get_internal_node_key(&self, cell_num: usize) -> u32 {
-        unsafe {
-            *((self.internal_node_cell(cell_num) + INTERNAL_NODE_CHILD_SIZE as isize) as *const u32)
-        }
-    }
+impl Hinter for ShellHelper {
+    type Hint = String;
+}
 
-    pub fn get_node_max_key(&self) -> u32 {
-        match self.get_node_type() {
-            NODE_INTERNAL => self.get_internal_node_key(self.get_internal_node_num_keys() - 1),
-            NODE_LEAF => self.leaf_node_key(self.leaf_node_num_cells() - 1)
-        }
-    }
+impl Highlighter for ShellHelper {}
 
-    pub fn get_leaf_node_next_leaf(&self) -> usize {
-        unsafe {
-            *(self.index(LEAF_NODE_NEXT_LEAF_OFFSET) as *const usize)
-        }
-    }
+impl Validator for ShellHelper {}
 
-    pub fn set_leaf_node_next_leaf(&self, next_leaf: usize) {
-        unsafe {
-            *(self.index(LEAF_NODE_NEXT_LEAF_OFFSET) as *mut usize) = next_leaf;
-        }
-    }
+impl Helper for ShellHelper {}
 
-    pub fn get_node_parent(&self) -> usize {
-        unsafe {
-            *(self.index(PARENT_POINTER_OFFSET) as *const usize)
+/// Whether the statement ends with a semicolon that is not inside a string.
+fn is_complete_statement(statement: &str) -> bool {
+    let mut quoted = false;
+    let mut complete = false;
+    for c in statement.chars() {
+        if c == '\'' {
+            quoted = !quoted;
         }
-    }
-
-    pub fn set_node_parent(&self, parent_page_num: usize) {
-        unsafe {
-            *(self.index(PARENT_POINTER_OFFSET) as *mut usize) = parent_page_num;
+        if !c.is_whitespace() {
+            complete = !quoted && c == ';';
         }
     }
+    complete
+}
 
-    pub fn update_internal_node_key(&mut self, old_key: u32, new_key: u32) {
-        let old_child_index = self.internal_node_find_child(old_key);
-        self.set_internal_node_key(old_child_index, new_key);
-    }
-
-    /// Return the index of the child which should contain the given key.
-    fn internal_node_find_child(&self, key: u32) -> usize {
-        let num_keys = self.get_internal_node_num_keys();
-        // binary search
-        let (mut min_cell, mut max_cell) = (0, num_keys);
-        while min_cell < max_cell {
-            let cell_num = (max_cell - min_cell) / 2 + min_cell;
-            let cell_key_value = self.get_internal_node_key(cell_num);
-            if cell_key_value >= key {
-                max_cell = cell_num;
-            } else {
-                min_cell = cell_num + 1;
-            }
-        }
-        max_cell
-    }
-
-    fn leaf_node_find(&self, key: u32) -> usize {
-        let num_cells = self.leaf_node_num_cells();
-        let (mut min_index, mut one_past_max_index) = (0, num_cells);
-        while one_past_max_index != min_index {
-            let index = (one_past_max_index + min_index) / 2;
-            let key_at_index = self.leaf_node_key(index);
-            if key_at_index == key {
-                // return
-                return index;
-            } else if key_at_index > key {
-                one_past_max_index = index;
-            } else {
-                min_index = index + 1;
-            }
-        }
-        min_index
+/// Whether the line is a statement of the tutorial, `insert ID USERNAME EMAIL` or a bare
+/// `select`, which take no semicolon.
+fn is_tutorial_statement(line: &str) -> bool {
+    let words: Vec<String> = line.split_whitespace().map(str::to_ascii_lowercase).collect();
+    match words.as_slice() {
+        [select] => select == "select",
+        [insert, second, ..] => insert == "insert" && second != "into" && second != "or",
+        _ => false
     }
 }
 
-pub struct Pager {
-    file_descriptor: RefCell<File>,
-    pages: Vec<Option<Box<Page>>>,
-    num_pages: usize
+/// Add a line to the command being read, returning the command once it is complete. A
+/// meta-command or a statement of the tutorial takes a single line, other statements go on
+/// until one ends with a semicolon.
+fn add_line(command: &mut String, line: &str) -> Option<String> {
+    if command.is_empty() && line.trim().is_empty() {
+        return None;
+    }
+    let first_line = command.is_empty();
+    if !first_line {
+        command.push('\n');
+    }
+    command.push_str(line);
+    let complete = command.trim_start().starts_with('.')
+        || (first_line && is_tutorial_statement(line))
+        || is_complete_statement(command);
+    complete.then(|| std::mem::take(command))
 }
 
-impl Pager {
-
-    fn new(file: File) -> Self {
-        fn num_pages_file(file_length: u64) -> usize {
-            let mut num_page = file_length / PAGE_SIZE as u64;
-            if file_length % PAGE_SIZE as u64 != 0 {
-                println!("Db file is not a whole number of pages. Corrupt file.");
-                process::exit(0x0100);
+fn main() {
+    /// Print the prompt for the first line of a command, or for a line continuing it.
+    fn print_prompt(continued: bool) {
+        print!("{}", if continued { "   ...> " } else { "db > " });
+        io::stdout().flush().expect("Failed to write prompt");
+    }
+
+    /// Write every page back and exit, with a failure status if any command failed.
+    fn exit(shell: &mut Shell) -> ! {
+        if let Err(e) = shell.connection.close() {
+            println!("Error: can not close the database: {}", e);
+            process::exit(1);
+        }
+        process::exit(if shell.failed { 1 } else { 0 });
+    }
+
+    fn print_prepare_error(result: PrepareResult, command: &str) {
+        match result {
+            PREPARE_UNRECOGNIZED_STATEMENT =>
+                println!("Unrecognized keyword at start of {}.", command),
+            PREPARE_SYNTAX_ERROR =>
+                println!("Syntax error. Could not parse statement."),
+            PREPARE_STRING_TOO_LONG =>
+                println!("String is too long."),
+            PREPARE_NEGATIVE_ID =>
+                println!("ID must be positive."),
+            PREPARE_UNKNOWN_TABLE(name) =>
+                println!("No such table: {}.", name),
+            PREPARE_UNKNOWN_COLUMN(name) =>
+                println!("No such column: {}.", name),
+            PREPARE_UNKNOWN_FUNCTION(name) =>
+                println!("No such function: {}.", name),
+            PREPARE_TYPE_MISMATCH(expr) =>
+                println!("Type mismatch in {}.", expr),
+            PREPARE_VALUE_COUNT_MISMATCH(columns, values) =>
+                println!("Table has {} columns but {} values were supplied.", columns, values),
+            PREPARE_INVALID_CONFLICT_TARGET(column) =>
+                println!("Conflict target {} is not the primary key.", column),
+            PREPARE_PRIMARY_KEY_UPDATE(column) =>
+                println!("Can not update primary key {}.", column),
+            PREPARE_UNKNOWN_PRAGMA(name) =>
+                println!("No such pragma: {}.", name),
+            PREPARE_INVALID_PRAGMA_VALUE(name) =>
+                println!("Invalid value for pragma {}.", name),
+            _ => {},
+        }
+    }
+
+    fn print_execute_error(result: ExecuteResult) {
+        match result {
+            EXECUTE_DUPLICATE_KEY => println!("Error: Duplicate key."),
+            EXECUTE_TABLE_FULL => println!("Error: Table full."),
+            EXECUTE_INTEGER_OVERFLOW => println!("Error: Integer overflow."),
+            EXECUTE_ID_OUT_OF_RANGE => println!("Error: ID out of range."),
+            EXECUTE_STRING_TOO_LONG => println!("Error: String is too long."),
+            EXECUTE_NOT_NULL(column) => println!("Error: Column {} can not be null.", column),
+            EXECUTE_TYPE_MISMATCH(column) => println!("Error: Type mismatch in column {}.", column),
+            EXECUTE_UNSORTED => println!("Error: Rows are not sorted by id."),
+            EXECUTE_TABLE_NOT_EMPTY => println!("Error: Table is not empty."),
+            EXECUTE_PAGE_SIZE_FIXED => println!("Error: The page size can only be changed before anything is written."),
+            EXECUTE_CORRUPT_PAGE(page_num) => println!("Error: Page {} is corrupt, its checksum does not match.", page_num),
+            EXECUTE_READ_ONLY => println!("Error: The database is open read-only."),
+            EXECUTE_NO_KEY => println!("Error: The database is encrypted, give its key with pragma key first."),
+            EXECUTE_WRONG_KEY => println!("Error: Wrong key, the database can not be decrypted."),
+            EXECUTE_NOT_ENCRYPTED => println!("Error: The database is not encrypted, use pragma rekey to encrypt it."),
+            _ => println!("Error: execute failed")
+        }
+    }
+
+    /// `.load FILE [FILL_PERCENT]` bulk loads a file of `id username email` lines sorted by id.
+    fn load_file(args: &[&str], connection: &mut Connection) -> bool {
+        let (file_name, fill_factor) = match args {
+            [file_name] => (*file_name, DEFAULT_FILL_FACTOR),
+            [file_name, percent] => match percent.parse::<u32>() {
+                Ok(percent) if (1..=100).contains(&percent) => (*file_name, percent as f64 / 100.0),
+                _ => {
+                    println!("Fill percent must be between 1 and 100.");
+                    return false;
+                }
+            },
+            _ => {
+                println!("Usage: .load FILE [FILL_PERCENT]");
+                return false;
             }
-            num_page as usize
-        }
-        Pager {
-            num_pages: num_pages_file(file.metadata().unwrap().len()),
-            file_descriptor: RefCell::new(file),
-            pages: std::iter::repeat_with(|| None).take(TABLE_MAX_PAGES).collect::<Vec<_>>()
-        }
-    }
-
-    fn get_page_view(&self, page_num: usize) -> Option<&Page> {
-        if page_num > TABLE_MAX_PAGES {
-            panic!("Tried to fetch page number out of bounds. {} > {}", page_num, TABLE_MAX_PAGES);
-        }
-
-        unsafe {
-            let ptr = self.pages.as_ptr();
-            let page = ptr.offset(page_num as isize);
-            if (*page).is_none() {
-                self.load_page(page_num);
+        };
+        let contents = match fs::read_to_string(file_name) {
+            Ok(contents) => contents,
+            Err(e) => {
+                println!("Error: can not read {}: {}", file_name, e);
+                return false;
             }
-            let page = ptr.offset(page_num as isize);
-            Some((*page).as_ref().unwrap().as_ref())
-        }
-    }
-
-    fn load_page(&self, page_num: usize) {
-        // create a page in memory
-        let mut new_page = Page::new();
-        if page_num <= self.num_pages {
-            self.file_descriptor.borrow_mut().seek(SeekFrom::Start(page_num as u64 * PAGE_SIZE as u64));
-            let result = self.file_descriptor.borrow_mut().read(&mut new_page.buf);
-            if result.is_err() {
-                println!("Error reading file: {}", result.unwrap());
-                process::exit(0x0100);
+        };
+        let mut rows = Vec::new();
+        for (i, line) in contents.lines().enumerate() {
+            let fields: Vec<&str> = line.split_whitespace().collect();
+            if fields.is_empty() {
+                continue;
             }
-        }
-
-        unsafe {
-            let ptr = self.pages.as_ptr();
-            let pages = ptr as *mut Option<Box<Page>>;
-            (*pages.offset(page_num as isize)) = Some(Box::new(new_page));
-        }
-    }
-
-    fn get_page(&mut self, page_num: usize) -> &mut Page {
-        if page_num > TABLE_MAX_PAGES {
-            panic!("Tried to fetch page number out of bounds. {} > {}", page_num, TABLE_MAX_PAGES);
-        }
-        unsafe {
-            let ptr = self.pages.as_ptr();
-            let page = ptr.offset(page_num as isize);
-            if (*page).is_none() {
-                self.load_page(page_num);
-                if page_num >= self.num_pages {
-                    self.num_pages += 1;
+            let id = match fields[..] {
+                [id, _, _] => id.parse::<i64>().ok(),
+                _ => None
+            };
+            match id {
+                Some(id) => rows.push((i + 1, [Value::Integer(id), Value::from(fields[1]), Value::from(fields[2])])),
+                None => {
+                    println!("Syntax error on line {}.", i + 1);
+                    return false;
                 }
             }
         }
-        let pages = self.pages.as_mut_ptr();
-        unsafe {
-            let page = pages.offset(page_num as isize);
-            (*page).as_mut().unwrap().as_mut()
-        }
-    }
-
-    /// Find the leftmost leaf page number.
-    /// This implementation is different from the origin of the tutorial in which the implementation
-    /// of finding the leftmost leaf page by finding the page of the lowest key residing. For example,
-    /// by finding the key 0, and then return the page key 0 should be inserted.
-    pub fn get_leftmost_leaf_page_num(&self, page_num: usize) -> usize {
-        let page = self.get_page_view(page_num);
-        if page.is_none() {
-            panic!("invalid page number {}", page_num);
-        }
-        let p = page.unwrap();
-        if p.is_leaf_node() {
-            return page_num;
-        }
-        let child_page_num = p.get_internal_node_child(0);
-        return self.get_leftmost_leaf_page_num(child_page_num);
-    }
-
-    pub fn pager_flush(&mut self, page_num: usize) {
-        match &self.pages[page_num] {
-            Some(page) => {
-                self.file_descriptor.borrow_mut().seek(SeekFrom::Start(page_num as u64 * PAGE_SIZE as u64));
-                self.file_descriptor.borrow_mut().write(page.buf.as_slice());
-                self.file_descriptor.borrow_mut().flush();
+        let count = rows.len();
+        // line of the last row handed to the loader, the one rejected if loading fails
+        let line = Cell::new(0);
+        let result = connection.bulk_load(rows.into_iter().map(|(n, row)| {
+            line.set(n);
+            row
+        }), fill_factor);
+        match result {
+            EXECUTE_SUCCESS => {
+                println!("Loaded {} rows.", count);
+                return true;
             },
-            None => ()
-
-        }
-    }
-
-    fn close(&mut self) {
-        self.file_descriptor.borrow_mut().flush();
-    }
-
-    fn get_unused_page_num(&self) -> usize {
-        self.num_pages
-    }
-}
-
-pub struct Table {
-    root_page_num: usize,
-    pager: Pager
-}
-
-impl Table {
-
-    fn new(pager: Pager) -> Self {
-        Table {
-            pager,
-            root_page_num: 0
-        }
-    }
-
-    /// Find the position of the key in the table from root page to leaf page according.
-    /// The position contains page number and cell number, if the key does not exist in any leaf
-    /// page, then the position the key could be inserted will be returned.
-    pub fn find(&self, key: u32) -> (usize, usize) {
-        let root_page_num = self.root_page_num;
-        let page = self.pager.get_page_view(root_page_num);
-        if page.is_none() {
-            return (0, 0);
+            EXECUTE_TABLE_NOT_EMPTY => print_execute_error(EXECUTE_TABLE_NOT_EMPTY),
+            result => {
+                print!("Line {}: ", line.get());
+                print_execute_error(result);
+            }
         }
-        self.find_by_page(page.unwrap(), key, root_page_num)
+        false
     }
 
-    fn find_by_page_num(&self, page_num: usize, key: u32) -> (usize, usize) {
-        let page = self.pager.get_page_view(page_num);
-        if page.is_none() {
-            println!("page {} not exist", page_num);
-            process::exit(0x0010);
+    fn print_csv_error(error: CsvError) {
+        match error {
+            CsvError::Prepare(result) => print_prepare_error(result, ""),
+            CsvError::Execute(result) => print_execute_error(result),
+            CsvError::FieldCount { expected, found } =>
+                println!("Error: Expected {} fields but found {}.", expected, found),
+            CsvError::Io(e) => println!("Error: {}", e)
         }
-        self.find_by_page(page.unwrap(), key, page_num)
     }
 
-    /// Find the page number and cell number of the given key, the whole search process starts from
-    /// the root page, which can be an internal node or a leaf node.
-    fn find_by_page(&self, page: &Page, key: u32, page_num: usize) -> (usize, usize) {
-        if *page.get_node_type() == NODE_LEAF {
-            self.leaf_node_find(page, key, page_num)
-        } else {
-            self.internal_node_find(page, key)
+    /// `.import FILE TABLE` inserts the rows of a CSV file, skipping the ones that are rejected.
+    fn import_file(args: &[&str], connection: &mut Connection) -> bool {
+        let [file_name, table] = args else {
+            println!("Usage: .import FILE TABLE");
+            return false;
+        };
+        let file = match fs::File::open(file_name) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Error: can not read {}: {}", file_name, e);
+                return false;
+            }
+        };
+        let mut rejected = 0;
+        let result = connection.import_csv(table, BufReader::new(file), |line, error| {
+            rejected += 1;
+            print!("Line {}: ", line);
+            print_csv_error(error);
+        });
+        match result {
+            Ok(imported) if rejected > 0 => println!("Imported {} rows, {} rejected.", imported, rejected),
+            Ok(imported) => println!("Imported {} rows.", imported),
+            Err(error) => {
+                print_csv_error(error);
+                return false;
+            }
         }
+        rejected == 0
     }
 
-    pub fn internal_node_find(&self, page: &Page, key: u32) -> (usize, usize) {
-        let cell_index = page.internal_node_find_child(key);
-        if page.get_internal_node_key(cell_index) >= key {
-            let child_page_num = page.get_internal_node_child(cell_index);
-            return self.find_by_page_num(child_page_num, key);
+    /// `.export TABLE FILE` writes the rows of the table to a CSV file.
+    fn export_file(args: &[&str], connection: &mut Connection) -> bool {
+        let [table, file_name] = args else {
+            println!("Usage: .export TABLE FILE");
+            return false;
+        };
+        let file = match fs::File::create(file_name) {
+            Ok(file) => file,
+            Err(e) => {
+                println!("Error: can not write {}: {}", file_name, e);
+                return false;
+            }
+        };
+        match connection.export_csv(table, BufWriter::new(file)) {
+            Ok(exported) => {
+                println!("Exported {} rows.", exported);
+                true
+            },
+            Err(error) => {
+                print_csv_error(error);
+                false
+            }
         }
-        let right_child_num = page.get_internal_node_right_child();
-        self.find_by_page_num(right_child_num, key)
     }
 
-    /// Binary searches this leaf node(page) for the given key.
-    ///
-    /// If the value is found, then the page number and cell number are returned, if the given key
-    /// is not found, then the position the key could be inserted is returned.
-    fn leaf_node_find(&self, page: &Page, key: u32, page_num: usize) -> (usize, usize) {
-        (page_num, page.leaf_node_find(key))
-    }
-
-    /// Add a new child/key pair to parent that corresponds to child
-    pub fn internal_node_insert(&mut self, parent_page_num: usize, child_page_num: usize) {
-        let child_max_key;
-        {
-            let child = self.pager.get_page_view(child_page_num).unwrap();
-            child_max_key = child.get_node_max_key();
-        }
-
-        let right_child_page_num;
-        let child_max_key_index;
-        let origin_num_keys;
-        {
-            let parent = self.pager.get_page(parent_page_num);
-            right_child_page_num = parent.get_internal_node_right_child();
-            child_max_key_index = parent.internal_node_find_child(child_max_key);
-            origin_num_keys = parent.get_internal_node_num_keys();
-            if origin_num_keys >= INTERNAL_NODE_MAX_CELLS {
-                println!("Need to implement splitting internal node");
-                process::exit(0x0010);
+    /// `.backup FILE` copies the database to a file.
+    fn backup(args: &[&str], connection: &mut Connection) -> bool {
+        let [file_name] = args else {
+            println!("Usage: .backup FILE");
+            return false;
+        };
+        match connection.backup_to(file_name) {
+            Ok(pages) => {
+                println!("Backed up {} pages.", pages);
+                true
+            },
+            Err(e) => {
+                println!("Error: can not back up to {}: {}", file_name, e);
+                false
             }
-            parent.increase_internal_node_num_keys(1);
-        }
-
-        let right_child_max_key;
-        {
-            let right_child = self.pager.get_page_view(right_child_page_num).unwrap();
-            right_child_max_key = right_child.get_node_max_key();
         }
+    }
 
-        if child_max_key > right_child_max_key {
-            let parent = self.pager.get_page(parent_page_num);
-            // let parent_ptr = parent as *mut Page;
-            parent.set_internal_node_right_child(child_page_num);
-            parent.set_internal_node_child(origin_num_keys, right_child_page_num);
-            parent.set_internal_node_key(origin_num_keys, right_child_max_key);
-        } else {
-            let parent = self.pager.get_page(parent_page_num);
-            for i in (child_max_key_index + 1..=origin_num_keys).rev() {
-                unsafe {
-                    std::ptr::copy_nonoverlapping(parent.leaf_node_cell(i - 1),
-                                                  parent.leaf_node_cell(i) as *mut u8,
-                                                  INTERNAL_NODE_CELL_SIZE);
+    /// `.restore FILE [KEY_FILE]` replaces the database with a copy made by `.backup`. A copy
+    /// encrypted with a salt of its own needs its passphrase, read from the first line of the
+    /// key file.
+    fn restore(args: &[&str], connection: &mut Connection) -> bool {
+        let (file_name, passphrase) = match args {
+            [file_name] => (file_name, None),
+            [file_name, key_file] => match fs::read_to_string(key_file) {
+                Ok(key) => (file_name, Some(key.lines().next().unwrap_or_default().to_string())),
+                Err(e) => {
+                    println!("Error: can not read {}: {}", key_file, e);
+                    return false;
                 }
+            },
+            _ => {
+                println!("Usage: .restore FILE [KEY_FILE]");
+                return false;
+            }
+        };
+        match connection.restore_from(file_name, passphrase.as_deref()) {
+            Ok(pages) => {
+                println!("Restored {} pages.", pages);
+                true
+            },
+            Err(e) => {
+                println!("Error: can not restore from {}: {}", file_name, e);
+                false
             }
-            parent.set_internal_node_child(child_max_key_index, child_page_num);
-            parent.set_internal_node_key(child_max_key_index, child_max_key);
         }
     }
 
-    pub fn print_tree(&self) {
-        fn print_tree_node(pager: &Pager, page_num: usize, indentation_level: usize) {
-            fn indent(level: usize) {
-                (0..level).for_each(|i| print!(" "));
+    /// `.dump [TABLE]` prints the insert statements recreating every row of the table, or of
+    /// every table, in key order.
+    fn dump(args: &[&str], connection: &mut Connection) -> bool {
+        let tables = match args {
+            [] => connection.table_names().into_iter().map(String::from).collect(),
+            [table] => vec![table.to_string()],
+            _ => {
+                println!("Usage: .dump [TABLE]");
+                return false;
             }
-            let node = pager.get_page_view(page_num);
-            match node {
-                Some(page) => {
-                    match page.get_node_type() {
-                        NodeType::NODE_LEAF => {
-                            let num_keys = page.leaf_node_num_cells();
-                            indent(indentation_level);
-                            println!("- leaf (size {})", num_keys);
-                            for i in 0..num_keys {
-                                indent(indentation_level + 1);
-                                println!("{}", page.leaf_node_key(i));
-                            }
-                        },
-                        NodeType::NODE_INTERNAL => {
-                            let num_keys = page.get_internal_node_num_keys();
-                            indent(indentation_level);
-                            println!("- internal (size {})", num_keys);
-                            for i in 0..num_keys {
-                                let child = page.get_internal_node_child(i);
-                                print_tree_node(pager, child, indentation_level + 1);
-                                indent(indentation_level + 1);
-                                println!("- key {}", page.get_internal_node_key(i));
-                            }
-                            let child = page.get_internal_node_right_child();
-                            print_tree_node(pager, child, indentation_level + 1);
-                        }
-                    }
-                },
-                _ => ()
-            }
-
         };
-
-        print_tree_node(&self.pager, 0, 0);
-    }
-}
-
-pub struct Cursor<'a> {
-    table: &'a mut Table,
-    page_num: usize,
-    cell_num: usize,
-    end_of_table: bool
-}
-
-impl <'a> Cursor<'a> {
-
-    pub fn table_start(table: &'a mut Table) -> Self {
-        let root_page_num = table.root_page_num;
-
-        let leaf_page_num = table.pager.get_leftmost_leaf_page_num(root_page_num);
-        let leaf_node = table.pager.get_page_view(leaf_page_num).unwrap();
-        let num_cells = leaf_node.leaf_node_num_cells();
-
-        Cursor {
-            table,
-            cell_num: 0,
-            page_num: leaf_page_num,
-            end_of_table: num_cells == 0
-        }
-    }
-
-    pub fn get_page(&mut self) -> &mut Page{
-        self.table.pager.get_page(self.page_num)
-    }
-
-    pub fn get_page_view(&self) -> Option<&Page> {
-        self.table.pager.get_page_view(self.page_num)
-    }
-
-    pub fn advance(&mut self) {
-        let page = self.table.pager.get_page_view(self.page_num).unwrap();
-        self.cell_num += 1;
-        if self.cell_num >= page.leaf_node_num_cells() {
-            /* Advance to next leaf node */
-            let next_page_num = page.get_leaf_node_next_leaf();
-            if next_page_num == 0 {
-                /* This was rightmost leaf */
-                self.end_of_table = true;
-            } else {
-                self.page_num = next_page_num;
-                self.cell_num = 0;
+        for table in tables {
+            let stmt = match connection.prepare(&format!("select * from {}", table)) {
+                Ok(stmt) => stmt,
+                Err(result) => {
+                    print_prepare_error(result, "");
+                    return false;
+                }
+            };
+            let result = connection.execute(&stmt, |row| {
+                let values: Vec<String> = row.iter().map(|v| v.sql_literal()).collect();
+                println!("insert into {} values ({});", table, values.join(", "));
+            });
+            if result != EXECUTE_SUCCESS {
+                print_execute_error(result);
+                return false;
             }
         }
+        true
     }
 
-    pub fn cursor_value(&mut self) -> Box<Row> {
-        let cell_num = self.cell_num;
-        let page = self.get_page_view().unwrap();
-        unsafe { page.row_mut_slot(cell_num) }
-    }
-
-    pub unsafe fn leaf_node_insert(&mut self, key: u32, value: &Row) {
-        let cell_num = self.cell_num;
-        let page = self.get_page();
-        let num_cells = page.leaf_node_num_cells();
-        if num_cells >= LEAF_NODE_MAX_CELLS {
-            self.leaf_node_split_and_insert(value.id, value);
-            return;
-        }
-        if cell_num < num_cells {
-            // shift cell from cell_num to num_cells to right to make room for new cell
-            for i in (cell_num + 1..=num_cells).rev() {
-                std::ptr::copy_nonoverlapping(page.leaf_node_cell(i - 1),
-                                              page.leaf_node_cell(i) as *mut u8,
-                                              LEAF_NODE_CELL_SIZE);
+    fn set_output_option(command: &str, args: &[&str], options: &mut OutputOptions) -> bool {
+        match (command, args) {
+            (".mode", []) => println!("current output mode: {}", options.mode.name()),
+            (".mode", [mode]) => match OutputMode::from_name(mode) {
+                Some(mode) => options.mode = mode,
+                None => {
+                    println!("Error: mode should be one of: table csv json line markdown list");
+                    return false;
+                }
+            },
+            (".headers", [flag]) if flag.eq_ignore_ascii_case("on") => options.headers = true,
+            (".headers", [flag]) if flag.eq_ignore_ascii_case("off") => options.headers = false,
+            (".nullvalue", [value]) => options.null_value = value.to_string(),
+            (".nullvalue", []) => options.null_value.clear(),
+            (".headers", _) => {
+                println!("Usage: .headers on|off");
+                return false;
+            },
+            _ => {
+                println!("Usage: {} {}", command, if command == ".mode" { "MODE" } else { "STRING" });
+                return false;
             }
         }
-        page.set_leaf_node_num_cells(num_cells + 1);
-        page.set_leaf_node_key(cell_num, key);
-
-        let cell = page.leaf_node_value(cell_num);
-        serialize_row(cell, value);
+        true
     }
 
-    /// Create a new node and move half the cells over.
-    ///
-    /// Insert the new value in one of the two nodes.
-    ///
-    /// Update parent or create a new parent.
-    ///
-    /// The implementation of this method is different from the origin c code which can be found in
-    /// [Part 10 - Splitting a Leaf Node](https://cstack.github.io/db_tutorial/parts/part10.html#splitting-algorithm).
-    /// Because of the reference borrow checker mechanism of Rust，only one mutable reference can be
-    /// borrowed at one time, so the copy page data process should be splitted into two code block.
-    fn leaf_node_split_and_insert(&mut self, key: u32, value: &Row) {
-        // create a new right node
-        let value_cell_num = self.cell_num;
-        // page that will be created
-        let new_page_num = self.table.pager.get_unused_page_num();
-        let old_max;
-        {
-            let old_node = self.get_page_view().unwrap();
-            old_max = old_node.get_node_max_key();
-            let old_next_page_num = old_node.get_leaf_node_next_leaf();
-            let old_node_parent_num = old_node.get_node_parent();
-            let old_node_ptr = old_node as *const Page;
-            // create a new node
-            let new_node = self.table.pager.get_page(new_page_num);
-            // init and copy cells to new right node from old node
-            new_node.initialize_leaf_node();
-            new_node.set_node_parent(old_node_parent_num);
-            new_node.set_leaf_node_next_leaf(old_next_page_num);
-            copy_page_data((LEAF_NODE_LEFT_SPLIT_COUNT..LEAF_NODE_MAX_CELLS + 1).rev(), old_node_ptr, new_node, key, value, value_cell_num);
-            new_node.set_leaf_node_num_cells(LEAF_NODE_RIGHT_SPLIT_COUNT);
-        }
-
-        let mut is_node_root = false;
-        {
-            // Move cell that still in old node to new position.
-            // for example, the node [1, 3, 5, 7, 9] is full, and cell 2 is being inserted now,
-            // so we should split this node, and [5, 7, 9] is the new node. At the same time,
-            // cell 3 should be moved to the next space, after the, cell 2 can be inserted into
-            // the old node. So the old node is [1, 2, 3] after inserting is finished.
-            let old_node = self.get_page();
-            is_node_root = old_node.is_node_root();
-            copy_page_data((0..LEAF_NODE_LEFT_SPLIT_COUNT).rev(), old_node as *const Page, old_node, key, value, value_cell_num);
-            old_node.set_leaf_node_num_cells(LEAF_NODE_LEFT_SPLIT_COUNT);
-            old_node.set_leaf_node_next_leaf(new_page_num);
-        }
-
-        if is_node_root {
-            // The old leaf node is the root node, then a new root node should be created.
-            self.create_new_node(new_page_num);
-        } else {
-            let old_node = self.get_page();
-            let parent_page_num = old_node.get_node_parent();
-            let new_max = old_node.get_node_max_key();
-            let parent = self.table.pager.get_page(parent_page_num);
-            parent.update_internal_node_key(old_max, new_max);
-            self.table.internal_node_insert(parent_page_num, new_page_num);
+    fn print_help() {
+        let usages: Vec<String> = META_COMMANDS.iter()
+            .map(|(name, args, _)| if args.is_empty() { name.to_string() } else { format!("{} {}", name, args) })
+            .collect();
+        let width = usages.iter().map(|u| u.len()).max().unwrap_or(0);
+        for (usage, (_, _, description)) in usages.iter().zip(META_COMMANDS) {
+            println!("{:width$}  {}", usage, description, width = width);
         }
     }
 
-    fn create_new_node(&mut self, right_child_page_num: usize) {
-        // create new root node
-        let left_child_page_num = self.table.pager.get_unused_page_num();
-        let mut node_max_key;
-        {
-            let old_node = self.get_page_view().unwrap();
-            let old_node_ptr = old_node as *const Page;
-            let left_child = self.table.pager.get_page(left_child_page_num);
-            unsafe {
-                std::ptr::copy(old_node_ptr as *const u8, left_child as *mut Page as *mut u8, PAGE_SIZE);
-                left_child.set_node_root(false);
+    /// The tables named by the arguments of `.schema` or `.indexes`, every table if none is.
+    fn tables_arg(command: &str, args: &[&str], connection: &Connection) -> Option<Vec<String>> {
+        match args {
+            [] => Some(connection.table_names().into_iter().map(String::from).collect()),
+            [table] => Some(vec![table.to_string()]),
+            _ => {
+                println!("Usage: {} [TABLE]", command);
+                None
             }
-            node_max_key = left_child.get_node_max_key();
-        }
-
-        let old_node = self.get_page();
-        old_node.initialize_internal_node();
-        old_node.set_node_root(true);
-        old_node.set_internal_node_num_keys(1);
-        old_node.set_internal_node_child(0, left_child_page_num);
-        old_node.set_internal_node_key(0, node_max_key);
-        old_node.set_internal_node_right_child(right_child_page_num);
-
-        let root_page_num = self.table.root_page_num;
-        {
-            let left_child = self.table.pager.get_page(left_child_page_num);
-            left_child.set_node_parent(root_page_num);
-        }
-        {
-            let right_child = self.table.pager.get_page(right_child_page_num);
-            right_child.set_node_parent(root_page_num);
         }
     }
-}
-
-unsafe fn serialize_row(cell: *mut u8, source: &Row) {
-    std::ptr::write(cell as *mut u32, source.id);
-
-    std::ptr::write((cell as usize + USERNAME_OFFSET) as *mut [u8; USERNAME_SIZE], [0 as u8; USERNAME_SIZE]);
-    std::ptr::copy(source.username.as_ptr(), (cell as usize + USERNAME_OFFSET) as *mut u8, source.username.len());
 
-    std::ptr::write((cell as usize + EMAIL_OFFSET) as *mut [u8; EMAIL_SIZE], [0 as u8; EMAIL_SIZE]);
-    std::ptr::copy(source.email.as_ptr(), (cell as usize + EMAIL_OFFSET) as *mut u8, source.email.len());
-}
-
-fn copy_page_data(rang: Rev<Range<usize>>, src_ptr: *const Page, dst_page: &mut Page, key: u32, value: &Row, value_cell_num: usize) {
-    for i in rang {
-        let index_within_node = i % LEAF_NODE_LEFT_SPLIT_COUNT;
-        let destination = dst_page.leaf_node_cell(index_within_node);
-        unsafe {
-            if i == value_cell_num {
-                dst_page.set_leaf_node_key(index_within_node, key);
-                let destination = dst_page.leaf_node_value(index_within_node);
-                serialize_row(destination as *mut u8, value);
-            } else if i > value_cell_num {
-                std::ptr::copy((*src_ptr).leaf_node_cell(i - 1), destination as *mut u8, LEAF_NODE_CELL_SIZE);
-            } else {
-                std::ptr::copy((*src_ptr).leaf_node_cell(i), destination as *mut u8, LEAF_NODE_CELL_SIZE)
+    fn print_schema(args: &[&str], connection: &Connection) -> bool {
+        let Some(tables) = tables_arg(".schema", args, connection) else {
+            return false;
+        };
+        for table in tables {
+            match connection.table_schema(&table) {
+                Some(sql) => println!("{}", sql),
+                None => {
+                    print_prepare_error(PREPARE_UNKNOWN_TABLE(table), "");
+                    return false;
+                }
             }
         }
+        true
     }
-}
-
-const ID_SIZE: usize = std::mem::size_of::<u32>();
-const USERNAME_SIZE: usize = 32;
-const EMAIL_SIZE: usize = 255;
-const ID_OFFSET: usize = 0;
-const USERNAME_OFFSET: usize = ID_OFFSET + ID_SIZE;
-const EMAIL_OFFSET: usize = USERNAME_OFFSET + USERNAME_SIZE;
-const ROW_SIZE: usize = ID_SIZE + USERNAME_SIZE + EMAIL_SIZE;
-const PAGE_SIZE: usize = 4096;
-const TABLE_MAX_PAGES: usize = 100;
-const ROWS_PER_PAGE: usize = PAGE_SIZE / ROW_SIZE;
-const TABLE_MAX_ROWS: usize = TABLE_MAX_PAGES * ROWS_PER_PAGE;
-
-/// Common Node Header Layout:
-/// NODE TYPE|IS ROOT|PARENT POINTER
-const NODE_TYPE_SIZE: usize = std::mem::size_of::<NodeType>();
-const NODE_TYPE_OFFSET: usize = 0;
-const IS_ROOT_SIZE: usize = std::mem::size_of::<bool>();
-const IS_ROOT_OFFSET: usize = NODE_TYPE_SIZE;
-const PARENT_POINTER_SIZE: usize = std::mem::size_of::<usize>();
-const PARENT_POINTER_OFFSET: usize = IS_ROOT_SIZE + IS_ROOT_OFFSET;
-const COMMON_NODE_HEADER_SIZE: usize = NODE_TYPE_SIZE + IS_ROOT_SIZE + PARENT_POINTER_SIZE;
-
-/// Leaf Node Header Layout:
-/// Common Node Header|Cell num of Leaf Node
-const LEAF_NODE_NUM_CELLS_SIZE: usize = std::mem::size_of::<usize>();
-const LEAF_NODE_NUM_CELLS_OFFSET: usize = COMMON_NODE_HEADER_SIZE;
-const LEAF_NODE_NEXT_LEAF_SIZE: usize = std::mem::size_of::<usize>();
-const LEAF_NODE_NEXT_LEAF_OFFSET: usize = LEAF_NODE_NUM_CELLS_OFFSET + LEAF_NODE_NUM_CELLS_SIZE;
-const LEAF_NODE_HEADER_SIZE: usize = COMMON_NODE_HEADER_SIZE + LEAF_NODE_NUM_CELLS_SIZE + LEAF_NODE_NEXT_LEAF_SIZE;
-
-/// Leaf Node Body Layout:
-/// [Leaf Node Key|Leaf Node Value]
-const LEAF_NODE_KEY_SIZE: usize = std::mem::size_of::<u32>();
-const LEAF_NODE_KEY_OFFSET: usize = 0;
-const LEAF_NODE_VALUE_SIZE: usize = ROW_SIZE;
-const LEAF_NODE_VALUE_OFFSET: usize = LEAF_NODE_KEY_OFFSET + LEAF_NODE_KEY_SIZE;
-const LEAF_NODE_CELL_SIZE: usize = LEAF_NODE_KEY_SIZE + LEAF_NODE_VALUE_SIZE;
-const LEAF_NODE_SPACE_FOR_CELLS: usize = PAGE_SIZE - LEAF_NODE_HEADER_SIZE;
-const LEAF_NODE_MAX_CELLS: usize = LEAF_NODE_SPACE_FOR_CELLS / LEAF_NODE_CELL_SIZE;
-const LEAF_NODE_RIGHT_SPLIT_COUNT: usize = (LEAF_NODE_MAX_CELLS + 1) / 2;
-const LEAF_NODE_LEFT_SPLIT_COUNT: usize = (LEAF_NODE_MAX_CELLS + 1) - LEAF_NODE_RIGHT_SPLIT_COUNT;
 
-/// Internal Node Header Layout
-const INTERNAL_NODE_NUM_KEYS_SIZE: usize = std::mem::size_of::<usize>();
-const INTERNAL_NODE_NUM_KEYS_OFFSET: usize = COMMON_NODE_HEADER_SIZE;
-const INTERNAL_NODE_RIGHT_CHILD_SIZE: usize = std::mem::size_of::<usize>();
-const INTERNAL_NODE_RIGHT_CHILD_OFFSET: usize = INTERNAL_NODE_NUM_KEYS_OFFSET + INTERNAL_NODE_NUM_KEYS_SIZE;
-const INTERNAL_NODE_HEADER_SIZE: usize = COMMON_NODE_HEADER_SIZE + INTERNAL_NODE_NUM_KEYS_SIZE + INTERNAL_NODE_RIGHT_CHILD_SIZE;
-
-/// Internal Node Body Layout
-const INTERNAL_NODE_KEY_SIZE: usize = std::mem::size_of::<u32>();
-const INTERNAL_NODE_CHILD_SIZE: usize = std::mem::size_of::<usize>();
-const INTERNAL_NODE_CELL_SIZE: usize = INTERNAL_NODE_KEY_SIZE + INTERNAL_NODE_CHILD_SIZE;
-
-// TODO for test, to be replaced with actual internal node cell number
-const INTERNAL_NODE_MAX_CELLS: usize = 3;
-
-fn main() {
-    fn print_prompt() {
-        print!("db > ");
-    }
-
-    fn read_input() -> String {
-        let mut input_buffer = String::new();
-        io::stdin()
-            .read_line(&mut input_buffer)
-            .expect("Failed to read line");
-        String::from(input_buffer.trim())
-    }
-
-    fn do_meta_command(command: &str, table: &mut Table) -> MetaCommandResult {
-        if command.eq(".exit") {
-            db_close(table);
-            process::exit(0x0100);
-        } else if command.eq(".constants") {
-            println!("Constants:");
-            print_constants();
-            return MetaCommandResult::META_COMMAND_SUCCESS;
-        } else if command.eq(".btree") {
-            println!("Btree:");
-            table.print_tree();
-            return MetaCommandResult::META_COMMAND_SUCCESS;
+    fn print_indexes(args: &[&str], connection: &Connection) -> bool {
+        let Some(tables) = tables_arg(".indexes", args, connection) else {
+            return false;
+        };
+        for table in tables {
+            match connection.index_names(&table) {
+                Some(indexes) => indexes.iter().for_each(|index| println!("{}", index)),
+                None => {
+                    print_prepare_error(PREPARE_UNKNOWN_TABLE(table), "");
+                    return false;
+                }
+            }
         }
-        MetaCommandResult::META_COMMAND_UNRECOGNIZED_COMMAND
+        true
     }
 
-    fn pager_open(file_name: &str) -> Pager {
-        // todo return Box<Pager>
-        let file = OpenOptions::new()
-            .write(true)
-            .create(true)
-            .read(true)
-            .open(file_name)
-            .unwrap();
-
-        let mut pager = Pager::new(file);
-        if pager.num_pages == 0 {
-            unsafe {
-                let root_node = pager.get_page(0);
-                root_node.initialize_leaf_node();
-                root_node.set_node_root(true);
+    /// `.read FILE` runs the commands of a file as if they were typed.
+    fn read_file(args: &[&str], shell: &mut Shell) -> bool {
+        let [file_name] = args else {
+            println!("Usage: .read FILE");
+            return false;
+        };
+        match fs::File::open(file_name) {
+            Ok(file) => {
+                let failed = std::mem::take(&mut shell.failed);
+                run_script(shell, BufReader::new(file), false);
+                let succeeded = !shell.failed;
+                shell.failed |= failed;
+                succeeded
+            },
+            Err(e) => {
+                println!("Error: can not read {}: {}", file_name, e);
+                false
             }
         }
-        pager
     }
 
-    fn db_open(file_name: &str) -> Table {
-        let pager = pager_open(file_name);
-        Table::new(pager)
-    }
-
-    fn db_close(table: &mut Table) {
-        for i in 0..table.pager.num_pages {
-            table.pager.pager_flush(i);
+    fn do_meta_command(command: &str, shell: &mut Shell) -> io::Result<MetaCommandResult> {
+        let mut words = command.split_whitespace();
+        let name = words.next().unwrap_or_default();
+        let args: Vec<&str> = words.collect();
+        let connection = &mut shell.connection;
+        let succeeded = match name {
+            ".exit" => exit(shell),
+            ".constants" => {
+                println!("Constants:");
+                print_constants(connection.page_size());
+                true
+            },
+            ".btree" => {
+                println!("Btree:");
+                match connection.print_tree(&mut io::stdout())? {
+                    EXECUTE_SUCCESS => true,
+                    result => {
+                        print_execute_error(result);
+                        false
+                    }
+                }
+            },
+            ".stats" => match connection.tree_stats() {
+                Ok(stats) => {
+                    println!("{}", stats);
+                    true
+                },
+                Err(result) => {
+                    print_execute_error(result);
+                    false
+                }
+            },
+            ".check" => {
+                let errors = connection.integrity_check();
+                if errors.is_empty() {
+                    println!("ok");
+                }
+                errors.iter().for_each(|e| println!("{}", e));
+                errors.is_empty()
+            },
+            ".help" => {
+                print_help();
+                true
+            },
+            ".tables" => {
+                println!("{}", connection.table_names().join("  "));
+                true
+            },
+            ".schema" => print_schema(&args, connection),
+            ".indexes" => print_indexes(&args, connection),
+            ".load" => load_file(&args, connection),
+            ".import" => import_file(&args, connection),
+            ".export" => export_file(&args, connection),
+            ".dump" => dump(&args, connection),
+            ".backup" => backup(&args, connection),
+            ".restore" => restore(&args, connection),
+            ".mode" | ".headers" | ".nullvalue" => set_output_option(name, &args, &mut shell.options),
+            ".read" => read_file(&args, shell),
+            _ => return Ok(MetaCommandResult::META_COMMAND_UNRECOGNIZED_COMMAND)
+        };
+        if succeeded {
+            Ok(MetaCommandResult::META_COMMAND_SUCCESS)
+        } else {
+            Ok(MetaCommandResult::META_COMMAND_FAILED)
         }
     }
 
-    fn prepare_insert(command: &str) -> Result<Box<Option<Statement>>, PrepareResult> {
-        let splits: Vec<&str> = command.split(" ").collect();
-        if splits.len() < 4 {
-            return Err(PREPARE_SYNTAX_ERROR);
-        }
-        let id: i32 = splits[1].trim().parse().unwrap();
-        if id < 0 {
-            return Err(PREPARE_NEGATIVE_ID);
-        }
-        let id = id as u32;
-        let username = splits[2].trim();
-        if username.len() > USERNAME_SIZE {
-            return Err(PREPARE_STRING_TOO_LONG);
-        }
-
-        let email = splits[3].trim();
-        if email.len() > EMAIL_SIZE {
-            return Err(PREPARE_STRING_TOO_LONG);
+    /// Run a meta-command or a statement. Returns whether it succeeded, or the error writing
+    /// its output, on which the rest of the rows are not written.
+    fn run_command(command: &str, shell: &mut Shell) -> io::Result<bool> {
+        if command.starts_with(".") {
+            return Ok(match do_meta_command(command, shell)? {
+                MetaCommandResult::META_COMMAND_UNRECOGNIZED_COMMAND => {
+                    println!("Unrecognized command {}. Enter \".help\" for the list of commands.", command);
+                    false
+                },
+                MetaCommandResult::META_COMMAND_FAILED => false,
+                MetaCommandResult::META_COMMAND_SUCCESS => true
+            });
         }
-        Ok(Box::new(Some(Statement {
-            stmt_type: StatementType::STATEMENT_INSERT,
-            row_to_insert: Some(Row {
-                id,
-                username: String::from(username),
-                email: String::from(email)
-            })
-        })))
-    }
 
-    fn prepare_statement(command: &str) -> Result<Box<Option<Statement>>, PrepareResult> {
-        if command.starts_with("insert") {
-            prepare_insert(command)
-        } else if command.starts_with("select") {
-            Ok(Box::new(Some(Statement {
-                stmt_type: StatementType::STATEMENT_SELECT,
-                row_to_insert: None
-            })))
+        // parameters are left unbound in the shell, so they are NULL
+        let stmt = match shell.connection.prepare(command) {
+            Ok(stmt) => stmt,
+            Err(prepare_result) => {
+                print_prepare_error(prepare_result, command);
+                return Ok(false);
+            }
+        };
+        let result = if stmt.column_names().is_empty() {
+            shell.connection.execute(&stmt, |_| {})
         } else {
-            Err(PREPARE_UNRECOGNIZED_STATEMENT)
-        }
-    }
-
-    fn execute_insert(statement: &Statement, table: &mut Table) -> ExecuteResult {
-        match statement.row_to_insert.as_ref() {
-            Some(row_to_insert) => {
-                let (page_num, cell_num) = table.find(row_to_insert.id);
-                let page = table.pager.get_page(page_num);
-                if cell_num < page.leaf_node_num_cells() {
-                    let key_at_index = page.leaf_node_key(cell_num);
-                    if key_at_index == row_to_insert.id {
-                        return EXECUTE_DUPLICATE_KEY
-                    }
+            let mut formatter = formatter(&shell.options, stmt.column_names(), io::stdout());
+            let mut written = Ok(());
+            let result = shell.connection.execute(&stmt, |row| {
+                if written.is_ok() {
+                    written = formatter.row(row);
                 }
-                let mut cursor = Cursor {
-                    table,
-                    page_num,
-                    cell_num,
-                    end_of_table: false
-                };
-                unsafe { cursor.leaf_node_insert((*row_to_insert).id, row_to_insert) };
-                EXECUTE_SUCCESS
+            });
+            written.and_then(|_| formatter.finish())?;
+            result
+        };
+        match result {
+            EXECUTE_SUCCESS => {
+                println!("Executed.");
+                Ok(true)
             },
-            None => EXECUTE_FAIL
+            result => {
+                print_execute_error(result);
+                Ok(false)
+            }
         }
     }
 
-    fn execute_select(statement: &Statement, table: &mut Table) -> ExecuteResult {
-        let mut cursor = Cursor::table_start(table);
-        while !cursor.end_of_table {
-            let row = cursor.cursor_value();
-            unsafe {
-                println!("{}, {}, {}", (*row).id, (*row).username, (*row).email)
+    /// Run the command, marking the shell failed if the command fails. The shell exits when
+    /// the output can no longer be written.
+    fn run_and_record(command: &str, shell: &mut Shell) {
+        match run_command(command, shell) {
+            Ok(true) => {},
+            Ok(false) => shell.failed = true,
+            Err(e) => {
+                eprintln!("Error: can not write the output: {}", e);
+                shell.failed = true;
+                exit(shell);
             }
-            cursor.advance();
         }
-        EXECUTE_SUCCESS
     }
 
-    fn execute_statement(statement: Box<Option<Statement>>, table: &mut Table) -> ExecuteResult {
-        let stmt = statement.unwrap();
-        match &stmt.stmt_type {
-            StatementType::STATEMENT_INSERT => execute_insert(&stmt, table),
-            StatementType::STATEMENT_SELECT => execute_select(&stmt, table),
-            _ => ExecuteResult::EXECUTE_FAIL
+    /// Run every command of the input until its end, printing a prompt before each line when
+    /// someone is typing them. A statement left without its semicolon at the end is run too.
+    fn run_script(shell: &mut Shell, mut input: impl BufRead, prompt: bool) {
+        let mut line = String::new();
+        let mut command = String::new();
+        loop {
+            if prompt {
+                print_prompt(!command.is_empty());
+            }
+            line.clear();
+            match input.read_line(&mut line) {
+                Ok(0) => break,
+                Ok(_) => {},
+                Err(e) => {
+                    println!("Error: {}", e);
+                    shell.failed = true;
+                    break;
+                }
+            }
+            if let Some(command) = add_line(&mut command, line.trim_end_matches(['\n', '\r'])) {
+                run_and_record(command.trim(), shell);
+            }
+        }
+        if !command.trim().is_empty() {
+            run_and_record(command.trim(), shell);
+        }
+        if prompt {
+            println!();
         }
     }
 
-    fn print_constants() {
-        println!("ROW_SIZE: {}", ROW_SIZE);
-        println!("COMMON_NODE_HEADER_SIZE: {}", COMMON_NODE_HEADER_SIZE);
-        println!();
-        println!("LEAF_NODE_HEADER_SIZE: {}", LEAF_NODE_HEADER_SIZE);
-        println!("LEAF_NODE_CELL_SIZE: {}", LEAF_NODE_CELL_SIZE);
-        println!("LEAF_NODE_SPACE_FOR_CELLS: {}", LEAF_NODE_SPACE_FOR_CELLS);
-        println!("LEAF_NODE_MAX_CELLS: {}", LEAF_NODE_MAX_CELLS);
-        println!();
-        println!("INTERNAL_NODE_HEADER_SIZE: {}", INTERNAL_NODE_HEADER_SIZE);
-        println!("INTERNAL_NODE_KEY_SIZE: {}", INTERNAL_NODE_KEY_SIZE);
-        println!("INTERNAL_NODE_CHILD_SIZE: {}", INTERNAL_NODE_CHILD_SIZE);
-        println!("INTERNAL_NODE_CELL_SIZE: {}", INTERNAL_NODE_CELL_SIZE);
+    /// Whether the statement is `pragma key` or `pragma rekey`, whose passphrase is kept out of
+    /// the history.
+    fn gives_passphrase(command: &str) -> bool {
+        let mut words = command.split(|c: char| c.is_whitespace() || c == '=' || c == '(')
+            .filter(|word| !word.is_empty())
+            .map(str::to_ascii_lowercase);
+        words.next().is_some_and(|word| word == "pragma") && words.next().is_some_and(|word| word == "key" || word == "rekey")
     }
 
-    let args: Vec<String> = env::args().collect();
-    if args.len() < 2 {
-        println!("Must supply a database filename.");
-        process::exit(0x0100);
-    }
-    let mut table = db_open(args[1].as_str());
-    loop {
-        print_prompt();
-        let command = read_input();
-        if command.starts_with(".") {
-            let meta_result = do_meta_command(&command, &mut table);
-            match meta_result {
-                MetaCommandResult::META_COMMAND_UNRECOGNIZED_COMMAND => {
-                    println!("Unrecognized command {}", command);
+    /// Read commands with line editing until the end of the input, over several lines as
+    /// `add_line` puts them together. The commands but the ones giving a passphrase are kept
+    /// in a history file in the home directory.
+    fn run_interactive(shell: &mut Shell) {
+        let config = Config::builder()
+            .completion_type(CompletionType::List)
+            .auto_add_history(false)
+            .build();
+        let mut editor: Editor<ShellHelper, FileHistory> = match Editor::with_config(config) {
+            Ok(editor) => editor,
+            Err(_) => return run_script(shell, io::stdin().lock(), true)
+        };
+        editor.set_helper(Some(ShellHelper { tables: shell.connection.table_names() }));
+        let history = env::var_os("HOME").map(|home| PathBuf::from(home).join(".db_tutorial_history"));
+        if let Some(history) = &history {
+            let _ = editor.load_history(history);
+        }
+
+        let mut command = String::new();
+        loop {
+            let prompt = if command.is_empty() { "db > " } else { "   ...> " };
+            let line = match editor.readline(prompt) {
+                Ok(line) => line,
+                Err(ReadlineError::Interrupted) => {
+                    command.clear();
                     continue;
                 },
-                MetaCommandResult::META_COMMAND_SUCCESS => continue
-            }
-        }
-
-        match prepare_statement(&command) {
-            Ok(stmt) => {
-                match execute_statement(stmt, &mut table) {
-                    EXECUTE_SUCCESS => println!("Executed."),
-                    EXECUTE_DUPLICATE_KEY => println!("Error: Duplicate key."),
-                    EXECUTE_TABLE_FULL => println!("Error: Table full."),
-                    _ => println!("Error: execute failed")
+                Err(ReadlineError::Eof) => break,
+                Err(e) => {
+                    println!("Error: {}", e);
+                    shell.failed = true;
+                    break;
                 }
-            },
-            Err(prepare_result) => {
-                match prepare_result {
-                    PREPARE_UNRECOGNIZED_STATEMENT =>
-                        println!("Unrecognized keyword at start of {}.", command),
-                    PREPARE_SYNTAX_ERROR =>
-                        println!("Syntax error. Could not parse statement."),
-                    PREPARE_STRING_TOO_LONG =>
-                        println!("String is too long."),
-                    PREPARE_NEGATIVE_ID =>
-                        println!("ID must be positive."),
-                    _ => {},
-                };
+            };
+            let Some(command) = add_line(&mut command, &line) else {
                 continue;
+            };
+            if !gives_passphrase(&command) {
+                let _ = editor.add_history_entry(command.as_str());
+                if let Some(history) = &history {
+                    let _ = editor.save_history(history);
+                }
             }
+            run_and_record(command.trim(), shell);
+        }
+    }
 
-        };
+    fn usage() -> ! {
+        println!("Usage: db_tutorial_rust [--mmap] [--readonly] [--busy-timeout MS] [-c COMMANDS]... FILE");
+        process::exit(1);
+    }
+
+    let mut file_name = None;
+    let mut commands = Vec::new();
+    let mut options = ConnectionOptions::default();
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "-c" => commands.push(args.next().unwrap_or_else(|| usage())),
+            "--mmap" => options.mmap = true,
+            "--readonly" => options.read_only = true,
+            "--busy-timeout" => {
+                let millis = args.next().and_then(|a| a.parse().ok()).unwrap_or_else(|| usage());
+                options.busy_timeout = Some(Duration::from_millis(millis));
+            },
+            _ if file_name.is_none() => file_name = Some(arg),
+            _ => usage()
+        }
+    }
+    let file_name = file_name.unwrap_or_else(|| usage());
+
+    let connection = Connection::open_with(&file_name, &options).unwrap_or_else(|e| {
+        match e {
+            OpenError::Locked => println!("Error: database is locked."),
+            OpenError::Corrupt(message) => println!("Error: cannot open {}: {} Corrupt file.", file_name, message),
+            OpenError::Io(e) => println!("Error: cannot open {}: {}", file_name, e)
+        }
+        process::exit(1);
+    });
+    let mut shell = Shell {
+        connection,
+        options: OutputOptions::default(),
+        failed: false
+    };
+    if commands.is_empty() {
+        if io::stdin().is_terminal() {
+            run_interactive(&mut shell);
+        } else {
+            run_script(&mut shell, io::stdin().lock(), false);
+        }
+    } else {
+        for command in commands {
+            run_script(&mut shell, command.as_bytes(), false);
+        }
     }
+    exit(&mut shell);
 }
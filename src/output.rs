@@ -0,0 +1,272 @@
+use std::io::{self, Write};
+use crate::csv;
+use crate::value::Value;
+
+/// How the rows of a query are printed.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum OutputMode {
+    /// Values separated by commas, the default.
+    List,
+    /// RFC 4180 records.
+    Csv,
+    /// An array of objects keyed by column name.
+    Json,
+    /// One `column = value` line per value, rows separated by a blank line.
+    Line,
+    Markdown,
+    /// Aligned columns framed by lines, the way a human would read them.
+    Table
+}
+
+impl OutputMode {
+
+    pub fn from_name(name: &str) -> Option<OutputMode> {
+        match name.to_ascii_lowercase().as_str() {
+            "list" => Some(OutputMode::List),
+            "csv" => Some(OutputMode::Csv),
+            "json" => Some(OutputMode::Json),
+            "line" => Some(OutputMode::Line),
+            "markdown" => Some(OutputMode::Markdown),
+            "table" => Some(OutputMode::Table),
+            _ => None
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            OutputMode::List => "list",
+            OutputMode::Csv => "csv",
+            OutputMode::Json => "json",
+            OutputMode::Line => "line",
+            OutputMode::Markdown => "markdown",
+            OutputMode::Table => "table"
+        }
+    }
+}
+
+/// Settings of the result formatters.
+pub struct OutputOptions {
+    pub mode: OutputMode,
+    /// Whether list and CSV output start with the column names. Table and markdown output
+    /// always have them, JSON and line output repeat them on every value.
+    pub headers: bool,
+    /// Text printed for `NULL`, except in JSON where it is `null`.
+    pub null_value: String
+}
+
+impl Default for OutputOptions {
+    fn default() -> Self {
+        OutputOptions {
+            mode: OutputMode::List,
+            headers: false,
+            null_value: String::new()
+        }
+    }
+}
+
+/// Prints the rows of one query. Formatters that align their columns hold the rows until
+/// `finish`.
+pub trait Formatter {
+    fn row(&mut self, row: &[Value]) -> io::Result<()>;
+    fn finish(&mut self) -> io::Result<()>;
+}
+
+/// Create the formatter of the mode for a query returning the given columns.
+pub fn formatter<'a>(options: &OutputOptions, columns: &[String], output: impl Write + 'a) -> Box<dyn Formatter + 'a> {
+    let columns = columns.to_vec();
+    let null_value = options.null_value.clone();
+    match options.mode {
+        OutputMode::List | OutputMode::Csv => Box::new(ListFormatter {
+            output,
+            columns,
+            headers: options.headers,
+            csv: options.mode == OutputMode::Csv,
+            null_value
+        }),
+        OutputMode::Json => Box::new(JsonFormatter { output, columns, rows: 0 }),
+        OutputMode::Line => Box::new(LineFormatter { output, columns, null_value, rows: 0 }),
+        OutputMode::Markdown | OutputMode::Table => Box::new(TableFormatter {
+            output,
+            columns,
+            markdown: options.mode == OutputMode::Markdown,
+            null_value,
+            rows: Vec::new()
+        })
+    }
+}
+
+fn display(value: &Value, null_value: &str) -> String {
+    match value {
+        Value::Null => String::from(null_value),
+        v => v.to_string()
+    }
+}
+
+struct ListFormatter<W> {
+    output: W,
+    /// Printed by the first row when headers are on.
+    columns: Vec<String>,
+    headers: bool,
+    csv: bool,
+    null_value: String
+}
+
+impl<W: Write> ListFormatter<W> {
+    fn write_line(&mut self, fields: Vec<String>) -> io::Result<()> {
+        if self.csv {
+            let fields: Vec<String> = fields.iter().map(|f| csv::quote(f).into_owned()).collect();
+            writeln!(self.output, "{}", fields.join(","))
+        } else {
+            writeln!(self.output, "{}", fields.join(", "))
+        }
+    }
+}
+
+impl<W: Write> Formatter for ListFormatter<W> {
+    fn row(&mut self, row: &[Value]) -> io::Result<()> {
+        if std::mem::take(&mut self.headers) {
+            let columns = self.columns.clone();
+            self.write_line(columns)?;
+        }
+        let fields = row.iter().map(|v| display(v, &self.null_value)).collect();
+        self.write_line(fields)
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+fn json_string(s: &str) -> String {
+    let mut escaped = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            '\n' => escaped.push_str("\\n"),
+            '\r' => escaped.push_str("\\r"),
+            '\t' => escaped.push_str("\\t"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c)
+        }
+    }
+    escaped.push('"');
+    escaped
+}
+
+struct JsonFormatter<W> {
+    output: W,
+    columns: Vec<String>,
+    rows: usize
+}
+
+impl<W: Write> Formatter for JsonFormatter<W> {
+    fn row(&mut self, row: &[Value]) -> io::Result<()> {
+        let members: Vec<String> = self.columns.iter().zip(row)
+            .map(|(column, value)| {
+                let value = match value {
+                    Value::Null => String::from("null"),
+                    Value::Integer(i) => i.to_string(),
+                    Value::Text(s) => json_string(s)
+                };
+                format!("{}:{}", json_string(column), value)
+            })
+            .collect();
+        let separator = if self.rows == 0 { "[" } else { ",\n" };
+        self.rows += 1;
+        write!(self.output, "{}{{{}}}", separator, members.join(","))
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.rows == 0 {
+            write!(self.output, "[")?;
+        }
+        writeln!(self.output, "]")?;
+        self.output.flush()
+    }
+}
+
+struct LineFormatter<W> {
+    output: W,
+    columns: Vec<String>,
+    null_value: String,
+    rows: usize
+}
+
+impl<W: Write> Formatter for LineFormatter<W> {
+    fn row(&mut self, row: &[Value]) -> io::Result<()> {
+        if self.rows > 0 {
+            writeln!(self.output)?;
+        }
+        self.rows += 1;
+        let width = self.columns.iter().map(|c| c.chars().count()).max().unwrap_or(0);
+        for (column, value) in self.columns.iter().zip(row) {
+            writeln!(self.output, "{:>width$} = {}", column, display(value, &self.null_value), width = width)?;
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        self.output.flush()
+    }
+}
+
+struct TableFormatter<W> {
+    output: W,
+    columns: Vec<String>,
+    markdown: bool,
+    null_value: String,
+    /// Every row as printed, with whether each value is a number, which is aligned right.
+    rows: Vec<Vec<(String, bool)>>
+}
+
+impl<W: Write> Formatter for TableFormatter<W> {
+    fn row(&mut self, row: &[Value]) -> io::Result<()> {
+        let row = row.iter()
+            .map(|v| (display(v, &self.null_value), matches!(v, Value::Integer(_))))
+            .collect();
+        self.rows.push(row);
+        Ok(())
+    }
+
+    fn finish(&mut self) -> io::Result<()> {
+        if self.rows.is_empty() {
+            return Ok(());
+        }
+        let widths: Vec<usize> = self.columns.iter().enumerate()
+            .map(|(i, c)| self.rows.iter()
+                .map(|row| row[i].0.chars().count())
+                .fold(c.chars().count(), usize::max))
+            .collect();
+        let border: Vec<String> = widths.iter().map(|&w| "-".repeat(w + 2)).collect();
+        let border = if self.markdown {
+            format!("|{}|", border.join("|"))
+        } else {
+            format!("+{}+", border.join("+"))
+        };
+        let line = |cells: Vec<String>| format!("| {} |", cells.join(" | "));
+
+        let header = self.columns.iter().zip(&widths)
+            .map(|(c, &w)| format!("{:<w$}", c, w = w))
+            .collect();
+        if !self.markdown {
+            writeln!(self.output, "{}", border)?;
+        }
+        writeln!(self.output, "{}", line(header))?;
+        writeln!(self.output, "{}", border)?;
+        for row in std::mem::take(&mut self.rows) {
+            let cells = row.iter().zip(&widths)
+                .map(|((value, number), &w)| if *number {
+                    format!("{:>w$}", value, w = w)
+                } else {
+                    format!("{:<w$}", value, w = w)
+                })
+                .collect();
+            writeln!(self.output, "{}", line(cells))?;
+        }
+        if !self.markdown {
+            writeln!(self.output, "{}", border)?;
+        }
+        self.output.flush()
+    }
+}
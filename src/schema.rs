@@ -0,0 +1,61 @@
+use std::fmt;
+use crate::value::SqlType;
+
+pub struct Column {
+    pub name: &'static str,
+    pub col_type: SqlType
+}
+
+/// Description of a table known to the database.
+///
+/// There is only one table for now, whose rows are laid out by `serialize_row`, so its schema is
+/// built in rather than stored in the database file. The first column is the primary key, which is
+/// also the key of the B-tree.
+pub struct TableSchema {
+    pub name: &'static str,
+    pub columns: &'static [Column]
+}
+
+pub const USERS: TableSchema = TableSchema {
+    name: "users",
+    columns: &[
+        Column { name: "id", col_type: SqlType::Integer },
+        Column { name: "username", col_type: SqlType::Text },
+        Column { name: "email", col_type: SqlType::Text },
+    ]
+};
+
+/// Every table of the database, in the order `.dump` writes them.
+pub const TABLES: [&TableSchema; 1] = [&USERS];
+
+impl TableSchema {
+
+    /// Look up a table by name, table names are case insensitive.
+    pub fn find(name: &str) -> Option<&'static TableSchema> {
+        TABLES.into_iter().find(|t| t.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Return the index of the column with the given name, column names are case insensitive.
+    pub fn column_index(&self, name: &str) -> Option<usize> {
+        self.columns.iter().position(|c| c.name.eq_ignore_ascii_case(name))
+    }
+
+    /// Names of the indexes of the table. The only way to find rows is the primary key, which
+    /// is the key of the table's own B-tree, so there are none yet.
+    pub fn index_names(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// The statement that would create the table. Every column rejects `NULL`.
+impl fmt::Display for TableSchema {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let columns: Vec<String> = self.columns.iter().enumerate()
+            .map(|(i, c)| {
+                let constraint = if i == 0 { "primary key" } else { "not null" };
+                format!("{} {} {}", c.name, c.col_type, constraint)
+            })
+            .collect();
+        write!(f, "create table {} ({});", self.name, columns.join(", "))
+    }
+}
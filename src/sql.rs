@@ -0,0 +1,759 @@
+use std::fmt;
+use std::iter::Peekable;
+use std::str::Chars;
+use crate::value::Value;
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Token {
+    Ident(String),
+    Integer(i64),
+    Str(String),
+    /// A parameter as written in the statement: `?`, `?N` or `:name`.
+    Variable(String),
+    Comma,
+    Dot,
+    LParen,
+    RParen,
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    Percent,
+    Concat,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Semicolon
+}
+
+/// Split a statement into tokens. Keywords are returned as identifiers and matched
+/// case-insensitively by the parser.
+pub fn tokenize(input: &str) -> Result<Vec<Token>, String> {
+    fn next_if_eq(chars: &mut Peekable<Chars>, expected: char) -> bool {
+        chars.next_if_eq(&expected).is_some()
+    }
+
+    let mut tokens = Vec::new();
+    let mut chars = input.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            ',' => Token::Comma,
+            '.' => Token::Dot,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '*' => Token::Star,
+            '+' => Token::Plus,
+            '-' => Token::Minus,
+            '/' => Token::Slash,
+            '%' => Token::Percent,
+            ';' => Token::Semicolon,
+            '=' => {
+                next_if_eq(&mut chars, '=');
+                Token::Eq
+            },
+            '|' if next_if_eq(&mut chars, '|') => Token::Concat,
+            '!' if next_if_eq(&mut chars, '=') => Token::NotEq,
+            '<' => {
+                if next_if_eq(&mut chars, '=') {
+                    Token::LtEq
+                } else if next_if_eq(&mut chars, '>') {
+                    Token::NotEq
+                } else {
+                    Token::Lt
+                }
+            },
+            '>' => {
+                if next_if_eq(&mut chars, '=') {
+                    Token::GtEq
+                } else {
+                    Token::Gt
+                }
+            },
+            '\'' => {
+                // string literal, a quote inside the literal is escaped by doubling it
+                let mut s = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\'') if next_if_eq(&mut chars, '\'') => s.push('\''),
+                        Some('\'') => break,
+                        Some(c) => s.push(c),
+                        None => return Err(String::from("unterminated string literal"))
+                    }
+                }
+                Token::Str(s)
+            },
+            c if c.is_ascii_digit() => {
+                let mut digits = String::from(c);
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                    digits.push(d);
+                }
+                match digits.parse() {
+                    Ok(i) => Token::Integer(i),
+                    Err(_) => return Err(format!("integer literal {} is too large", digits))
+                }
+            },
+            '?' => {
+                let mut variable = String::from(c);
+                while let Some(d) = chars.next_if(|d| d.is_ascii_digit()) {
+                    variable.push(d);
+                }
+                Token::Variable(variable)
+            },
+            ':' => {
+                let mut variable = String::from(c);
+                while let Some(d) = chars.next_if(|d| d.is_alphanumeric() || *d == '_') {
+                    variable.push(d);
+                }
+                if variable.len() == 1 {
+                    return Err(String::from("missing parameter name after ':'"));
+                }
+                Token::Variable(variable)
+            },
+            c if c.is_alphabetic() || c == '_' => {
+                let mut ident = String::from(c);
+                while let Some(d) = chars.next_if(|d| d.is_alphanumeric() || *d == '_') {
+                    ident.push(d);
+                }
+                Token::Ident(ident)
+            },
+            c => return Err(format!("unexpected character '{}'", c))
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum UnaryOp {
+    Neg,
+    Not
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum BinaryOp {
+    Mul,
+    Div,
+    Rem,
+    Add,
+    Sub,
+    Concat,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or
+}
+
+impl BinaryOp {
+
+    pub fn is_comparison(self) -> bool {
+        matches!(self, BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq)
+    }
+
+    /// Binding strength of the operator, higher binds tighter.
+    fn precedence(self) -> u8 {
+        match self {
+            BinaryOp::Or => 1,
+            BinaryOp::And => 2,
+            BinaryOp::Eq | BinaryOp::NotEq | BinaryOp::Lt | BinaryOp::LtEq | BinaryOp::Gt | BinaryOp::GtEq => 3,
+            BinaryOp::Add | BinaryOp::Sub => 4,
+            BinaryOp::Mul | BinaryOp::Div | BinaryOp::Rem => 5,
+            BinaryOp::Concat => 6
+        }
+    }
+
+    pub fn symbol(self) -> &'static str {
+        match self {
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Rem => "%",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Concat => "||",
+            BinaryOp::Eq => "=",
+            BinaryOp::NotEq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or"
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum Expr {
+    Literal(Value),
+    Column(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Function(String, Vec<Expr>),
+    /// A parameter whose value is bound before the statement is executed. `index` counts from 1,
+    /// `name` is the parameter as written in the statement.
+    Parameter { index: usize, name: String },
+    /// `excluded.name` in `on conflict do update`, a column of the row that could not be inserted.
+    Excluded(String)
+}
+
+impl Expr {
+
+    /// Whether the expression has the same value for every row, that is it references no column.
+    pub fn is_constant(&self) -> bool {
+        match self {
+            Expr::Literal(_) => true,
+            Expr::Column(_) => false,
+            Expr::Unary(_, operand) => operand.is_constant(),
+            Expr::Binary(_, left, right) => left.is_constant() && right.is_constant(),
+            Expr::Function(_, args) => args.iter().all(|arg| arg.is_constant()),
+            Expr::Parameter { .. } => true,
+            Expr::Excluded(_) => false
+        }
+    }
+}
+
+/// Expressions are printed back as SQL, which is also the name of an unaliased result column.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Literal(value) => write!(f, "{}", value.sql_literal()),
+            Expr::Column(name) => write!(f, "{}", name),
+            Expr::Parameter { name, .. } => write!(f, "{}", name),
+            Expr::Excluded(name) => write!(f, "excluded.{}", name),
+            Expr::Unary(op, operand) => {
+                match op {
+                    UnaryOp::Neg => write!(f, "-")?,
+                    UnaryOp::Not => write!(f, "not ")?
+                }
+                match operand.as_ref() {
+                    Expr::Binary(..) => write!(f, "({})", operand),
+                    _ => write!(f, "{}", operand)
+                }
+            },
+            Expr::Binary(op, left, right) => {
+                // operators are left associative, so a right operand of the same precedence
+                // needs parentheses as well
+                match left.as_ref() {
+                    Expr::Binary(l, ..) if l.precedence() < op.precedence() => write!(f, "({})", left)?,
+                    _ => write!(f, "{}", left)?
+                }
+                write!(f, " {} ", op.symbol())?;
+                match right.as_ref() {
+                    Expr::Binary(r, ..) if r.precedence() <= op.precedence() => write!(f, "({})", right),
+                    _ => write!(f, "{}", right)
+                }
+            },
+            Expr::Function(name, args) => {
+                write!(f, "{}(", name)?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+        }
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub enum ResultColumn {
+    /// `*`, all columns of the table.
+    All,
+    Expr {
+        expr: Expr,
+        alias: Option<String>
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Select {
+    pub columns: Vec<ResultColumn>,
+    /// The table name, or `None` for a select computing a single row without reading a table.
+    /// A bare `select` reads the `users` table.
+    pub from: Option<String>,
+    pub where_clause: Option<Expr>
+}
+
+/// The rows to insert, in the column order of the table.
+#[derive(Clone, PartialEq, Debug)]
+pub enum InsertSource {
+    /// `values (...), (...)`, the values of every row.
+    Values(Vec<Vec<Expr>>),
+    /// The rows returned by a `select`.
+    Select(Box<Select>)
+}
+
+/// What an insert does with a row whose key is already in the table.
+#[derive(Clone, PartialEq, Debug)]
+pub enum OnConflict {
+    /// Fail the statement, the default.
+    Abort,
+    /// Skip the row, `insert or ignore` or `on conflict do nothing`.
+    Ignore,
+    /// Overwrite the existing row, `insert or replace`.
+    Replace,
+    /// `on conflict do update set ...`, change the existing row where the condition holds.
+    Update {
+        assignments: Vec<(String, Expr)>,
+        where_clause: Option<Expr>
+    }
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Insert {
+    pub table: String,
+    pub source: InsertSource,
+    pub on_conflict: OnConflict,
+    /// The column named by `on conflict (column)`, which must be the primary key.
+    pub conflict_target: Option<String>
+}
+
+/// `pragma name`, which runs a command of the database or reads a setting, or
+/// `pragma name = value`, which changes the setting.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Pragma {
+    pub name: String,
+    pub value: Option<Value>
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Delete {
+    pub table: String,
+    pub where_clause: Option<Expr>
+}
+
+/// Every keyword of the statements, as offered by completion in the shell.
+pub const KEYWORDS: [&str; 31] = [
+    "and", "as", "compression", "conflict", "delete", "do", "excluded", "explain", "from", "ignore",
+    "insert", "integrity_check", "into", "key", "not", "nothing", "null", "on", "or", "page_size",
+    "plan", "pragma", "query", "rekey", "replace", "select", "set", "update", "values",
+    "verify_checksums", "where"
+];
+
+/// Words that can not be used as a column alias without `as`.
+const RESERVED: [&str; 5] = ["from", "where", "and", "or", "on"];
+
+/// Largest number a `?N` parameter may have.
+const MAX_PARAMETERS: usize = 999;
+
+/// A recursive descent parser over the tokens of one statement.
+pub struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    /// Parameters found so far, indexed from 0: the name of each `:name` parameter, `None` for
+    /// parameters written as `?` or `?N`.
+    pub parameters: Vec<Option<String>>,
+    /// Set while parsing `on conflict do update`, the only place `excluded.name` is allowed.
+    in_upsert: bool
+}
+
+impl Parser {
+
+    pub fn new(input: &str) -> Result<Self, String> {
+        Ok(Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+            parameters: Vec::new(),
+            in_upsert: false
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    /// The keyword the statement starts with in lower case, `None` if it does not start with one.
+    pub fn first_keyword(&self) -> Option<String> {
+        match self.tokens.first() {
+            Some(Token::Ident(word)) => Some(word.to_ascii_lowercase()),
+            _ => None
+        }
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn next_if(&mut self, expected: &Token) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<(), String> {
+        if self.next_if(expected) {
+            Ok(())
+        } else {
+            Err(format!("expected {:?}", expected))
+        }
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Ident(word)) if word.eq_ignore_ascii_case(keyword))
+    }
+
+    fn next_if_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<(), String> {
+        if self.next_if_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(format!("expected {}", keyword))
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String, String> {
+        match self.next() {
+            Some(Token::Ident(name)) => Ok(name),
+            _ => Err(String::from("expected identifier"))
+        }
+    }
+
+    /// Consume an optional trailing `;` and make sure nothing follows the statement.
+    pub fn finish(&mut self) -> Result<(), String> {
+        self.next_if(&Token::Semicolon);
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(format!("unexpected {:?}", token))
+        }
+    }
+
+    pub fn parse_select(&mut self) -> Result<Select, String> {
+        self.expect_keyword("select")?;
+        let mut columns = Vec::new();
+        let bare = self.peek().is_none() || self.peek() == Some(&Token::Semicolon) || self.peek_keyword("from");
+        if bare {
+            // a bare `select` lists every column, as it always has
+            columns.push(ResultColumn::All);
+        } else {
+            loop {
+                columns.push(self.parse_result_column()?);
+                if !self.next_if(&Token::Comma) {
+                    break;
+                }
+            }
+        }
+        let from = if self.next_if_keyword("from") {
+            Some(self.identifier()?)
+        } else if bare {
+            Some(String::from("users"))
+        } else if columns.contains(&ResultColumn::All) {
+            return Err(String::from("select * needs a from clause"));
+        } else {
+            None
+        };
+        let where_clause = self.parse_where()?;
+        Ok(Select { columns, from, where_clause })
+    }
+
+    pub fn parse_insert(&mut self) -> Result<Insert, String> {
+        self.expect_keyword("insert")?;
+        let mut on_conflict = OnConflict::Abort;
+        if self.next_if_keyword("or") {
+            on_conflict = if self.next_if_keyword("replace") {
+                OnConflict::Replace
+            } else {
+                self.expect_keyword("ignore")?;
+                OnConflict::Ignore
+            };
+        }
+        self.expect_keyword("into")?;
+        let table = self.identifier()?;
+        let source = self.parse_insert_source()?;
+        let mut conflict_target = None;
+        if self.next_if_keyword("on") {
+            if on_conflict != OnConflict::Abort {
+                return Err(String::from("insert or replace and insert or ignore take no on conflict clause"));
+            }
+            (on_conflict, conflict_target) = self.parse_upsert()?;
+        }
+        Ok(Insert { table, source, on_conflict, conflict_target })
+    }
+
+    fn parse_insert_source(&mut self) -> Result<InsertSource, String> {
+        if self.peek_keyword("select") {
+            return Ok(InsertSource::Select(Box::new(self.parse_select()?)));
+        }
+        self.expect_keyword("values")?;
+        let mut rows = Vec::new();
+        loop {
+            self.expect(&Token::LParen)?;
+            let mut row = Vec::new();
+            loop {
+                row.push(self.parse_expr()?);
+                if !self.next_if(&Token::Comma) {
+                    break;
+                }
+            }
+            self.expect(&Token::RParen)?;
+            rows.push(row);
+            if !self.next_if(&Token::Comma) {
+                break;
+            }
+        }
+        Ok(InsertSource::Values(rows))
+    }
+
+    /// Parse what follows `on` in `on conflict [(column)] do nothing` or
+    /// `on conflict [(column)] do update set column = expr, ... [where expr]`.
+    fn parse_upsert(&mut self) -> Result<(OnConflict, Option<String>), String> {
+        self.expect_keyword("conflict")?;
+        let mut target = None;
+        if self.next_if(&Token::LParen) {
+            target = Some(self.identifier()?);
+            self.expect(&Token::RParen)?;
+        }
+        self.expect_keyword("do")?;
+        if self.next_if_keyword("nothing") {
+            return Ok((OnConflict::Ignore, target));
+        }
+        self.expect_keyword("update")?;
+        self.expect_keyword("set")?;
+        self.in_upsert = true;
+        let mut assignments = Vec::new();
+        loop {
+            let column = self.identifier()?;
+            self.expect(&Token::Eq)?;
+            assignments.push((column, self.parse_expr()?));
+            if !self.next_if(&Token::Comma) {
+                break;
+            }
+        }
+        let where_clause = self.parse_where()?;
+        self.in_upsert = false;
+        Ok((OnConflict::Update { assignments, where_clause }, target))
+    }
+
+    pub fn parse_delete(&mut self) -> Result<Delete, String> {
+        self.expect_keyword("delete")?;
+        self.expect_keyword("from")?;
+        let table = self.identifier()?;
+        let where_clause = self.parse_where()?;
+        Ok(Delete { table, where_clause })
+    }
+
+    pub fn parse_pragma(&mut self) -> Result<Pragma, String> {
+        self.expect_keyword("pragma")?;
+        let name = self.identifier()?;
+        let value = if self.next_if(&Token::Eq) {
+            match self.next() {
+                Some(Token::Integer(i)) => Some(Value::Integer(i)),
+                Some(Token::Str(s)) | Some(Token::Ident(s)) => Some(Value::Text(s)),
+                _ => return Err(String::from("expected pragma value"))
+            }
+        } else {
+            None
+        };
+        Ok(Pragma { name, value })
+    }
+
+    fn parse_where(&mut self) -> Result<Option<Expr>, String> {
+        if self.next_if_keyword("where") {
+            Ok(Some(self.parse_expr()?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn parse_result_column(&mut self) -> Result<ResultColumn, String> {
+        if self.next_if(&Token::Star) {
+            return Ok(ResultColumn::All);
+        }
+        let expr = self.parse_expr()?;
+        let alias = if self.next_if_keyword("as") {
+            Some(self.identifier()?)
+        } else {
+            match self.peek() {
+                Some(Token::Ident(word)) if !RESERVED.iter().any(|r| word.eq_ignore_ascii_case(r)) => {
+                    Some(self.identifier()?)
+                },
+                _ => None
+            }
+        };
+        Ok(ResultColumn::Expr { expr, alias })
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expr, String> {
+        self.parse_or()
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_and()?;
+        while self.next_if_keyword("or") {
+            let right = self.parse_and()?;
+            left = Expr::Binary(BinaryOp::Or, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_not()?;
+        while self.next_if_keyword("and") {
+            let right = self.parse_not()?;
+            left = Expr::Binary(BinaryOp::And, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, String> {
+        if self.next_if_keyword("not") {
+            let operand = self.parse_not()?;
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(operand)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_additive()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Eq) => BinaryOp::Eq,
+                Some(Token::NotEq) => BinaryOp::NotEq,
+                Some(Token::Lt) => BinaryOp::Lt,
+                Some(Token::LtEq) => BinaryOp::LtEq,
+                Some(Token::Gt) => BinaryOp::Gt,
+                Some(Token::GtEq) => BinaryOp::GtEq,
+                _ => return Ok(left)
+            };
+            self.pos += 1;
+            let right = self.parse_additive()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_multiplicative()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Plus) => BinaryOp::Add,
+                Some(Token::Minus) => BinaryOp::Sub,
+                _ => return Ok(left)
+            };
+            self.pos += 1;
+            let right = self.parse_multiplicative()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_concat()?;
+        loop {
+            let op = match self.peek() {
+                Some(Token::Star) => BinaryOp::Mul,
+                Some(Token::Slash) => BinaryOp::Div,
+                Some(Token::Percent) => BinaryOp::Rem,
+                _ => return Ok(left)
+            };
+            self.pos += 1;
+            let right = self.parse_concat()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn parse_concat(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_unary()?;
+        while self.next_if(&Token::Concat) {
+            let right = self.parse_unary()?;
+            left = Expr::Binary(BinaryOp::Concat, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr, String> {
+        if self.next_if(&Token::Minus) {
+            let operand = self.parse_unary()?;
+            return Ok(match operand {
+                Expr::Literal(Value::Integer(i)) => Expr::Literal(Value::Integer(-i)),
+                operand => Expr::Unary(UnaryOp::Neg, Box::new(operand))
+            });
+        }
+        if self.next_if(&Token::Plus) {
+            return self.parse_unary();
+        }
+        self.parse_primary()
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Integer(i)) => Ok(Expr::Literal(Value::Integer(i))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(Value::Text(s))),
+            Some(Token::LParen) => {
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RParen)?;
+                Ok(expr)
+            },
+            Some(Token::Variable(name)) => {
+                let index = self.parameter_index(&name)?;
+                Ok(Expr::Parameter { index, name })
+            },
+            Some(Token::Ident(name)) if name.eq_ignore_ascii_case("null") => Ok(Expr::Literal(Value::Null)),
+            Some(Token::Ident(name)) if self.in_upsert && name.eq_ignore_ascii_case("excluded") && self.next_if(&Token::Dot) => {
+                Ok(Expr::Excluded(self.identifier()?))
+            },
+            Some(Token::Ident(name)) => {
+                if self.next_if(&Token::LParen) {
+                    let mut args = Vec::new();
+                    if !self.next_if(&Token::RParen) {
+                        loop {
+                            args.push(self.parse_expr()?);
+                            if !self.next_if(&Token::Comma) {
+                                break;
+                            }
+                        }
+                        self.expect(&Token::RParen)?;
+                    }
+                    Ok(Expr::Function(name.to_lowercase(), args))
+                } else {
+                    Ok(Expr::Column(name))
+                }
+            },
+            Some(token) => Err(format!("unexpected {:?}", token)),
+            None => Err(String::from("unexpected end of statement"))
+        }
+    }
+
+    /// Number a parameter the way SQLite does: `?` takes the number after the largest one so
+    /// far, `?N` takes the number N and every occurrence of `:name` shares one number.
+    fn parameter_index(&mut self, variable: &str) -> Result<usize, String> {
+        if variable == "?" {
+            self.parameters.push(None);
+            return Ok(self.parameters.len());
+        }
+        if let Some(digits) = variable.strip_prefix('?') {
+            let index: usize = digits.parse().map_err(|_| format!("invalid parameter {}", variable))?;
+            if index == 0 || index > MAX_PARAMETERS {
+                return Err(format!("parameter {} out of range", variable));
+            }
+            if index > self.parameters.len() {
+                self.parameters.resize(index, None);
+            }
+            return Ok(index);
+        }
+        if let Some(i) = self.parameters.iter().position(|p| p.as_deref() == Some(variable)) {
+            return Ok(i + 1);
+        }
+        self.parameters.push(Some(String::from(variable)));
+        Ok(self.parameters.len())
+    }
+}
@@ -0,0 +1,94 @@
+use std::fmt;
+use crate::{NodeType, Table, EMAIL_SIZE, INTERNAL_NODE_CELL_SIZE, INTERNAL_NODE_HEADER_SIZE, LEAF_NODE_CELL_SIZE,
+            LEAF_NODE_HEADER_SIZE, PAGE_CHECKSUM_SIZE, USERNAME_SIZE};
+
+/// The shape of a B-tree and how much of its pages hold data.
+#[derive(Default, Debug)]
+pub struct TreeStats {
+    pub page_size: usize,
+    /// Number of levels, 1 for a tree that is a single leaf.
+    pub height: usize,
+    pub leaf_pages: usize,
+    pub internal_pages: usize,
+    pub rows: usize,
+    /// Mean over every node of its cells over the cells it can hold, between 0 and 1.
+    pub average_fill: f64,
+    /// Bytes of the pages between the last cell and the checksum.
+    pub free_bytes: usize,
+    /// Zero bytes padding the text values of the rows up to the size of their column.
+    pub padding_bytes: usize,
+    /// Bytes the pages take in the file once compressed, and encrypted when the database has a
+    /// key, `None` when compression is off.
+    pub compressed_bytes: Option<usize>
+}
+
+impl TreeStats {
+    /// Bytes holding nothing, free space in the pages and padding in the rows.
+    pub fn wasted_bytes(&self) -> usize {
+        self.free_bytes + self.padding_bytes
+    }
+}
+
+impl fmt::Display for TreeStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bytes = (self.leaf_pages + self.internal_pages) * self.page_size;
+        writeln!(f, "page size: {}", self.page_size)?;
+        writeln!(f, "height: {}", self.height)?;
+        writeln!(f, "leaf pages: {}", self.leaf_pages)?;
+        writeln!(f, "internal pages: {}", self.internal_pages)?;
+        writeln!(f, "rows: {}", self.rows)?;
+        writeln!(f, "average fill: {:.1}%", self.average_fill * 100.0)?;
+        writeln!(f, "free bytes: {}", self.free_bytes)?;
+        writeln!(f, "padding bytes: {}", self.padding_bytes)?;
+        writeln!(f, "wasted bytes: {} of {} ({:.1}%)", self.wasted_bytes(), bytes,
+                 self.wasted_bytes() as f64 * 100.0 / bytes as f64)?;
+        match self.compressed_bytes {
+            Some(compressed_bytes) => write!(f, "compressed bytes: {} of {} ({:.1}x)", compressed_bytes, bytes,
+                                             bytes as f64 / compressed_bytes as f64),
+            None => write!(f, "compression: off")
+        }
+    }
+}
+
+/// Walk the tree of the table from its root and add up its nodes, rows and unused bytes.
+pub fn tree_stats(table: &Table) -> TreeStats {
+    let page_size = table.pager.page_size;
+    let mut stats = TreeStats { page_size, ..TreeStats::default() };
+    let mut total_fill = 0.0;
+    let mut compressed_bytes = 0;
+    let mut level = vec![table.root_page_num];
+    while !level.is_empty() {
+        stats.height += 1;
+        let mut next_level = Vec::new();
+        for page_num in level {
+            let page = table.pager.get_page_view(page_num).unwrap();
+            if table.pager.compression {
+                compressed_bytes += table.pager.page_image(page_num, page).len();
+            }
+            match page.get_node_type() {
+                NodeType::NODE_LEAF => {
+                    let num_cells = page.leaf_node_num_cells();
+                    stats.leaf_pages += 1;
+                    stats.rows += num_cells;
+                    total_fill += num_cells as f64 / page.leaf_node_max_cells() as f64;
+                    stats.free_bytes += page_size - PAGE_CHECKSUM_SIZE - LEAF_NODE_HEADER_SIZE - num_cells * LEAF_NODE_CELL_SIZE;
+                    for i in 0..num_cells {
+                        let row = unsafe { page.row_mut_slot(i) };
+                        stats.padding_bytes += USERNAME_SIZE - row.username.len() + EMAIL_SIZE - row.email.len();
+                    }
+                },
+                NodeType::NODE_INTERNAL => {
+                    let num_keys = page.get_internal_node_num_keys();
+                    stats.internal_pages += 1;
+                    total_fill += num_keys as f64 / page.internal_node_max_cells() as f64;
+                    stats.free_bytes += page_size - PAGE_CHECKSUM_SIZE - INTERNAL_NODE_HEADER_SIZE - num_keys * INTERNAL_NODE_CELL_SIZE;
+                    next_level.extend((0..=num_keys).map(|i| page.get_internal_node_child(i)));
+                }
+            }
+        }
+        level = next_level;
+    }
+    stats.compressed_bytes = table.pager.compression.then_some(compressed_bytes);
+    stats.average_fill = total_fill / (stats.leaf_pages + stats.internal_pages) as f64;
+    stats
+}
@@ -0,0 +1,122 @@
+use std::fmt;
+
+/// Static type of a column or an expression.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SqlType {
+    Integer,
+    Text,
+    /// The type of the `NULL` literal, which is compatible with every other type.
+    Null
+}
+
+impl SqlType {
+
+    /// Whether values of the two types can be compared or combined with each other.
+    pub fn is_compatible(self, other: SqlType) -> bool {
+        self == other || self == SqlType::Null || other == SqlType::Null
+    }
+}
+
+impl fmt::Display for SqlType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SqlType::Integer => write!(f, "integer"),
+            SqlType::Text => write!(f, "text"),
+            SqlType::Null => write!(f, "null")
+        }
+    }
+}
+
+/// A value produced by evaluating an expression against a row.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Value {
+    Null,
+    Integer(i64),
+    Text(String)
+}
+
+impl Value {
+
+    pub fn sql_type(&self) -> SqlType {
+        match self {
+            Value::Null => SqlType::Null,
+            Value::Integer(_) => SqlType::Integer,
+            Value::Text(_) => SqlType::Text
+        }
+    }
+
+    /// Truth value used by `NOT`, `AND` and `OR`, `NULL` is neither true nor false.
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            Value::Null => None,
+            Value::Integer(i) => Some(*i != 0),
+            Value::Text(s) => Some(!s.is_empty())
+        }
+    }
+
+    pub fn from_bool(b: bool) -> Value {
+        Value::Integer(b as i64)
+    }
+
+    /// The value written as a SQL literal that reads back as the same value. Control characters,
+    /// which can not be typed in a statement, are written with `char()`.
+    pub fn sql_literal(&self) -> String {
+        let s = match self {
+            Value::Null => return String::from("null"),
+            Value::Integer(i) => return i.to_string(),
+            Value::Text(s) => s
+        };
+        let mut parts = Vec::new();
+        let mut quoted = String::new();
+        for c in s.chars() {
+            if c.is_control() {
+                if !quoted.is_empty() {
+                    parts.push(format!("'{}'", std::mem::take(&mut quoted)));
+                }
+                parts.push(format!("char({})", c as u32));
+            } else if c == '\'' {
+                quoted.push_str("''");
+            } else {
+                quoted.push(c);
+            }
+        }
+        if !quoted.is_empty() || parts.is_empty() {
+            parts.push(format!("'{}'", quoted));
+        }
+        parts.join(" || ")
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Null => Ok(()),
+            Value::Integer(i) => write!(f, "{}", i),
+            Value::Text(s) => write!(f, "{}", s)
+        }
+    }
+}
+
+impl From<i64> for Value {
+    fn from(i: i64) -> Self {
+        Value::Integer(i)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Value::Text(String::from(s))
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Value::Text(s)
+    }
+}
+
+impl<T: Into<Value>> From<Option<T>> for Value {
+    fn from(v: Option<T>) -> Self {
+        v.map_or(Value::Null, Into::into)
+    }
+}
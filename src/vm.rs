@@ -0,0 +1,521 @@
+use std::fmt;
+use crate::{check, expr};
+use crate::sql::{BinaryOp, UnaryOp};
+use crate::value::Value;
+use crate::{Cursor, ExecuteResult, Row, TableRef};
+
+/// Index of a register of the virtual machine.
+pub type Reg = usize;
+/// Index of an instruction in a program, the target of jumps.
+pub type Addr = usize;
+
+/// What `Insert` does when the key is already in the table.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Conflict {
+    /// Fail with `EXECUTE_DUPLICATE_KEY`.
+    Abort,
+    /// Leave the existing row alone and go on.
+    Ignore,
+    /// Overwrite the existing row in place.
+    Replace,
+    /// Jump to the code updating the existing row, the cursor pointing at it.
+    Update(Addr)
+}
+
+/// Instructions of the virtual machine, modeled after the opcodes of SQLite's VDBE.
+///
+/// Values live in registers, cursors iterate over the B-tree of a table, and every instruction
+/// moves on to the next one unless it jumps.
+#[derive(Clone, PartialEq, Debug)]
+pub enum Opcode {
+    /// Open a read only cursor over the table.
+    OpenRead { cursor: usize, table: String },
+    /// Open a cursor that can insert and delete rows.
+    OpenWrite { cursor: usize, table: String },
+    /// Open a cursor over an empty table held in memory until the program halts. `Rewind`,
+    /// `Next` and `Column` work on it as on the cursor over a table.
+    OpenEphemeral { cursor: usize, columns: usize },
+    /// Append the registers as a row of the ephemeral table of the cursor.
+    Append { cursor: usize, start: Reg, count: usize },
+    /// Point the cursor at the first row, jump to `if_empty` if there is none.
+    Rewind { cursor: usize, if_empty: Addr },
+    /// Advance the cursor to the next row and jump to `target`, fall through after the last row.
+    Next { cursor: usize, target: Addr },
+    /// Point the cursor at the row with the given key, jump to `not_found` if there is none.
+    SeekRowid { cursor: usize, key: Reg, not_found: Addr },
+    /// Point the cursor at the first row whose key is greater than or equal to the register.
+    SeekGE { cursor: usize, key: Reg, if_eof: Addr },
+    /// Point the cursor at the first row whose key is greater than the register.
+    SeekGT { cursor: usize, key: Reg, if_eof: Addr },
+    /// Read the key of the current row.
+    Rowid { cursor: usize, dest: Reg },
+    /// Read a column of the current row.
+    Column { cursor: usize, column: usize, dest: Reg },
+    Integer { value: i64, dest: Reg },
+    String { value: String, dest: Reg },
+    Null { dest: Reg },
+    Copy { source: Reg, dest: Reg },
+    /// Copy the value bound to the parameter with the given index, counted from 1.
+    Variable { index: usize, name: String, dest: Reg },
+    Unary { op: UnaryOp, operand: Reg, dest: Reg },
+    Binary { op: BinaryOp, left: Reg, right: Reg, dest: Reg },
+    Function { name: String, arg: Reg, dest: Reg },
+    /// Compare two registers and jump if the comparison holds. A comparison with `NULL` is
+    /// unknown, in which case the jump is taken only if `jump_if_null` is set.
+    Eq { left: Reg, right: Reg, target: Addr, jump_if_null: bool },
+    Ne { left: Reg, right: Reg, target: Addr, jump_if_null: bool },
+    Lt { left: Reg, right: Reg, target: Addr, jump_if_null: bool },
+    Le { left: Reg, right: Reg, target: Addr, jump_if_null: bool },
+    Gt { left: Reg, right: Reg, target: Addr, jump_if_null: bool },
+    Ge { left: Reg, right: Reg, target: Addr, jump_if_null: bool },
+    /// Jump if the register is false or `NULL`.
+    IfNot { reg: Reg, target: Addr },
+    Goto { target: Addr },
+    /// Output `count` registers starting at `start` as a row of the result.
+    ResultRow { start: Reg, count: usize },
+    /// Insert the row held in the registers starting at `start`, whose first register is the key.
+    /// The cursor is left at the position of the key, so that a conflicting row can be read or
+    /// overwritten without searching the tree again.
+    Insert { cursor: usize, start: Reg, on_conflict: Conflict },
+    /// Overwrite the row at the cursor with the registers starting at `start`, keeping its key.
+    Update { cursor: usize, start: Reg },
+    /// Delete the row at the cursor, the next `Next` moves to the row that followed it.
+    Delete { cursor: usize },
+    /// Check the B-tree of the table, storing `ok` or the problems found, one per line.
+    IntegrityCk { table: String, dest: Reg },
+    /// Change the setting named by a pragma when given a value, then store its value.
+    Pragma { name: String, value: Option<Value>, dest: Reg },
+    Halt
+}
+
+impl Opcode {
+
+    /// The instruction as listed by `explain`: its name, its operands laid out in the p1 to p4
+    /// columns of SQLite, and a comment describing what it does.
+    pub fn explain(&self) -> [String; 6] {
+        fn jump(target: &Addr, jump_if_null: &bool) -> String {
+            if *jump_if_null {
+                format!("goto {} (or null)", target)
+            } else {
+                format!("goto {}", target)
+            }
+        }
+        let (name, p1, p2, p3, p4, comment) = match self {
+            Opcode::OpenRead { cursor, table } => ("OpenRead", *cursor, 0, 0, table.clone(), format!("read {}", table)),
+            Opcode::OpenWrite { cursor, table } => ("OpenWrite", *cursor, 0, 0, table.clone(), format!("write {}", table)),
+            Opcode::OpenEphemeral { cursor, columns } => ("OpenEphemeral", *cursor, *columns, 0, String::new(), format!("{} columns", columns)),
+            Opcode::Append { cursor, start, count } => ("Append", *cursor, *start, *count, String::new(), format!("data=r[{}..{}]", start, start + count)),
+            Opcode::Rewind { cursor, if_empty } => ("Rewind", *cursor, *if_empty, 0, String::new(), format!("if empty goto {}", if_empty)),
+            Opcode::Next { cursor, target } => ("Next", *cursor, *target, 0, String::new(), format!("if more rows goto {}", target)),
+            Opcode::SeekRowid { cursor, key, not_found } => ("SeekRowid", *cursor, *not_found, *key, String::new(), format!("key=r[{}]; if not found goto {}", key, not_found)),
+            Opcode::SeekGE { cursor, key, if_eof } => ("SeekGE", *cursor, *if_eof, *key, String::new(), format!("key>=r[{}]; if eof goto {}", key, if_eof)),
+            Opcode::SeekGT { cursor, key, if_eof } => ("SeekGT", *cursor, *if_eof, *key, String::new(), format!("key>r[{}]; if eof goto {}", key, if_eof)),
+            Opcode::Rowid { cursor, dest } => ("Rowid", *cursor, *dest, 0, String::new(), format!("r[{}]=key", dest)),
+            Opcode::Column { cursor, column, dest } => ("Column", *cursor, *column, *dest, String::new(), format!("r[{}]=column {}", dest, column)),
+            Opcode::Integer { value, dest } => ("Integer", 0, *dest, 0, value.to_string(), format!("r[{}]={}", dest, value)),
+            Opcode::String { value, dest } => ("String", 0, *dest, 0, value.clone(), format!("r[{}]='{}'", dest, value)),
+            Opcode::Null { dest } => ("Null", 0, *dest, 0, String::new(), format!("r[{}]=NULL", dest)),
+            Opcode::Copy { source, dest } => ("Copy", *source, *dest, 0, String::new(), format!("r[{}]=r[{}]", dest, source)),
+            Opcode::Variable { index, name, dest } => ("Variable", *index, *dest, 0, name.clone(), format!("r[{}]=parameter({})", dest, index)),
+            Opcode::Unary { op, operand, dest } => {
+                let symbol = if *op == UnaryOp::Neg { "-" } else { "not " };
+                ("Unary", *operand, *dest, 0, format!("{:?}", op), format!("r[{}]={}r[{}]", dest, symbol, operand))
+            },
+            Opcode::Binary { op, left, right, dest } => ("Binary", *left, *right, *dest, format!("{:?}", op), format!("r[{}]=r[{}] {} r[{}]", dest, left, op.symbol(), right)),
+            Opcode::Function { name, arg, dest } => ("Function", *arg, *dest, 0, name.clone(), format!("r[{}]={}(r[{}])", dest, name, arg)),
+            Opcode::Eq { left, right, target, jump_if_null } => ("Eq", *left, *target, *right, String::new(), format!("if r[{}]==r[{}] {}", left, right, jump(target, jump_if_null))),
+            Opcode::Ne { left, right, target, jump_if_null } => ("Ne", *left, *target, *right, String::new(), format!("if r[{}]!=r[{}] {}", left, right, jump(target, jump_if_null))),
+            Opcode::Lt { left, right, target, jump_if_null } => ("Lt", *left, *target, *right, String::new(), format!("if r[{}]<r[{}] {}", left, right, jump(target, jump_if_null))),
+            Opcode::Le { left, right, target, jump_if_null } => ("Le", *left, *target, *right, String::new(), format!("if r[{}]<=r[{}] {}", left, right, jump(target, jump_if_null))),
+            Opcode::Gt { left, right, target, jump_if_null } => ("Gt", *left, *target, *right, String::new(), format!("if r[{}]>r[{}] {}", left, right, jump(target, jump_if_null))),
+            Opcode::Ge { left, right, target, jump_if_null } => ("Ge", *left, *target, *right, String::new(), format!("if r[{}]>=r[{}] {}", left, right, jump(target, jump_if_null))),
+            Opcode::IfNot { reg, target } => ("IfNot", *reg, *target, 0, String::new(), format!("if not r[{}] goto {}", reg, target)),
+            Opcode::ResultRow { start, count } => ("ResultRow", *start, *count, 0, String::new(), format!("output=r[{}..{}]", start, start + count)),
+            Opcode::Goto { target } => ("Goto", 0, *target, 0, String::new(), String::new()),
+            Opcode::Insert { cursor, start, on_conflict } => {
+                let comment = format!("key=r[{}] data=r[{}..{}]", start, start, start + 3);
+                let (p3, p4, comment) = match on_conflict {
+                    Conflict::Abort => (0, "", comment),
+                    Conflict::Ignore => (0, "ignore", comment),
+                    Conflict::Replace => (0, "replace", comment),
+                    Conflict::Update(target) => (*target, "update", format!("{}; if exists goto {}", comment, target))
+                };
+                ("Insert", *cursor, *start, p3, String::from(p4), comment)
+            },
+            Opcode::Update { cursor, start } => ("Update", *cursor, *start, 0, String::new(), format!("data=r[{}..{}]", start, start + 3)),
+            Opcode::Delete { cursor } => ("Delete", *cursor, 0, 0, String::new(), String::new()),
+            Opcode::IntegrityCk { table, dest } => ("IntegrityCk", 0, *dest, 0, table.clone(), format!("r[{}]=check {}", dest, table)),
+            Opcode::Pragma { name, value, dest } => {
+                let comment = match value {
+                    // a passphrase is not listed
+                    Some(_) if name == "key" || name == "rekey" => format!("{}=...", name),
+                    Some(value) => format!("{}={}; r[{}]={}", name, value, dest, name),
+                    None => format!("r[{}]={}", dest, name)
+                };
+                ("Pragma", 0, *dest, 0, name.clone(), comment)
+            },
+            Opcode::Halt => ("Halt", 0, 0, 0, String::new(), String::new())
+        };
+        [String::from(name), p1.to_string(), p2.to_string(), p3.to_string(), p4, comment]
+    }
+}
+
+/// A compiled statement.
+#[derive(Clone, PartialEq, Debug)]
+pub struct Program {
+    pub instructions: Vec<Opcode>,
+    pub num_registers: usize,
+    /// Names of the columns of the rows output by `ResultRow`.
+    pub column_names: Vec<String>,
+    /// How the statement accesses its table, one line per access, as listed by
+    /// `explain query plan`.
+    pub plan: Vec<String>
+}
+
+impl Program {
+
+    /// Whether the program gives the key of an encrypted database, the one statement that can
+    /// run before the key is given.
+    fn gives_key(&self) -> bool {
+        self.instructions.iter().any(|instruction| matches!(instruction, Opcode::Pragma { name, .. } if name == "key"))
+    }
+}
+
+/// Lists the instructions of the program, one per line, the way `explain` prints them.
+impl fmt::Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let header = ["addr", "opcode", "p1", "p2", "p3", "p4", "comment"].map(String::from);
+        let mut rows = vec![header];
+        for (addr, instruction) in self.instructions.iter().enumerate() {
+            let [name, p1, p2, p3, p4, comment] = instruction.explain();
+            rows.push([addr.to_string(), name, p1, p2, p3, p4, comment]);
+        }
+        let mut widths = [0; 7];
+        for row in &rows {
+            for (width, column) in widths.iter_mut().zip(row) {
+                *width = (*width).max(column.chars().count());
+            }
+        }
+        let separator = widths.map(|width| "-".repeat(width));
+        for (i, row) in rows.iter().enumerate() {
+            if i == 1 {
+                writeln!(f, "{}", separator.join("  ").trim_end())?;
+            }
+            let columns: Vec<String> = row.iter().zip(widths)
+                .map(|(column, width)| format!("{:<width$}", column, width = width))
+                .collect();
+            writeln!(f, "{}", columns.join("  ").trim_end())?;
+        }
+        Ok(())
+    }
+}
+
+/// Position of an open cursor. A `Cursor` borrows the table, so the machine keeps only the
+/// position and creates a `Cursor` whenever it needs to move or read.
+#[derive(Default)]
+struct CursorState {
+    page_num: usize,
+    cell_num: usize,
+    end_of_table: bool,
+    /// Set after a delete, the cursor already points to the row that followed the deleted one.
+    deleted: bool,
+    /// The current row, decoded on the first `Column` instruction.
+    row: Option<Row>,
+    /// The rows of an ephemeral table, `cell_num` being the position in it.
+    ephemeral: Option<Vec<Vec<Value>>>
+}
+
+pub struct Vm<'a> {
+    table: TableRef<'a>,
+    registers: Vec<Value>,
+    cursors: Vec<Option<CursorState>>
+}
+
+impl<'a> Vm<'a> {
+
+    pub fn new(table: TableRef<'a>) -> Self {
+        Vm {
+            table,
+            registers: Vec::new(),
+            cursors: Vec::new()
+        }
+    }
+
+    fn open_cursor(&mut self, cursor: usize, state: CursorState) {
+        if self.cursors.len() <= cursor {
+            self.cursors.resize_with(cursor + 1, || None);
+        }
+        self.cursors[cursor] = Some(state);
+    }
+
+    fn cursor(&mut self, cursor: usize) -> &mut CursorState {
+        self.cursors[cursor].as_mut().expect("cursor is not open")
+    }
+
+    /// Run a `Cursor` operation at the position of the given cursor and save the new position.
+    fn with_cursor<T>(&mut self, cursor: usize, f: impl FnOnce(&mut Cursor) -> T) -> T {
+        let state = self.cursors[cursor].as_mut().expect("cursor is not open");
+        let mut table_cursor = Cursor {
+            table: self.table.reborrow(),
+            page_num: state.page_num,
+            cell_num: state.cell_num,
+            end_of_table: state.end_of_table
+        };
+        let result = f(&mut table_cursor);
+        state.page_num = table_cursor.page_num;
+        state.cell_num = table_cursor.cell_num;
+        state.end_of_table = table_cursor.end_of_table;
+        state.row = None;
+        result
+    }
+
+    /// Point the cursor at the first row with a key greater than or equal to `key`, returns false
+    /// if there is no such row.
+    fn seek(&mut self, cursor: usize, key: i64) -> bool {
+        if key > u32::MAX as i64 {
+            return false;
+        }
+        let key = key.max(0) as u32;
+        let (page_num, cell_num) = self.table.find(key);
+        *self.cursor(cursor) = CursorState {
+            page_num,
+            cell_num,
+            ..CursorState::default()
+        };
+        self.with_cursor(cursor, |c| {
+            c.skip_to_valid_cell();
+            !c.end_of_table
+        })
+    }
+
+    fn current_row(&mut self, cursor: usize) -> &Row {
+        if self.cursor(cursor).row.is_none() {
+            let row = self.with_cursor(cursor, |c| *c.cursor_value());
+            self.cursor(cursor).row = Some(row);
+        }
+        self.cursor(cursor).row.as_ref().unwrap()
+    }
+
+    fn integer(&self, reg: Reg) -> Option<i64> {
+        match self.registers[reg] {
+            Value::Integer(i) => Some(i),
+            _ => None
+        }
+    }
+
+    /// Execute the program with the values bound to its parameters, calling `on_row` with every
+    /// row output by `ResultRow`.
+    pub fn run(&mut self, program: &Program, parameters: &[Value], mut on_row: impl FnMut(&[Value])) -> ExecuteResult {
+        if self.table.pager.needs_key() && !program.gives_key() {
+            return ExecuteResult::EXECUTE_NO_KEY;
+        }
+        self.registers = vec![Value::Null; program.num_registers];
+        self.cursors.clear();
+        let mut pc = 0;
+        while pc < program.instructions.len() {
+            let mut next_pc = pc + 1;
+            match &program.instructions[pc] {
+                Opcode::OpenRead { cursor, .. } | Opcode::OpenWrite { cursor, .. } => {
+                    self.open_cursor(*cursor, CursorState::default());
+                },
+                Opcode::OpenEphemeral { cursor, .. } => {
+                    self.open_cursor(*cursor, CursorState {
+                        ephemeral: Some(Vec::new()),
+                        ..CursorState::default()
+                    });
+                },
+                Opcode::Append { cursor, start, count } => {
+                    let row = self.registers[*start..*start + *count].to_vec();
+                    self.cursor(*cursor).ephemeral.as_mut().expect("cursor is not ephemeral").push(row);
+                },
+                Opcode::Rewind { cursor, if_empty } if self.cursor(*cursor).ephemeral.is_some() => {
+                    let state = self.cursor(*cursor);
+                    state.cell_num = 0;
+                    if state.ephemeral.as_ref().unwrap().is_empty() {
+                        next_pc = *if_empty;
+                    }
+                },
+                Opcode::Rewind { cursor, if_empty } => {
+                    let start = Cursor::table_start(self.table.reborrow());
+                    let (page_num, cell_num, end_of_table) = (start.page_num, start.cell_num, start.end_of_table);
+                    *self.cursor(*cursor) = CursorState {
+                        page_num,
+                        cell_num,
+                        end_of_table,
+                        ..CursorState::default()
+                    };
+                    if end_of_table {
+                        next_pc = *if_empty;
+                    }
+                },
+                Opcode::Next { cursor, target } if self.cursor(*cursor).ephemeral.is_some() => {
+                    let state = self.cursor(*cursor);
+                    state.cell_num += 1;
+                    if state.cell_num < state.ephemeral.as_ref().unwrap().len() {
+                        next_pc = *target;
+                    }
+                },
+                Opcode::Next { cursor, target } => {
+                    if std::mem::take(&mut self.cursor(*cursor).deleted) {
+                        self.with_cursor(*cursor, |c| c.skip_to_valid_cell());
+                    } else {
+                        self.with_cursor(*cursor, |c| c.advance());
+                    }
+                    if !self.cursor(*cursor).end_of_table {
+                        next_pc = *target;
+                    }
+                },
+                Opcode::SeekRowid { cursor, key, not_found } => {
+                    let found = match self.integer(*key) {
+                        Some(key) if self.seek(*cursor, key) => {
+                            self.current_row(*cursor).id as i64 == key
+                        },
+                        _ => false
+                    };
+                    if !found {
+                        next_pc = *not_found;
+                    }
+                },
+                Opcode::SeekGE { cursor, key, if_eof } | Opcode::SeekGT { cursor, key, if_eof } => {
+                    let found = match self.integer(*key) {
+                        Some(key) if matches!(program.instructions[pc], Opcode::SeekGT { .. }) => {
+                            self.seek(*cursor, key.saturating_add(1))
+                        },
+                        Some(key) => self.seek(*cursor, key),
+                        None => false
+                    };
+                    if !found {
+                        next_pc = *if_eof;
+                    }
+                },
+                Opcode::Rowid { cursor, dest } => {
+                    let id = self.current_row(*cursor).id;
+                    self.registers[*dest] = Value::Integer(id as i64);
+                },
+                Opcode::Column { cursor, column, dest } if self.cursor(*cursor).ephemeral.is_some() => {
+                    let state = self.cursor(*cursor);
+                    let value = state.ephemeral.as_ref().unwrap()[state.cell_num][*column].clone();
+                    self.registers[*dest] = value;
+                },
+                Opcode::Column { cursor, column, dest } => {
+                    let value = self.current_row(*cursor).values()[*column].clone();
+                    self.registers[*dest] = value;
+                },
+                Opcode::Integer { value, dest } => self.registers[*dest] = Value::Integer(*value),
+                Opcode::String { value, dest } => self.registers[*dest] = Value::Text(value.clone()),
+                Opcode::Null { dest } => self.registers[*dest] = Value::Null,
+                Opcode::Copy { source, dest } => self.registers[*dest] = self.registers[*source].clone(),
+                Opcode::Variable { index, dest, .. } => {
+                    self.registers[*dest] = parameters.get(index - 1).cloned().unwrap_or(Value::Null);
+                },
+                Opcode::Unary { op, operand, dest } => {
+                    match expr::apply_unary(*op, self.registers[*operand].clone()) {
+                        Ok(value) => self.registers[*dest] = value,
+                        Err(result) => return result
+                    }
+                },
+                Opcode::Binary { op, left, right, dest } => {
+                    let (left, right) = (self.registers[*left].clone(), self.registers[*right].clone());
+                    match expr::apply_binary(*op, left, right) {
+                        Ok(value) => self.registers[*dest] = value,
+                        Err(result) => return result
+                    }
+                },
+                Opcode::Function { name, arg, dest } => {
+                    match expr::call_function(name, &self.registers[*arg]) {
+                        Ok(value) => self.registers[*dest] = value,
+                        Err(result) => return result
+                    }
+                },
+                Opcode::Eq { left, right, target, jump_if_null }
+                | Opcode::Ne { left, right, target, jump_if_null }
+                | Opcode::Lt { left, right, target, jump_if_null }
+                | Opcode::Le { left, right, target, jump_if_null }
+                | Opcode::Gt { left, right, target, jump_if_null }
+                | Opcode::Ge { left, right, target, jump_if_null } => {
+                    let holds = expr::compare(&self.registers[*left], &self.registers[*right])
+                        .map(|ordering| match program.instructions[pc] {
+                            Opcode::Eq { .. } => ordering.is_eq(),
+                            Opcode::Ne { .. } => ordering.is_ne(),
+                            Opcode::Lt { .. } => ordering.is_lt(),
+                            Opcode::Le { .. } => ordering.is_le(),
+                            Opcode::Gt { .. } => ordering.is_gt(),
+                            _ => ordering.is_ge()
+                        });
+                    if holds.unwrap_or(*jump_if_null) {
+                        next_pc = *target;
+                    }
+                },
+                Opcode::IfNot { reg, target } => {
+                    if self.registers[*reg].as_bool() != Some(true) {
+                        next_pc = *target;
+                    }
+                },
+                Opcode::ResultRow { start, count } => on_row(&self.registers[*start..*start + *count]),
+                Opcode::Goto { target } => next_pc = *target,
+                Opcode::Insert { cursor, start, on_conflict } => {
+                    let row = match Row::from_values(&self.registers[*start..*start + 3]) {
+                        Ok(row) => row,
+                        Err(result) => return result
+                    };
+                    let (page_num, cell_num) = self.table.find(row.id);
+                    *self.cursor(*cursor) = CursorState {
+                        page_num,
+                        cell_num,
+                        ..CursorState::default()
+                    };
+                    let duplicate = self.with_cursor(*cursor, |c| {
+                        let page = c.get_page_view().unwrap();
+                        cell_num < page.leaf_node_num_cells() && page.leaf_node_key(cell_num) == row.id
+                    });
+                    if !duplicate {
+                        if self.table.is_full() {
+                            return ExecuteResult::EXECUTE_TABLE_FULL;
+                        }
+                        self.with_cursor(*cursor, |c| unsafe { c.leaf_node_insert(row.id, &row) });
+                    } else {
+                        match on_conflict {
+                            Conflict::Abort => return ExecuteResult::EXECUTE_DUPLICATE_KEY,
+                            Conflict::Ignore => {},
+                            Conflict::Replace => self.with_cursor(*cursor, |c| c.leaf_node_replace(&row)),
+                            Conflict::Update(target) => next_pc = *target
+                        }
+                    }
+                },
+                Opcode::Update { cursor, start } => {
+                    let row = match Row::from_values(&self.registers[*start..*start + 3]) {
+                        Ok(row) => row,
+                        Err(result) => return result
+                    };
+                    self.with_cursor(*cursor, |c| c.leaf_node_replace(&row));
+                },
+                Opcode::Delete { cursor } => {
+                    self.with_cursor(*cursor, |c| c.leaf_node_delete());
+                    self.cursor(*cursor).deleted = true;
+                },
+                Opcode::IntegrityCk { dest, .. } => {
+                    let errors = check::integrity_check(&self.table);
+                    // corrupt pages are reported by the check rather than failing it
+                    self.table.pager.drop_corrupt_pages();
+                    self.registers[*dest] = Value::Text(if errors.is_empty() {
+                        String::from("ok")
+                    } else {
+                        errors.join("\n")
+                    });
+                },
+                Opcode::Pragma { name, value, dest } => {
+                    match self.table.pragma(name, value.as_ref()) {
+                        Ok(value) => self.registers[*dest] = value,
+                        Err(result) => return result
+                    }
+                },
+                Opcode::Halt => break
+            }
+            if let Some(page_num) = self.table.pager.first_corrupt_page() {
+                return ExecuteResult::EXECUTE_CORRUPT_PAGE(page_num);
+            }
+            pc = next_pc;
+        }
+        ExecuteResult::EXECUTE_SUCCESS
+    }
+}
@@ -0,0 +1,118 @@
+mod common;
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::PathBuf;
+use common::temp_db;
+use db_tutorial_rust::{Connection, ExecuteResult};
+
+/// A database of 400 rows, many more pages than a backup step copies.
+fn filled(name: &str) -> (PathBuf, Connection) {
+    let path = temp_db(name);
+    let connection = Connection::open(path.to_str().unwrap()).unwrap();
+    for id in 0..400 {
+        let sql = format!("insert into users values ({}, 'user{}', 'user{}@example.com')", id, id, id);
+        let statement = connection.prepare(&sql).unwrap();
+        assert_eq!(connection.execute(&statement, |_| {}), ExecuteResult::EXECUTE_SUCCESS);
+    }
+    (path, connection)
+}
+
+fn count(connection: &Connection) -> usize {
+    let statement = connection.prepare("select id from users").unwrap();
+    let mut count = 0;
+    connection.execute(&statement, |_| count += 1);
+    count
+}
+
+#[test]
+fn backup_then_restore() {
+    let (path, connection) = filled("backup_source");
+    let copy = temp_db("backup_copy");
+    let pages = connection.backup_to(copy.to_str().unwrap()).unwrap();
+    assert!(pages > 1);
+
+    let statement = connection.prepare("delete from users where id >= 100").unwrap();
+    assert_eq!(connection.execute(&statement, |_| {}), ExecuteResult::EXECUTE_SUCCESS);
+    assert_eq!(count(&connection), 100);
+    assert_eq!(connection.restore_from(copy.to_str().unwrap(), None).unwrap(), pages);
+    assert_eq!(count(&connection), 400);
+    assert_eq!(connection.integrity_check(), Vec::<String>::new());
+
+    connection.close().unwrap();
+    drop(connection);
+    fs::remove_file(path).unwrap();
+    fs::remove_file(copy).unwrap();
+}
+
+#[test]
+fn incomplete_backup_is_not_left_behind() {
+    let (path, connection) = filled("partial_source");
+    let copy = temp_db("partial_copy");
+    let temp = PathBuf::from(format!("{}.tmp", copy.display()));
+    let mut backup = connection.backup(copy.to_str().unwrap()).unwrap();
+    assert!(!backup.step(5).unwrap());
+    assert!(backup.remaining() > 0);
+    assert!(!copy.exists());
+
+    let e = connection.close().unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::ResourceBusy);
+    drop(backup);
+    assert!(!copy.exists());
+    assert!(!temp.exists());
+
+    connection.close().unwrap();
+    drop(connection);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn truncated_backup_is_rejected() {
+    let (path, connection) = filled("truncated_source");
+    let copy = temp_db("truncated_copy");
+    connection.backup_to(copy.to_str().unwrap()).unwrap();
+    // the header page and four more
+    let bytes = fs::read(&copy).unwrap();
+    fs::write(&copy, &bytes[..5 * connection.page_size()]).unwrap();
+
+    let e = connection.restore_from(copy.to_str().unwrap(), None).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::InvalidData);
+    assert_eq!(count(&connection), 400);
+    assert_eq!(connection.integrity_check(), Vec::<String>::new());
+
+    connection.close().unwrap();
+    drop(connection);
+    fs::remove_file(path).unwrap();
+    fs::remove_file(copy).unwrap();
+}
+
+#[test]
+fn restoring_a_copy_with_another_salt_needs_its_passphrase() {
+    let run = |connection: &Connection, sql: &str| {
+        let statement = connection.prepare(sql).unwrap();
+        assert_eq!(connection.execute(&statement, |_| {}), ExecuteResult::EXECUTE_SUCCESS);
+    };
+    let source_path = temp_db("salt_source");
+    let source = Connection::open(source_path.to_str().unwrap()).unwrap();
+    run(&source, "pragma key = 'secret'");
+    run(&source, "insert into users values (1, 'user1', 'user1@example.com')");
+    let copy = temp_db("salt_copy");
+    let pages = source.backup_to(copy.to_str().unwrap()).unwrap();
+    source.close().unwrap();
+    drop(source);
+
+    // the same passphrase, with a salt of its own
+    let path = temp_db("salt_target");
+    let connection = Connection::open(path.to_str().unwrap()).unwrap();
+    run(&connection, "pragma key = 'secret'");
+    let e = connection.restore_from(copy.to_str().unwrap(), None).unwrap_err();
+    assert_eq!(e.kind(), ErrorKind::PermissionDenied);
+    assert_eq!(connection.restore_from(copy.to_str().unwrap(), Some("secret")).unwrap(), pages);
+    assert_eq!(count(&connection), 1);
+
+    connection.close().unwrap();
+    drop(connection);
+    for path in [source_path, copy, path] {
+        fs::remove_file(path).unwrap();
+    }
+}
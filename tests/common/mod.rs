@@ -0,0 +1,11 @@
+use std::env;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+/// A path in the temporary directory, removed first if a previous run left it behind.
+pub fn temp_db(name: &str) -> PathBuf {
+    let path = env::temp_dir().join(format!("db_tutorial_{}_{}.db", name, process::id()));
+    let _ = fs::remove_file(&path);
+    path
+}
@@ -0,0 +1,46 @@
+mod common;
+
+use std::fs;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
+use common::temp_db;
+use db_tutorial_rust::{Connection, ExecuteResult, Value};
+
+#[test]
+fn readers_see_committed_rows_while_a_writer_inserts() {
+    let path = temp_db("concurrency");
+    let connection = Arc::new(Connection::open(path.to_str().unwrap()).unwrap());
+    let done = Arc::new(AtomicBool::new(false));
+
+    let readers: Vec<_> = (0..4).map(|_| {
+        let (connection, done) = (Arc::clone(&connection), Arc::clone(&done));
+        thread::spawn(move || {
+            let statement = connection.prepare("select id from users").unwrap();
+            let mut last = 0;
+            while !done.load(Ordering::Relaxed) {
+                let mut ids = Vec::new();
+                let result = connection.execute(&statement, |row| ids.push(row[0].clone()));
+                assert_eq!(result, ExecuteResult::EXECUTE_SUCCESS);
+                // every select sees a prefix of the inserts, never fewer rows than the last one
+                assert_eq!(ids, (0..ids.len() as i64).map(Value::Integer).collect::<Vec<_>>());
+                assert!(ids.len() >= last);
+                last = ids.len();
+            }
+        })
+    }).collect();
+
+    let mut statement = connection.prepare("insert into users values (?, 'user', 'user@example.com')").unwrap();
+    for id in 0..500i64 {
+        statement.bind(1, id);
+        assert_eq!(connection.execute(&statement, |_| {}), ExecuteResult::EXECUTE_SUCCESS);
+        statement.reset();
+    }
+    done.store(true, Ordering::Relaxed);
+    readers.into_iter().for_each(|reader| reader.join().unwrap());
+
+    assert_eq!(connection.integrity_check(), Vec::<String>::new());
+    connection.close().unwrap();
+    drop(connection);
+    fs::remove_file(path).unwrap();
+}
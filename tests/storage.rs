@@ -0,0 +1,129 @@
+mod common;
+
+use std::fs;
+use std::path::PathBuf;
+use common::temp_db;
+use db_tutorial_rust::{Connection, ExecuteResult, Value};
+
+fn run(connection: &Connection, sql: &str) -> ExecuteResult {
+    let statement = connection.prepare(sql).unwrap();
+    connection.execute(&statement, |_| {})
+}
+
+fn ids(connection: &Connection) -> Vec<Value> {
+    let statement = connection.prepare("select id from users").unwrap();
+    let mut ids = Vec::new();
+    assert_eq!(connection.execute(&statement, |row| ids.push(row[0].clone())), ExecuteResult::EXECUTE_SUCCESS);
+    ids
+}
+
+fn insert_rows(connection: &Connection, range: std::ops::Range<i64>) {
+    for id in range {
+        let sql = format!("insert into users values ({}, 'user{}', 'user{}@example.com')", id, id, id);
+        assert_eq!(run(connection, &sql), ExecuteResult::EXECUTE_SUCCESS);
+    }
+}
+
+/// Write rows in the format the pragmas set, close, reopen twice adding rows in between, and
+/// check that every row is read back from a sound tree.
+fn round_trip(name: &str, pragmas: &[&str]) -> PathBuf {
+    let path = temp_db(name);
+    let path_str = path.to_str().unwrap();
+    let connection = Connection::open(path_str).unwrap();
+    for pragma in pragmas {
+        assert_eq!(run(&connection, pragma), ExecuteResult::EXECUTE_SUCCESS);
+    }
+    insert_rows(&connection, 0..300);
+    connection.close().unwrap();
+    drop(connection);
+
+    for (start, end) in [(300, 400), (400, 420)] {
+        let connection = Connection::open(path_str).unwrap();
+        for pragma in pragmas.iter().filter(|p| p.starts_with("pragma key")) {
+            assert_eq!(run(&connection, pragma), ExecuteResult::EXECUTE_SUCCESS);
+        }
+        assert_eq!(ids(&connection).len(), start as usize);
+        insert_rows(&connection, start..end);
+        assert_eq!(run(&connection, "delete from users where id < 10"), ExecuteResult::EXECUTE_SUCCESS);
+        insert_rows(&connection, 0..10);
+        connection.close().unwrap();
+    }
+
+    let connection = Connection::open(path_str).unwrap();
+    for pragma in pragmas.iter().filter(|p| p.starts_with("pragma key")) {
+        assert_eq!(run(&connection, pragma), ExecuteResult::EXECUTE_SUCCESS);
+    }
+    assert_eq!(connection.integrity_check(), Vec::<String>::new());
+    assert_eq!(ids(&connection), (0..420).map(Value::Integer).collect::<Vec<_>>());
+    path
+}
+
+#[test]
+fn plain_round_trip() {
+    let path = round_trip("plain", &[]);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn compressed_round_trip() {
+    let path = round_trip("compressed", &["pragma compression = on"]);
+    let connection = Connection::open(path.to_str().unwrap()).unwrap();
+    assert!(connection.tree_stats().unwrap().compressed_bytes.is_some());
+    drop(connection);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn encrypted_round_trip_rejects_wrong_key() {
+    let path = round_trip("encrypted", &["pragma compression = on", "pragma key = 'correct horse'"]);
+    let connection = Connection::open(path.to_str().unwrap()).unwrap();
+    assert_eq!(run(&connection, "select * from users"), ExecuteResult::EXECUTE_NO_KEY);
+    assert_eq!(run(&connection, "pragma key = 'battery staple'"), ExecuteResult::EXECUTE_WRONG_KEY);
+    assert_eq!(run(&connection, "select * from users"), ExecuteResult::EXECUTE_NO_KEY);
+    drop(connection);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn key_does_not_encrypt_a_database_with_rows() {
+    let path = temp_db("key_plaintext");
+    let connection = Connection::open(path.to_str().unwrap()).unwrap();
+    insert_rows(&connection, 0..3);
+    connection.close().unwrap();
+    drop(connection);
+
+    let connection = Connection::open(path.to_str().unwrap()).unwrap();
+    assert_eq!(run(&connection, "pragma key = 'typo'"), ExecuteResult::EXECUTE_NOT_ENCRYPTED);
+    assert_eq!(ids(&connection).len(), 3);
+    drop(connection);
+    fs::remove_file(path).unwrap();
+}
+
+#[test]
+fn legacy_file_gets_checksums_on_every_page() {
+    let path = temp_db("legacy");
+    let connection = Connection::open(path.to_str().unwrap()).unwrap();
+    insert_rows(&connection, 0..300);
+    connection.close().unwrap();
+    drop(connection);
+
+    // a file from before the header and the checksums: the pages alone, checksums zeroed
+    let page_size = db_tutorial_rust::DEFAULT_PAGE_SIZE;
+    let mut bytes = fs::read(&path).unwrap().split_off(page_size);
+    for page in bytes.chunks_mut(page_size) {
+        page[page_size - 4..].fill(0);
+    }
+    fs::write(&path, bytes).unwrap();
+
+    let connection = Connection::open(path.to_str().unwrap()).unwrap();
+    assert_eq!(run(&connection, "pragma verify_checksums = off"), ExecuteResult::EXECUTE_SUCCESS);
+    insert_rows(&connection, 300..301);
+    connection.close().unwrap();
+    drop(connection);
+
+    let connection = Connection::open(path.to_str().unwrap()).unwrap();
+    assert_eq!(connection.integrity_check(), Vec::<String>::new());
+    assert_eq!(ids(&connection), (0..301).map(Value::Integer).collect::<Vec<_>>());
+    drop(connection);
+    fs::remove_file(path).unwrap();
+}